@@ -0,0 +1,10 @@
+// used by the wgsl_variants! doctest
+@fragment
+fn main() -> @location(0) vec4<f32> {
+#if FOO == true
+    let owo = f32(#OWO);
+#else
+    let owo = 0.;
+#endif
+    return vec4<f32>(owo, f32(#BAR), 0., 1.);
+}
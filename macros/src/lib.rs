@@ -13,6 +13,8 @@ mod codegen;
 // todo: a lot of work went into this so it might as well be made public in a crate
 /// Preprocessor macro that generates multiple variants from the same shader file using [naga_oil](https://github.com/bevyengine/naga_oil)
 ///```
+///# use fractal_studio_macros::wgsl_variants;
+///# use wgpu::ShaderModuleDescriptor;
 ///wgsl_variants!{
 ///    // used to enumerate the possible values of a shader def value
 ///    value_enum OWO: i32 {
@@ -26,25 +28,31 @@ mod codegen;
 ///        B = 420,
 ///    }
 ///
-///    pub variants Shader from "src/wgsl/file.wgsl" {
+///    pub variants Shader from "macros/src/example.wgsl" {
 ///        // applies to all variants, used mainly for default values
 ///        shared {
 ///            FOO: bool = true,
+///            BAR: u32 = 0,
 ///        },
 ///        // variant with hardcoded values
 ///        Variant1 {
+///            OWO: i32 = 1,
 ///            BAR: u32 = 69,
 ///        },
 ///        Variant2 {
 ///            FOO: bool = false,
-///            BAZ: i32 = true,
+///            BAR: u32 = 7,
 ///        },
 ///        // generates 2 variants, one where OWO is 2 and one where OWO is -5
-///        Variant3 (OWO)
+///        Variant3 (OWO),
 ///        // cross product of OWO and UWU, so it generates 4 variants
-///        Variant4 (OWO, UWU),
+///        Variant4 (OWO, Uwu),
 ///    }
 ///}
+///
+///assert!(Shader::Variant3(OWO::B).get_raw_shader().contains("#OWO"));
+///let wgpu::ShaderSource::Wgsl(source) = Shader::Variant3(OWO::B).get_shader().source else { unreachable!() };
+///assert!(source.contains("-5"));
 ///```
 #[proc_macro]
 pub fn wgsl_variants(input: TokenStream) -> TokenStream {
@@ -128,7 +128,7 @@ fn parse_variant(input: ParseStream) -> syn::Result<Variant> {
             value_enums,
         })
     } else {
-        return Err(lookahead.error())?;
+        Err(lookahead.error())
     }
 }
 
@@ -1,8 +1,14 @@
 pub mod widgets;
 mod settings;
-mod visualizer;
-mod library;
-mod rendering;
+pub mod visualizer;
+pub mod library;
+pub mod rendering;
+pub mod cpu_rendering;
+pub mod export;
+pub mod perturbation;
+pub mod gradient;
+pub mod palette_files;
+mod files;
 
 use std::ops::Deref;
 use std::sync::Arc;
@@ -27,7 +33,8 @@ impl EguiApp {
         let mut settings: Settings = cc.storage.and_then(|storage| eframe::get_value(storage, eframe::APP_KEY)).unwrap_or_default();
 
         let wgpu = cc.wgpu_render_state.as_ref().unwrap();
-        let rd = RenderData::new(&wgpu.device, wgpu.target_format);
+        let rd = RenderData::new(&wgpu.device, &wgpu.adapter, wgpu.target_format);
+        let supports_storage_buffers = rd.supports_storage_buffers();
         wgpu.renderer.write().callback_resources.insert(rd);
 
         // used to create sharable links, on non wasm platforms it's hardcoded
@@ -53,7 +60,7 @@ impl EguiApp {
 
         EguiApp {
             settings,
-            visualizer: Visualizer::default(),
+            visualizer: Visualizer::new(supports_storage_buffers),
             toasts: Toasts::default().with_anchor(Anchor::TopLeft),
         }
 
@@ -61,7 +68,7 @@ impl EguiApp {
 }
 
 impl App for EguiApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // don't render most of the UI if we're taking a screenshot
         let screenshot_triggered = self.visualizer.screenshot_triggered;
 
@@ -70,6 +77,8 @@ impl App for EguiApp {
 
             self.settings.show(ctx, &mut self.toasts);
 
+            self.settings.export.show(ctx, frame.wgpu_render_state(), &self.settings.fractal, &self.visualizer, &mut self.toasts);
+
             self.toasts.show(ctx);
         }
 
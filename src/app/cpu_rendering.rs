@@ -0,0 +1,37 @@
+use glam::{DVec2, Vec2 as GVec2, Vec4 as GVec4};
+use rayon::prelude::*;
+use crate::app::rendering::Supersampling;
+use crate::fractal::{Fractal, FractalTrait};
+
+/// Renders the fractal on the cpu using [FractalTrait::evaluate], rows are rendered in parallel.
+/// Slow but it works without a gpu and it's useful for checking the shaders.
+/// Returns rgba8 pixels, scale and offset are the same as the ones passed to [crate::app::rendering::main_uniform_data]
+/// and `cycle_offset` is the position of the color cycling, check [crate::app::gradient::ColorCycling]
+pub fn render_cpu(fractal: &Fractal, scale: DVec2, offset: DVec2, size: [u32; 2], samples: Supersampling, cycle_offset: f32) -> Vec<u8> {
+    let [width, height] = size;
+    // the vertex shaders work in single precision
+    let (scale, offset) = (scale.as_vec2(), offset.as_vec2());
+    // the size of a pixel in clip space
+    let pixel_size = GVec2::new(2. / width as f32, 2. / height as f32);
+    let jitter: Vec<GVec2> = samples.jitter().map(|j| GVec2::new(j.x, j.y) * pixel_size).collect();
+    // height of a pixel in the fractal's coordinates, same as main_uniform_data
+    let fractal_pixel_size = pixel_size.y * scale.y;
+
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    pixels.par_chunks_exact_mut(width as usize * 4).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.as_chunks_mut::<4>().0.iter_mut().enumerate() {
+            // clip space position of the center of the pixel
+            let pos = GVec2::new((x as f32 + 0.5) * pixel_size.x - 1., 1. - (y as f32 + 0.5) * pixel_size.y);
+            let color = jitter.iter()
+                .map(|&jitter| fractal.evaluate((pos + offset + jitter) * scale, fractal_pixel_size, cycle_offset))
+                .sum::<GVec4>() / jitter.len() as f32;
+            *pixel = to_rgba8(color);
+        }
+    });
+    pixels
+}
+
+/// same conversion as writing to a Rgba8Unorm texture
+fn to_rgba8(color: GVec4) -> [u8; 4] {
+    color.to_array().map(|c| (c.clamp(0., 1.) * 255.).round() as u8)
+}
@@ -0,0 +1,374 @@
+use std::sync::{mpsc::{Receiver, TryRecvError}, Arc, Mutex};
+use anyhow::{anyhow, Result};
+use eframe::egui::{self, Align, ComboBox, DragValue, Grid, Layout, ProgressBar, Widget, Window};
+use eframe::egui_wgpu::RenderState;
+use egui_notify::Toasts;
+use glam::DVec2;
+use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder};
+use wgpu::{BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d, MapMode, Origin3d, Queue, TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, COPY_BYTES_PER_ROW_ALIGNMENT};
+use crate::app::files::FileFilter;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::files::pick_save_path;
+#[cfg(target_arch = "wasm32")]
+use crate::app::files::save_file;
+use crate::app::perturbation::PreparedView;
+use crate::app::rendering::{FractalData, RenderData, Supersampling, ViewHistogram};
+use crate::app::visualizer::Visualizer;
+use crate::app::widgets::error_toast;
+use crate::fractal::Fractal;
+
+const EXPORT_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+/// every tile is drawn in a single submission, long ones can trip the gpu's watchdog.
+/// Tiles are this big without supersampling and smaller with it so a submission never draws more than MAX_TILE_SIZE² samples
+pub const MAX_TILE_SIZE: u32 = 1024;
+/// the whole image is kept in memory as rgba8, 16384² is already 1 GiB
+const MAX_IMAGE_SIZE: u32 = 16384;
+const PNG_FILTER: FileFilter = FileFilter { name: "PNG image", extensions: &["png"], mime: "image/png" };
+
+/// Renders the current fractal offscreen at an arbitrary resolution and saves it as a png
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Export {
+    #[serde(skip)]
+    pub open: bool,
+    width: u32,
+    height: u32,
+    supersampling: Supersampling,
+    #[serde(skip)]
+    job: Option<ExportJob>,
+    /// picked before rendering so the dialog doesn't pop up after a long export
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    path: Option<std::path::PathBuf>,
+    /// result of encoding and writing the png on another thread
+    #[serde(skip)]
+    saving: Option<Receiver<Result<()>>>,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Self {
+            open: false,
+            width: 3840,
+            height: 2160,
+            supersampling: Supersampling::X16,
+            job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            path: None,
+            saving: None,
+        }
+    }
+}
+
+/// Renders an image tile by tile so it can be bigger than the max texture size, also used by the cli
+#[derive(Debug)]
+pub struct ExportJob {
+    width: u32,
+    height: u32,
+    /// view of the whole image
+    view: PreparedView,
+    fractal: Fractal,
+    samples: Supersampling,
+    /// the position of the color cycling when the export started, check [crate::app::gradient::ColorCycling]
+    cycle_offset: f32,
+    tiles: Vec<Tile>,
+    next_tile: usize,
+    pending: Option<PendingTile>,
+    /// computed before the first tile if there's more than one, otherwise every tile would be colored with its own
+    histogram: Option<ViewHistogram>,
+    /// the histogram is computed on the whole image scaled down to this size
+    histogram_size: [u32; 2],
+    pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tile { x: u32, y: u32, width: u32, height: u32 }
+
+#[derive(Debug)]
+struct PendingTile {
+    tile: Tile,
+    buffer: wgpu::Buffer,
+    padded_bytes_per_row: u32,
+    mapped: Arc<Mutex<Option<Result<(), BufferAsyncError>>>>,
+}
+
+impl Export {
+    pub fn show(&mut self, ctx: &egui::Context, render_state: Option<&RenderState>, fractal: &Fractal, visualizer: &Visualizer, toasts: &mut Toasts) {
+        if let (Some(job), Some(render_state)) = (&mut self.job, render_state) {
+            let result = {
+                let mut renderer = render_state.renderer.write();
+                match renderer.callback_resources.get_mut::<RenderData>() {
+                    Some(render_data) => job.step(&render_state.device, &render_state.queue, render_data),
+                    None => Err(anyhow!("Render data is missing")),
+                }
+            };
+            match result {
+                Ok(false) => ctx.request_repaint(),
+                Ok(true) => {
+                    let job = self.job.take().unwrap();
+                    self.save(ctx, job);
+                }
+                Err(e) => {
+                    self.job = None;
+                    toasts.add(error_toast(e));
+                }
+            }
+        }
+
+        match self.saving.as_ref().map(|saving| saving.try_recv()) {
+            Some(Ok(result)) => {
+                self.saving = None;
+                match result {
+                    Ok(()) => {toasts.success("Exported image");},
+                    Err(e) => {toasts.add(error_toast(e));},
+                }
+            }
+            Some(Err(TryRecvError::Disconnected)) => {
+                self.saving = None;
+                toasts.add(error_toast(anyhow!("Failed to save the image")));
+            }
+            Some(Err(TryRecvError::Empty)) | None => {}
+        }
+
+        let mut open = self.open;
+        Window::new("Export image")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let running = self.job.is_some();
+                ui.add_enabled_ui(!running, |ui| {
+                    Grid::new("export_size").num_columns(2).show(ui, |ui| {
+                        ui.label("Width");
+                        DragValue::new(&mut self.width).range(1..=MAX_IMAGE_SIZE).suffix("px").ui(ui);
+                        ui.end_row();
+                        ui.label("Height");
+                        DragValue::new(&mut self.height).range(1..=MAX_IMAGE_SIZE).suffix("px").ui(ui);
+                        ui.end_row();
+                        ui.label("Anti-aliasing");
+                        ComboBox::from_id_salt("export_supersampling")
+                            .selected_text(self.supersampling.to_string())
+                            .show_ui(ui, |ui| {
+                                for s in Supersampling::ALL {
+                                    ui.selectable_value(&mut self.supersampling, s, s.to_string());
+                                }
+                            });
+                        ui.end_row();
+                    });
+
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.button("Window").clicked() {
+                            self.width = (visualizer.viewport_size.x.round() as u32).max(1);
+                            self.height = (visualizer.viewport_size.y.round() as u32).max(1);
+                        }
+                        for (label, w, h) in [("1080p", 1920, 1080), ("4K", 3840, 2160), ("8K", 7680, 4320), ("8000²", 8000, 8000)] {
+                            if ui.button(label).clicked() {
+                                self.width = w;
+                                self.height = h;
+                            }
+                        }
+                    });
+                });
+
+                ui.separator();
+
+                if let Some(job) = &self.job {
+                    ui.add(ProgressBar::new(job.next_tile as f32 / job.tiles.len() as f32).show_percentage());
+                } else if self.saving.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Saving");
+                    });
+                }
+
+                ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                    if running {
+                        if ui.button("Cancel").clicked() {
+                            self.job = None;
+                        }
+                    } else if ui.add_enabled(render_state.is_some() && self.saving.is_none(), egui::Button::new("Export")).clicked() && self.pick_path() {
+                        let max_tile_size = render_state.map_or(MAX_TILE_SIZE, |rs| rs.device.limits().max_texture_dimension_2d.min(MAX_TILE_SIZE));
+                        self.job = Some(ExportJob::new(self.width, self.height, max_tile_size, visualizer.prepare_view(fractal, [self.width, self.height]), fractal.clone(), self.supersampling, visualizer.cycle_offset()));
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    /// returns false if the user canceled
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pick_path(&mut self) -> bool {
+        self.path = pick_save_path("fractal.png", &PNG_FILTER);
+        self.path.is_some()
+    }
+
+    /// the image is downloaded once it's done
+    #[cfg(target_arch = "wasm32")]
+    fn pick_path(&mut self) -> bool { true }
+
+    /// encodes and writes the png on another thread, a big image takes seconds
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save(&mut self, ctx: &egui::Context, job: ExportJob) {
+        let Some(path) = self.path.take() else { return; };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let result = job.into_png().and_then(|png| Ok(std::fs::write(path, png)?));
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        });
+        self.saving = Some(receiver);
+    }
+
+    /// there are no threads on the web, so the download blocks the ui
+    #[cfg(target_arch = "wasm32")]
+    fn save(&mut self, _ctx: &egui::Context, job: ExportJob) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let _ = sender.send(job.into_png().and_then(|png| save_file(&png, "fractal.png", &PNG_FILTER).map(|_| ())));
+        self.saving = Some(receiver);
+    }
+}
+
+impl ExportJob {
+    /// `max_tile_size` is the size of the tiles without supersampling, at most [MAX_TILE_SIZE]
+    pub fn new(width: u32, height: u32, max_tile_size: u32, view: PreparedView, fractal: Fractal, samples: Supersampling, cycle_offset: f32) -> Self {
+        let tile_size = (max_tile_size / samples.grid_size()).max(1);
+        let mut tiles = vec![];
+        for y in (0..height).step_by(tile_size as usize) {
+            for x in (0..width).step_by(tile_size as usize) {
+                tiles.push(Tile {
+                    x, y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                });
+            }
+        }
+
+        let factor = (max_tile_size as f64 / width.max(height) as f64).min(1.);
+        let histogram_size = [((width as f64 * factor) as u32).max(1), ((height as f64 * factor) as u32).max(1)];
+
+        Self {
+            width,
+            height,
+            view,
+            fractal,
+            samples,
+            cycle_offset,
+            tiles,
+            next_tile: 0,
+            pending: None,
+            histogram: None,
+            histogram_size,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Submits the next tile or copies it back once the gpu is done with it, returns true when all tiles are done
+    pub fn step(&mut self, device: &Device, queue: &Queue, render_data: &mut RenderData) -> Result<bool> {
+        // on the web this is a no-op and the callback is called by the browser
+        device.poll(wgpu::Maintain::Poll);
+
+        if let Some(pending) = &self.pending {
+            let Some(result) = pending.mapped.lock().unwrap().take() else { return Ok(false); };
+            result?;
+
+            let PendingTile { tile, buffer, padded_bytes_per_row, .. } = self.pending.take().unwrap();
+            {
+                let data = buffer.slice(..).get_mapped_range();
+                let row_len = tile.width as usize * 4;
+                for row in 0..tile.height as usize {
+                    let src = row * padded_bytes_per_row as usize;
+                    let dst = ((tile.y as usize + row) * self.width as usize + tile.x as usize) * 4;
+                    self.pixels[dst..dst + row_len].copy_from_slice(&data[src..src + row_len]);
+                }
+            }
+            buffer.unmap();
+            self.next_tile += 1;
+        }
+
+        let Some(&tile) = self.tiles.get(self.next_tile) else { return Ok(true); };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Export tile"),
+            size: Extent3d { width: tile.width, height: tile.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: EXPORT_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let padded_bytes_per_row = (tile.width * 4).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Export readback"),
+            size: padded_bytes_per_row as u64 * tile.height as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (scale, offset) = self.tile_view(tile);
+        // the export keeps the current position of the color cycling, like a screenshot would
+        let data = FractalData::new(scale, offset, [tile.width, tile.height], &self.fractal, self.cycle_offset);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Export encoder") });
+        if self.next_tile == 0 && self.tiles.len() > 1 {
+            let data = FractalData::new(self.view.scale, self.view.offset, self.histogram_size, &self.fractal, self.cycle_offset);
+            self.histogram = render_data.compute_view_histogram(device, queue, &mut encoder, self.view.shader, &data, self.view.reference.as_deref(), self.histogram_size);
+        }
+        render_data.render_to_texture(device, queue, &mut encoder, self.view.shader, &data, self.view.reference.as_deref(), self.histogram.as_ref(), self.samples, &texture.create_view(&Default::default()), EXPORT_FORMAT, [tile.width, tile.height]);
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo { texture: &texture, mip_level: 0, origin: Origin3d::ZERO, aspect: TextureAspect::All },
+            TexelCopyBufferInfo { buffer: &buffer, layout: TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded_bytes_per_row), rows_per_image: None } },
+            texture.size(),
+        );
+        queue.submit([encoder.finish()]);
+
+        let mapped = Arc::new(Mutex::new(None));
+        let mapped_clone = mapped.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |result| *mapped_clone.lock().unwrap() = Some(result));
+
+        self.pending = Some(PendingTile { tile, buffer, padded_bytes_per_row, mapped });
+        Ok(false)
+    }
+
+    /// scale and offset that render only the part of the image covered by the tile
+    fn tile_view(&self, tile: Tile) -> (DVec2, DVec2) {
+        let (scale, offset) = (self.view.scale, self.view.offset);
+        let image_size = DVec2::new(self.width as f64, self.height as f64);
+        // half of the size of the tile and its center in clip space
+        let half_size = DVec2::new(tile.width as f64, tile.height as f64) / image_size;
+        let center = DVec2::new(
+            (2 * tile.x + tile.width) as f64 / image_size.x - 1.,
+            1. - (2 * tile.y + tile.height) as f64 / image_size.y,
+        );
+        (scale * half_size, (center + offset) / half_size)
+    }
+
+    /// renders all the tiles, waiting for the gpu after each one
+    pub fn finish_blocking(&mut self, device: &Device, queue: &Queue, render_data: &mut RenderData) -> Result<()> {
+        while !self.step(device, queue, render_data)? {
+            device.poll(wgpu::Maintain::Wait);
+        }
+        Ok(())
+    }
+
+    /// rgba8 pixels of the whole image
+    pub fn into_pixels(self) -> Vec<u8> { self.pixels }
+
+    pub fn into_png(self) -> Result<Vec<u8>> {
+        encode_png(self.pixels, self.width, self.height)
+    }
+}
+
+/// encodes rgba8 pixels as a png
+pub fn encode_png(mut pixels: Vec<u8>, width: u32, height: u32) -> Result<Vec<u8>> {
+    // shaders don't care about alpha and some of them discard pixels
+    for pixel in pixels.as_chunks_mut::<4>().0 {
+        pixel[3] = 255;
+    }
+
+    let mut png = vec![];
+    PngEncoder::new(&mut png).write_image(&pixels, width, height, ExtendedColorType::Rgba8)?;
+    Ok(png)
+}
@@ -0,0 +1,116 @@
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use eframe::egui::Context;
+
+/// Kind of file shown in the file dialogs
+pub struct FileFilter {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    /// used on the web when downloading
+    pub mime: &'static str,
+}
+
+/// name and contents of the file picked by the user
+pub type LoadedFile = Result<(String, Vec<u8>)>;
+
+/// Receives the file picked with [pick_file]. On the web the file is read asynchronously so it arrives in a later frame
+#[derive(Clone, Default)]
+pub struct PickedFile(Arc<Mutex<Option<LoadedFile>>>);
+
+impl PickedFile {
+    /// Some once a file has been picked and loaded
+    pub fn take(&self) -> Option<LoadedFile> {
+        self.0.lock().unwrap().take()
+    }
+
+    fn set(&self, file: LoadedFile) {
+        *self.0.lock().unwrap() = Some(file);
+    }
+}
+
+/// Asks where to save the file, returns false if the user cancelled
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(bytes: &[u8], file_name: &str, filter: &FileFilter) -> Result<bool> {
+    let Some(path) = pick_save_path(file_name, filter) else { return Ok(false); };
+    std::fs::write(path, bytes)?;
+    Ok(true)
+}
+
+/// Asks where to save a file that isn't ready yet, None if the user cancelled
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_save_path(file_name: &str, filter: &FileFilter) -> Option<std::path::PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter(filter.name, filter.extensions)
+        .set_file_name(file_name)
+        .save_file()
+}
+
+/// Downloads the file
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(bytes: &[u8], file_name: &str, filter: &FileFilter) -> Result<bool> {
+    use anyhow::anyhow;
+    use eframe::wasm_bindgen::JsCast;
+    use eframe::web_sys::{window, Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+    let js_err = |e| anyhow!("{e:?}");
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = BlobPropertyBag::new();
+    options.set_type(filter.mime);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(js_err)?;
+    let url = Url::create_object_url_with_blob(&blob).map_err(js_err)?;
+
+    let document = window().and_then(|w| w.document()).ok_or_else(|| anyhow!("No document"))?;
+    let anchor = document.create_element("a").map_err(js_err)?
+        .dyn_into::<HtmlAnchorElement>().map_err(|_| anyhow!("Failed to create anchor"))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url).map_err(js_err)?;
+    Ok(true)
+}
+
+/// Lets the user pick a file to open, nothing is sent to `picked` if they cancel
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_file(_ctx: &Context, filter: &FileFilter, picked: PickedFile) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter(filter.name, filter.extensions)
+        .pick_file() else { return; };
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    picked.set(std::fs::read(&path).map(|bytes| (name, bytes)).map_err(|e| e.into()));
+}
+
+/// Lets the user upload a file, nothing is sent to `picked` if they cancel
+#[cfg(target_arch = "wasm32")]
+pub fn pick_file(ctx: &Context, filter: &FileFilter, picked: PickedFile) {
+    use anyhow::anyhow;
+    use eframe::wasm_bindgen::{closure::Closure, JsCast};
+    use eframe::web_sys::{window, HtmlInputElement};
+
+    let input = window().and_then(|w| w.document())
+        .and_then(|document| document.create_element("input").ok())
+        .and_then(|element| element.dyn_into::<HtmlInputElement>().ok());
+    let Some(input) = input else {
+        picked.set(Err(anyhow!("Failed to create the file input")));
+        return;
+    };
+    input.set_type("file");
+    input.set_accept(&filter.extensions.iter().map(|e| format!(".{e}")).collect::<Vec<_>>().join(","));
+
+    let ctx = ctx.clone();
+    let onchange = Closure::once_into_js({
+        let input = input.clone();
+        move || {
+            let Some(file) = input.files().and_then(|files| files.get(0)) else { return; };
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await
+                    .map(|buffer| (file.name(), js_sys::Uint8Array::new(&buffer).to_vec()))
+                    .map_err(|e| anyhow!("Failed to read the file: {e:?}"));
+                picked.set(result);
+                ctx.request_repaint();
+            });
+        }
+    });
+    input.set_onchange(Some(onchange.unchecked_ref()));
+    input.click();
+}
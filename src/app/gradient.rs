@@ -0,0 +1,302 @@
+use std::sync::LazyLock;
+use ecolor::{hex_color, Color32, Hsva, Rgba};
+use eframe::egui::{Checkbox, Slider, Ui, Widget, WidgetText};
+use glam::{Vec3, Vec4 as GVec4};
+
+/// number of gradients a shader can sample, same as gradient.wgsl
+pub const MAX_GRADIENTS: usize = 4;
+/// every gradient is baked into this many evenly spaced colors, same as gradient.wgsl
+pub const GRADIENT_SAMPLES: usize = 256;
+/// size of the gradients uniform, every color is packed into a u32 and they're followed by the cycling offset padded to 16 bytes
+pub const GRADIENTS_UNIFORM_SIZE: usize = MAX_GRADIENTS * GRADIENT_SAMPLES * 4 + 16;
+
+/// Colors placed anywhere from 0 to 1, interpolated in the chosen color space
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "UncheckedGradient")]
+pub struct Gradient {
+    /// at least 1 and the editor keeps at least 2, they don't have to be sorted
+    pub stops: Vec<GradientStop>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+/// a gradient from a share code or the palette library before its stops are checked
+#[derive(serde::Deserialize)]
+struct UncheckedGradient {
+    stops: Vec<GradientStop>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+impl TryFrom<UncheckedGradient> for Gradient {
+    type Error = &'static str;
+
+    fn try_from(gradient: UncheckedGradient) -> Result<Self, Self::Error> {
+        if gradient.stops.is_empty() { return Err("a gradient needs at least one color"); }
+        Ok(Self { stops: gradient.stops, interpolation: gradient.interpolation })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GradientStop {
+    /// from 0 to 1
+    pub position: f32,
+    pub color: Color32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Interpolation {
+    /// in gamma space, the usual way
+    #[default]
+    Rgb,
+    LinearRgb,
+    /// perceptually uniform, https://bottosson.github.io/posts/oklab/
+    Oklab,
+    /// along the shortest way around the hue circle
+    Hsv,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 4] = [Self::Rgb, Self::LinearRgb, Self::Oklab, Self::Hsv];
+
+    fn mix(self, a: Color32, b: Color32, t: f32) -> Color32 {
+        match self {
+            Self::Rgb => a.lerp_to_gamma(b, t),
+            Self::LinearRgb => Color32::from(Rgba::from(a) * (1. - t) + Rgba::from(b) * t),
+            Self::Oklab => {
+                let (a, b) = (to_oklab(a), to_oklab(b));
+                from_oklab(a.lerp(b, t))
+            }
+            Self::Hsv => {
+                let (a, b) = (Hsva::from(a), Hsva::from(b));
+                let mut h = b.h - a.h;
+                h -= h.round();
+                let lerp = |a: f32, b: f32| a + (b - a) * t;
+                Color32::from(Hsva::new((a.h + h * t).rem_euclid(1.), lerp(a.s, b.s), lerp(a.v, b.v), lerp(a.a, b.a)))
+            }
+        }
+    }
+}
+
+impl From<Interpolation> for WidgetText {
+    fn from(value: Interpolation) -> Self {
+        match value {
+            Interpolation::Rgb => "RGB".into(),
+            Interpolation::LinearRgb => "Linear RGB".into(),
+            Interpolation::Oklab => "Oklab".into(),
+            Interpolation::Hsv => "HSV".into(),
+        }
+    }
+}
+
+fn to_oklab(c: Color32) -> GVec4 {
+    let c = Rgba::from(c);
+    let lms = Vec3::new(
+        0.412_221_46 * c.r() + 0.536_332_55 * c.g() + 0.051_445_995 * c.b(),
+        0.211_903_5 * c.r() + 0.680_699_5 * c.g() + 0.107_396_96 * c.b(),
+        0.088_302_46 * c.r() + 0.281_718_85 * c.g() + 0.629_978_7 * c.b(),
+    ).map(f32::cbrt);
+    GVec4::new(
+        0.210_454_26 * lms.x + 0.793_617_8 * lms.y - 0.004_072_047 * lms.z,
+        1.977_998_5 * lms.x - 2.428_592_2 * lms.y + 0.450_593_7 * lms.z,
+        0.025_904_037 * lms.x + 0.782_771_77 * lms.y - 0.808_675_77 * lms.z,
+        c.a(),
+    )
+}
+
+fn from_oklab(lab: GVec4) -> Color32 {
+    let lms = Vec3::new(
+        lab.x + 0.396_337_78 * lab.y + 0.215_803_76 * lab.z,
+        lab.x - 0.105_561_346 * lab.y - 0.063_854_17 * lab.z,
+        lab.x - 0.089_484_18 * lab.y - 1.291_485_5 * lab.z,
+    ).powf(3.);
+    Color32::from(Rgba::from_rgba_premultiplied(
+        4.076_741_7 * lms.x - 3.307_711_6 * lms.y + 0.230_969_94 * lms.z,
+        -1.268_438 * lms.x + 2.609_757_4 * lms.y - 0.341_319_38 * lms.z,
+        -0.004_196_086_3 * lms.x - 0.703_418_6 * lms.y + 1.707_614_7 * lms.z,
+        lab.w,
+    ))
+}
+
+impl Gradient {
+    /// the colors are spread evenly
+    pub fn new(colors: &[Color32]) -> Self {
+        let stops = colors.iter().enumerate()
+            .map(|(i, &color)| GradientStop { position: i as f32 / (colors.len() - 1).max(1) as f32, color })
+            .collect();
+        Self { stops, interpolation: Interpolation::Rgb }
+    }
+
+    /// from black to white
+    pub fn grayscale() -> Self {
+        Self::new(&[Color32::BLACK, Color32::WHITE])
+    }
+
+    /// the stops from left to right
+    pub fn sorted_stops(&self) -> Vec<GradientStop> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops
+    }
+
+    /// color at t, before the first and after the last stop the color doesn't change.
+    /// The shaders interpolate between the baked colors instead so they can be a tiny bit different
+    pub fn sample(&self, t: f32) -> Color32 {
+        sample_sorted(&self.sorted_stops(), self.interpolation, t)
+    }
+
+    /// evenly spaced colors that are uploaded to the shaders
+    pub fn bake(&self) -> Vec<Color32> {
+        let stops = self.sorted_stops();
+        (0..GRADIENT_SAMPLES).map(|i| sample_sorted(&stops, self.interpolation, i as f32 / (GRADIENT_SAMPLES - 1) as f32)).collect()
+    }
+
+    /// the colors of the stops from left to right
+    pub fn colors(&self) -> Vec<Color32> {
+        self.sorted_stops().iter().map(|stop| stop.color).collect()
+    }
+
+    /// Removes the stops that the interpolation of their neighbours reproduces,
+    /// palettes imported as hundreds of evenly spaced colors usually only need a few
+    pub fn simplified(&self) -> Self {
+        // colors that are off by this much in every channel are considered the same
+        const TOLERANCE: u8 = 2;
+        let stops = self.sorted_stops();
+        if stops.len() <= 2 {
+            return self.clone();
+        }
+        let mut kept = vec![stops[0]];
+        let mut start = 0;
+        for end in 2..stops.len() {
+            let segment = [stops[start], stops[end]];
+            let fits = stops[start + 1..end].iter().all(|stop| {
+                let color = sample_sorted(&segment, self.interpolation, stop.position);
+                color.to_array().iter().zip(stop.color.to_array()).all(|(a, b)| a.abs_diff(b) <= TOLERANCE)
+            });
+            if !fits {
+                start = end - 1;
+                kept.push(stops[start]);
+            }
+        }
+        kept.push(stops[stops.len() - 1]);
+        Self { stops: kept, interpolation: self.interpolation }
+    }
+}
+
+/// transparent if there are no stops
+fn sample_sorted(stops: &[GradientStop], interpolation: Interpolation, t: f32) -> Color32 {
+    let Some(first) = stops.first() else { return Color32::TRANSPARENT; };
+    let next = stops.partition_point(|stop| stop.position <= t);
+    if next == 0 { return first.color; }
+    if next == stops.len() { return stops[next - 1].color; }
+    let (a, b) = (stops[next - 1], stops[next]);
+    interpolation.mix(a.color, b.color, (t - a.position) / (b.position - a.position).max(f32::EPSILON))
+}
+
+/// Packs the baked gradients for gradient.wgsl, missing ones are transparent.
+/// `cycle_offset` moves the colors along the gradients, check [ColorCycling]
+pub fn gradients_uniform_data(gradients: &[&Gradient], cycle_offset: f32) -> Vec<u8> {
+    debug_assert!(gradients.len() <= MAX_GRADIENTS, "Shaders can only sample {MAX_GRADIENTS} gradients");
+    let mut texels = vec![[0u8; 4]; MAX_GRADIENTS * GRADIENT_SAMPLES];
+    for (gradient, slot) in gradients.iter().zip(texels.chunks_mut(GRADIENT_SAMPLES)) {
+        for (color, texel) in gradient.bake().iter().zip(slot) {
+            *texel = color.to_array();
+        }
+    }
+    let mut data = texels.concat();
+    data.extend_from_slice(bytemuck::bytes_of(&[cycle_offset, 0., 0., 0.]));
+    data
+}
+
+/// same as cycle_position in gradient.wgsl, `t` is kept as is when the colors aren't cycling so 1 stays the last color
+pub fn cycle_position(t: f32, cycle_offset: f32) -> f32 {
+    if cycle_offset == 0. { t } else { (t + cycle_offset).rem_euclid(1.) }
+}
+
+/// Animates the gradients of the fractal by moving their colors over time, like the palette cycling of classic fractal explorers
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ColorCycling {
+    pub enabled: bool,
+    /// gradient lengths per second
+    pub speed: f32,
+    pub reverse: bool,
+    /// keeps the current offset without animating it
+    pub paused: bool,
+}
+
+impl Default for ColorCycling {
+    fn default() -> Self {
+        Self { enabled: false, speed: 0.1, reverse: false, paused: false }
+    }
+}
+
+impl ColorCycling {
+    /// true if the offset changes every frame
+    pub fn is_animating(&self) -> bool { self.enabled && !self.paused }
+
+    /// moves the offset by `dt` seconds of cycling, it stays between 0 and 1
+    pub fn advance(&self, offset: f32, dt: f32) -> f32 {
+        let direction = if self.reverse { -1. } else { 1. };
+        (offset + direction * self.speed * dt).rem_euclid(1.)
+    }
+
+    /// `supported` is false if the fractal doesn't have gradients, like Newton's fractal whose roots have fixed colors
+    pub fn ui(&mut self, ui: &mut Ui, supported: bool) {
+        ui.horizontal(|ui| {
+            ui.add_enabled(supported, Checkbox::new(&mut self.enabled, "Color cycling"))
+                .on_disabled_hover_text("Only gradients are cycled, this fractal's colors don't come from a gradient");
+            ui.add_enabled_ui(self.enabled && supported, |ui| {
+                let label = if self.paused { "⏵" } else { "⏸" };
+                if ui.button(label).on_hover_text(if self.paused { "Resume" } else { "Pause" }).clicked() {
+                    self.paused = !self.paused;
+                }
+                ui.toggle_value(&mut self.reverse, "⟲").on_hover_text("Reverse direction");
+            });
+        });
+        ui.add_enabled_ui(self.enabled && supported, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Speed");
+                Slider::new(&mut self.speed, 0.01..=2.).logarithmic(true).ui(ui)
+                    .on_hover_text("Gradient lengths per second");
+            });
+        });
+    }
+}
+
+pub static GRADIENT_PALETTES: LazyLock<Vec<Gradient>> = LazyLock::new(|| vec![
+    Gradient::new(&[hex_color!("000764"), hex_color!("206BCB"), hex_color!("EDFFFF"), hex_color!("FFAA00"), hex_color!("000200")]),
+    Gradient::new(&[hex_color!("000000"), hex_color!("7A0000"), hex_color!("FF6A00"), hex_color!("FFE600"), hex_color!("FFFFFF")]),
+    Gradient::new(&[hex_color!("0B0033"), hex_color!("370617"), hex_color!("9D0208"), hex_color!("F48C06"), hex_color!("FFBA08"), hex_color!("0B0033")]),
+    Gradient {
+        stops: vec![
+            GradientStop { position: 0., color: hex_color!("FF0000") },
+            GradientStop { position: 0.5, color: hex_color!("00FFFF") },
+            GradientStop { position: 1., color: hex_color!("FF0000") },
+        ],
+        interpolation: Interpolation::Hsv,
+    },
+    Gradient::grayscale(),
+]);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn gradients_without_stops_are_rejected() {
+        let empty = rmp_serde::to_vec_named(&HashMap::from([("stops", Vec::<GradientStop>::new())])).unwrap();
+        assert!(rmp_serde::from_slice::<Gradient>(&empty).is_err());
+
+        let gradient = Gradient { stops: vec![GradientStop { position: 0.5, color: Color32::RED }], interpolation: Interpolation::Oklab };
+        let round_trip: Gradient = rmp_serde::from_slice(&rmp_serde::to_vec_named(&gradient).unwrap()).unwrap();
+        assert_eq!(round_trip, gradient);
+    }
+
+    #[test]
+    fn empty_gradients_are_transparent() {
+        let gradient = Gradient { stops: vec![], interpolation: Interpolation::Rgb };
+        assert_eq!(gradient.sample(0.5), Color32::TRANSPARENT);
+        assert!(gradient.bake().iter().all(|&color| color == Color32::TRANSPARENT));
+    }
+}
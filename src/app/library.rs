@@ -1,5 +1,6 @@
 use eframe::egui::{Button, Grid, Separator, TextEdit, Ui, Widget};
 use egui_notify::Toasts;
+use crate::app::gradient::Gradient;
 use crate::app::widgets::error_toast;
 use crate::fractal::Fractal;
 use crate::evenly_spaced_out;
@@ -13,7 +14,7 @@ pub struct Library {
     add_text: String,
 }
 
-const EXAMPLE_FRACTALS: &[(&str, &str)] = &[
+pub const EXAMPLE_FRACTALS: &[(&str, &str)] = &[
     // madelbrot
     ("Starfish", "gbBNYW5kZWxicm90RmFtaWx5hKppdGVyYXRpb25zZKd2YXJpYW50qk1hbmRlbGJyb3SnanVsaWFfY5LKO4bWMMo_DtV6p211bHRpX2XKQKAAAA"),
     ("Sun", "gbBNYW5kZWxicm90RmFtaWx5hKppdGVyYXRpb25zZKd2YXJpYW50qk1hbmRlbGJyb3SnanVsaWFfY5LKvdXPq8o_X1wpp211bHRpX2XKQVAAAA"),
@@ -30,6 +31,12 @@ const EXAMPLE_FRACTALS: &[(&str, &str)] = &[
     ("Zircon City", "gahMeWFwdW5vdoOqaXRlcmF0aW9uc80BLKhzZXF1ZW5jZaxCQkJCQkJBQUFBQUGndmFyaWFudKtMb2dpc3RpY01hcA"),
 ];
 
+/// Palettes saved by the user, they can be picked in the dropdown of any palette or gradient editor
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct PaletteLibrary {
+    pub user_palettes: Vec<(String, Gradient)>,
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub enum Tab {
     Examples,
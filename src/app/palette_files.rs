@@ -0,0 +1,329 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ecolor::Color32;
+use crate::app::files::FileFilter;
+use crate::app::gradient::{Gradient, GradientStop, Interpolation};
+
+/// every format that can be imported
+pub const PALETTE_FILTER: FileFilter = FileFilter { name: "Palettes", extensions: &["map", "ugr", "ggr"], mime: "text/plain" };
+
+/// Palette files of other fractal programs, they are converted to and from gradients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// Fractint, 256 lines of "r g b"
+    Map,
+    /// Ultra Fractal, colors placed from 0 to 399 that wrap around
+    Ugr,
+    /// GIMP, segments with a color at each end
+    Ggr,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 3] = [Self::Map, Self::Ugr, Self::Ggr];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Map => "Fractint map (.map)",
+            Self::Ugr => "Ultra Fractal gradient (.ugr)",
+            Self::Ggr => "GIMP gradient (.ggr)",
+        }
+    }
+
+    pub fn filter(self) -> FileFilter {
+        let (name, extensions): (_, &'static [&'static str]) = match self {
+            Self::Map => ("Fractint map", &["map"]),
+            Self::Ugr => ("Ultra Fractal gradient", &["ugr"]),
+            Self::Ggr => ("GIMP gradient", &["ggr"]),
+        };
+        FileFilter { name, extensions, mime: "text/plain" }
+    }
+
+    pub fn extension(self) -> &'static str {
+        self.filter().extensions[0]
+    }
+
+    /// picks the format from the extension
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    pub fn parse(self, text: &str) -> Result<Gradient> {
+        let gradient = match self {
+            Self::Map => parse_map(text),
+            Self::Ugr => parse_ugr(text),
+            Self::Ggr => parse_ggr(text),
+        }?;
+        Ok(gradient.simplified())
+    }
+
+    /// `name` is used as the title by the formats that have one
+    pub fn write(self, gradient: &Gradient, name: &str) -> Result<String> {
+        if gradient.stops.is_empty() {
+            bail!("The gradient has no colors");
+        }
+        Ok(match self {
+            Self::Map => write_map(gradient),
+            Self::Ugr => write_ugr(&rgb_stops(gradient), name),
+            Self::Ggr => write_ggr(&rgb_stops(gradient), name)?,
+        })
+    }
+}
+
+/// Reads a palette file, the format is picked from the extension
+pub fn import_palette(file_name: &str, bytes: &[u8]) -> Result<Gradient> {
+    let format = PaletteFormat::from_file_name(file_name)
+        .ok_or_else(|| anyhow!("Unsupported palette file, expected .map, .ugr or .ggr"))?;
+    format.parse(&String::from_utf8_lossy(bytes))
+        .with_context(|| format!("Failed to import {file_name}"))
+}
+
+/// The stops of a gradient that looks the same when interpolated in rgb, the other formats don't know about color spaces.
+/// Every segment is split on its own so hard edges stay where they are
+fn rgb_stops(gradient: &Gradient) -> Vec<GradientStop> {
+    let stops = gradient.sorted_stops();
+    if gradient.interpolation == Interpolation::Rgb {
+        return stops;
+    }
+    const STEPS: usize = 32;
+    let mut baked = vec![stops[0]];
+    for pair in stops.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if b.position > a.position {
+            baked.extend((1..STEPS).map(|i| {
+                let position = a.position + (b.position - a.position) * i as f32 / STEPS as f32;
+                GradientStop { position, color: gradient.sample(position) }
+            }));
+        }
+        baked.push(b);
+    }
+    Gradient { stops: baked, interpolation: Interpolation::Rgb }.simplified().stops
+}
+
+fn parse_map(text: &str) -> Result<Gradient> {
+    let mut colors = vec![];
+    for (i, line) in text.lines().enumerate() {
+        // anything after the color is a comment
+        let values = line.split_whitespace().take(3).map(str::parse::<u8>).collect::<Result<Vec<_>, _>>();
+        match values.as_deref() {
+            Ok([]) => continue,
+            Ok(&[r, g, b]) => colors.push(Color32::from_rgb(r, g, b)),
+            _ => bail!("Line {} isn't a color", i + 1),
+        }
+    }
+    if colors.len() < 2 {
+        bail!("The map needs at least 2 colors");
+    }
+    Ok(Gradient::new(&colors))
+}
+
+fn write_map(gradient: &Gradient) -> String {
+    (0..256).map(|i| {
+        let [r, g, b, _] = gradient.sample(i as f32 / 255.).to_array();
+        format!("{r} {g} {b}\n")
+    }).collect()
+}
+
+/// indices of ultra fractal gradients go from 0 to 399
+const UGR_LENGTH: f32 = 400.;
+
+fn parse_ugr(text: &str) -> Result<Gradient> {
+    // only the first gradient of the file is read, its colors are listed before the opacity
+    let start = text.find("gradient:").ok_or_else(|| anyhow!("No gradient found"))?;
+    let body = &text[start..];
+    let body = &body[..body.find('}').unwrap_or(body.len())];
+    let body = &body[..body.find("opacity:").unwrap_or(body.len())];
+
+    let mut stops: Vec<(f32, Color32)> = vec![];
+    let mut index = None;
+    for token in body.split_whitespace() {
+        if let Some(value) = token.strip_prefix("index=") {
+            index = Some(value.parse::<i32>().with_context(|| format!("Invalid index {value}"))?);
+        } else if let Some(value) = token.strip_prefix("color=") {
+            let color = value.parse::<i64>().with_context(|| format!("Invalid color {value}"))?;
+            let index = index.take().ok_or_else(|| anyhow!("Color {value} has no index"))?;
+            // stored as 0xBBGGRR
+            let [r, g, b, ..] = (color as u32).to_le_bytes();
+            stops.push((index.rem_euclid(UGR_LENGTH as i32) as f32, Color32::from_rgb(r, g, b)));
+        }
+    }
+    if stops.is_empty() {
+        bail!("The gradient has no colors");
+    }
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // the gradient wraps around so the color at both ends is between the last and the first color,
+    // unless the last color is at 399 which is how gradients that don't wrap are stored
+    let (first, last) = (stops[0], stops[stops.len() - 1]);
+    let wraps = last.0 < UGR_LENGTH - 1.;
+    let t = (UGR_LENGTH - last.0) / (first.0 + UGR_LENGTH - last.0);
+    let wrap = if wraps { last.1.lerp_to_gamma(first.1, t) } else { last.1 };
+
+    let mut gradient = Gradient { stops: vec![], interpolation: Interpolation::Rgb };
+    if first.0 > 0. {
+        gradient.stops.push(GradientStop { position: 0., color: wrap });
+    }
+    gradient.stops.extend(stops.iter().map(|&(index, color)| GradientStop { position: index / UGR_LENGTH, color }));
+    if wraps {
+        gradient.stops.push(GradientStop { position: 1., color: wrap });
+    } else if let Some(stop) = gradient.stops.last_mut() {
+        stop.position = 1.;
+    }
+    Ok(gradient)
+}
+
+fn write_ugr(stops: &[GradientStop], name: &str) -> String {
+    let entry: String = name.split_whitespace().collect::<Vec<_>>().join("_");
+    let mut text = format!("{entry} {{\ngradient:\n  title=\"{name}\" smooth=yes\n");
+    // the ends are kept at 0 and 399, indices have to be unique
+    let mut previous = None;
+    for stop in stops {
+        let mut index = (stop.position * (UGR_LENGTH - 1.)).round() as i32;
+        if let Some(previous) = previous {
+            index = index.max(previous + 1);
+        }
+        if index >= UGR_LENGTH as i32 { break; }
+        previous = Some(index);
+        let [r, g, b, _] = stop.color.to_array();
+        let color = r as u32 | (g as u32) << 8 | (b as u32) << 16;
+        text += &format!("  index={index} color={color}\n");
+    }
+    text += "opacity:\n  smooth=no index=0 opacity=255\n}\n";
+    text
+}
+
+fn parse_ggr(text: &str) -> Result<Gradient> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        bail!("Not a GIMP gradient");
+    }
+    let mut count = lines.next().ok_or_else(|| anyhow!("The gradient is empty"))?;
+    if count.starts_with("Name:") {
+        count = lines.next().ok_or_else(|| anyhow!("The gradient is empty"))?;
+    }
+    let count: usize = count.parse().with_context(|| format!("Invalid number of segments {count}"))?;
+
+    let color = |v: &[f32]| Color32::from_rgb((v[0] * 255.).round() as u8, (v[1] * 255.).round() as u8, (v[2] * 255.).round() as u8);
+    let mut stops = vec![];
+    for i in 0..count {
+        let line = lines.next().ok_or_else(|| anyhow!("Expected {count} segments, found {i}"))?;
+        // left middle right, rgba at the left, rgba at the right, blending and coloring types are ignored
+        let values = line.split_whitespace().take(11).map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()
+            .ok().filter(|values| values.len() == 11)
+            .ok_or_else(|| anyhow!("Segment {} is invalid", i + 1))?;
+        let (left, middle, right) = (values[0], values[1], values[2]);
+        let (left_color, right_color) = (color(&values[3..6]), color(&values[7..10]));
+        stops.push(GradientStop { position: left, color: left_color });
+        // the middle is where the colors are mixed half and half
+        if (middle - (left + right) / 2.).abs() > 1e-3 {
+            stops.push(GradientStop { position: middle, color: left_color.lerp_to_gamma(right_color, 0.5) });
+        }
+        stops.push(GradientStop { position: right, color: right_color });
+    }
+    if stops.is_empty() {
+        bail!("The gradient is empty");
+    }
+    Ok(Gradient { stops, interpolation: Interpolation::Rgb })
+}
+
+fn write_ggr(stops: &[GradientStop], name: &str) -> Result<String> {
+    let (Some(&first), Some(&last)) = (stops.first(), stops.last()) else {
+        bail!("The gradient has no colors");
+    };
+    // segments have to cover 0 to 1, the colors are constant past the first and the last stop
+    let mut stops = stops.to_vec();
+    stops.insert(0, GradientStop { position: 0., color: first.color });
+    stops.push(GradientStop { position: 1., color: last.color });
+    let segments: Vec<_> = stops.windows(2).filter(|pair| pair[1].position > pair[0].position).collect();
+
+    let rgb = |color: Color32| {
+        let [r, g, b, _] = color.to_array().map(|c| c as f32 / 255.);
+        format!("{r:.6} {g:.6} {b:.6} 1.000000")
+    };
+    let mut text = format!("GIMP Gradient\nName: {name}\n{}\n", segments.len());
+    for pair in segments {
+        let (left, right) = (pair[0], pair[1]);
+        text += &format!(
+            "{:.6} {:.6} {:.6} {} {} 0 0\n",
+            left.position, (left.position + right.position) / 2., right.position, rgb(left.color), rgb(right.color),
+        );
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(colors: &[Color32], interpolation: Interpolation) -> Gradient {
+        Gradient { interpolation, ..Gradient::new(colors) }
+    }
+
+    fn assert_looks_the_same(a: &Gradient, b: &Gradient, tolerance: u8, what: &str) {
+        for i in 0..=64 {
+            let t = i as f32 / 64.;
+            let (a, b) = (a.sample(t), b.sample(t));
+            let difference = a.to_array().iter().zip(b.to_array()).map(|(a, b)| a.abs_diff(b)).max().unwrap();
+            assert!(difference <= tolerance, "{what} at {t}: {a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        let colors = [Color32::BLACK, Color32::from_rgb(200, 30, 60), Color32::from_rgb(20, 220, 120), Color32::WHITE];
+        for interpolation in Interpolation::ALL {
+            let original = gradient(&colors, interpolation);
+            for format in PaletteFormat::ALL {
+                let text = format.write(&original, "Round trip").unwrap();
+                let parsed = format.parse(&text).unwrap_or_else(|e| panic!("{format:?} {interpolation:?}: {e:#}\n{text}"));
+                assert_eq!(parsed.interpolation, Interpolation::Rgb);
+                // ugr moves the stops to the closest of 400 indices
+                assert_looks_the_same(&original, &parsed, 6, &format!("{format:?} {interpolation:?}"));
+            }
+        }
+    }
+
+    #[test]
+    fn hard_edges_are_kept() {
+        let mut original = gradient(&[Color32::RED, Color32::BLUE], Interpolation::Oklab);
+        original.stops.insert(1, GradientStop { position: 0.3, color: Color32::WHITE });
+        original.stops.insert(2, GradientStop { position: 0.3, color: Color32::BLACK });
+        let stops = rgb_stops(&original);
+        let edge: Vec<_> = stops.iter().filter(|stop| stop.position == 0.3).map(|stop| stop.color).collect();
+        assert_eq!(edge, [Color32::WHITE, Color32::BLACK]);
+    }
+
+    #[test]
+    fn empty_gradients_are_not_written() {
+        let empty = Gradient { stops: vec![], interpolation: Interpolation::Rgb };
+        for format in PaletteFormat::ALL {
+            assert!(format.write(&empty, "Empty").is_err(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn ggr_with_missing_segments() {
+        let text = "GIMP Gradient\nName: Short\n3\n0 0.25 0.5 0 0 0 1 1 1 1 1 0 0\n";
+        let error = PaletteFormat::Ggr.parse(text).unwrap_err();
+        assert_eq!(error.to_string(), "Expected 3 segments, found 1");
+        assert!(PaletteFormat::Ggr.parse("GIMP Gradient\nName: Empty\n").is_err());
+        assert!(PaletteFormat::Ggr.parse("GIMP Gradient\n1\n0 0.5 1 0 0 0 1\n").is_err());
+    }
+
+    #[test]
+    fn ugr_color_without_index() {
+        let text = "Broken {\ngradient:\n  title=\"Broken\" smooth=yes\n  index=0 color=0\n  color=255\n}\n";
+        let error = PaletteFormat::Ugr.parse(text).unwrap_err();
+        assert_eq!(error.to_string(), "Color 255 has no index");
+        assert!(PaletteFormat::Ugr.parse("Empty {\ngradient:\n}\n").is_err());
+    }
+
+    #[test]
+    fn map_with_short_lines() {
+        let error = PaletteFormat::Map.parse("0 0 0\n255 255\n255 255 255\n").unwrap_err();
+        assert_eq!(error.to_string(), "Line 2 isn't a color");
+        assert!(PaletteFormat::Map.parse("0 0 0\n").is_err());
+        // blank lines and comments after the colors are fine
+        let gradient = PaletteFormat::Map.parse("0 0 0 black\n\n255 255 255 white\n").unwrap();
+        assert_eq!(gradient.colors(), [Color32::BLACK, Color32::WHITE]);
+    }
+}
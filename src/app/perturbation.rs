@@ -0,0 +1,400 @@
+// Deep zoom past double precision using perturbation theory.
+// A single reference orbit is computed on the cpu with arbitrary precision and every pixel only iterates its (tiny) difference from it on the gpu.
+// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Perturbation_theory_and_series_approximation
+use std::ops::{Add, Mul, Sub};
+use std::sync::{Arc, Mutex};
+use anyhow::{bail, Result};
+use glam::DVec2;
+use num_bigint::BigInt;
+use num_complex::Complex32;
+use num_traits::{ToPrimitive, Zero};
+use crate::app::rendering::needs_double_precision;
+use crate::fractal::{Fractal, FractalTrait};
+use crate::wgsl::Shader;
+
+/// double-float shaders stop being accurate when pixels get this small
+const MAX_DOUBLE_PRECISION_PIXEL_SIZE: f64 = 1e-13;
+/// the reference orbit is recomputed if its center drifts this far from the view's center, in clip space units
+const MAX_REFERENCE_DISTANCE: f64 = 4.;
+
+/// Fixed point number with an arbitrary amount of fractional bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigFixed {
+    value: BigInt,
+    frac_bits: u32,
+}
+
+impl BigFixed {
+    pub fn zero(frac_bits: u32) -> Self {
+        Self { value: BigInt::zero(), frac_bits }
+    }
+
+    /// the conversion is exact as long as there are enough fractional bits
+    pub fn from_f64(v: f64, frac_bits: u32) -> Self {
+        if v == 0. || !v.is_finite() { return Self::zero(frac_bits); }
+
+        // v = mantissa * 2^exponent
+        let bits = v.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = if biased_exponent == 0 { (bits & 0xf_ffff_ffff_ffff) << 1 } else { (bits & 0xf_ffff_ffff_ffff) | (1 << 52) };
+        let shift = biased_exponent - 1075 + frac_bits as i64;
+
+        let mut value = BigInt::from(mantissa);
+        if shift >= 0 { value <<= shift as u64 } else { value >>= (-shift) as u64 }
+        if v < 0. { value = -value; }
+        Self { value, frac_bits }
+    }
+
+    /// parses a decimal number like `-1.25e-3`, f64 would lose the digits needed for deep zooms
+    pub fn parse(s: &str, frac_bits: u32) -> Result<Self> {
+        let s = s.trim();
+        let (negative, number) = match s.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>()?),
+            None => (number, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = format!("{int}{frac}");
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            bail!("Invalid number: {s}");
+        }
+
+        // number = digits * 10^exponent
+        let exponent = exponent - frac.len() as i32;
+        if exponent.unsigned_abs() > 10_000 { bail!("Exponent is too large: {s}"); }
+        let ten_pow = BigInt::from(10).pow(exponent.unsigned_abs());
+        let mut value = BigInt::parse_bytes(digits.as_bytes(), 10).unwrap() << frac_bits;
+        if exponent >= 0 { value *= ten_pow } else { value /= ten_pow }
+        if negative { value = -value; }
+        Ok(Self { value, frac_bits })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // only the top 64 bits fit in a f64 anyway
+        let shift = self.value.bits().saturating_sub(64);
+        let mantissa = (&self.value >> shift).to_f64().unwrap_or(0.);
+        mantissa * (shift as f64 - self.frac_bits as f64).exp2()
+    }
+
+    pub fn frac_bits(&self) -> u32 { self.frac_bits }
+
+    pub fn with_precision(&self, frac_bits: u32) -> Self {
+        let value = if frac_bits >= self.frac_bits {
+            &self.value << (frac_bits - self.frac_bits)
+        } else {
+            &self.value >> (self.frac_bits - frac_bits)
+        };
+        Self { value, frac_bits }
+    }
+
+    /// both operands with the same precision, the highest of the two
+    fn aligned(&self, other: &Self) -> (Self, Self) {
+        let frac_bits = self.frac_bits.max(other.frac_bits);
+        (self.with_precision(frac_bits), other.with_precision(frac_bits))
+    }
+}
+
+impl Add for &BigFixed {
+    type Output = BigFixed;
+    fn add(self, rhs: Self) -> BigFixed {
+        let (a, b) = self.aligned(rhs);
+        BigFixed { value: a.value + b.value, frac_bits: a.frac_bits }
+    }
+}
+
+impl Sub for &BigFixed {
+    type Output = BigFixed;
+    fn sub(self, rhs: Self) -> BigFixed {
+        let (a, b) = self.aligned(rhs);
+        BigFixed { value: a.value - b.value, frac_bits: a.frac_bits }
+    }
+}
+
+impl Mul for &BigFixed {
+    type Output = BigFixed;
+    fn mul(self, rhs: Self) -> BigFixed {
+        let (a, b) = self.aligned(rhs);
+        BigFixed { value: (a.value * b.value) >> a.frac_bits, frac_bits: a.frac_bits }
+    }
+}
+
+/// Arbitrary precision complex number, used for the center of the view
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigComplex {
+    pub re: BigFixed,
+    pub im: BigFixed,
+}
+
+impl BigComplex {
+    pub fn zero(frac_bits: u32) -> Self {
+        Self { re: BigFixed::zero(frac_bits), im: BigFixed::zero(frac_bits) }
+    }
+
+    pub fn from_dvec2(v: DVec2, frac_bits: u32) -> Self {
+        Self { re: BigFixed::from_f64(v.x, frac_bits), im: BigFixed::from_f64(v.y, frac_bits) }
+    }
+
+    pub fn to_dvec2(&self) -> DVec2 {
+        DVec2::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    pub fn frac_bits(&self) -> u32 { self.re.frac_bits() }
+
+    pub fn with_precision(&self, frac_bits: u32) -> Self {
+        Self { re: self.re.with_precision(frac_bits), im: self.im.with_precision(frac_bits) }
+    }
+
+    pub fn square(&self) -> Self {
+        let re = &(&self.re * &self.re) - &(&self.im * &self.im);
+        let im = &self.re * &self.im;
+        Self { re, im: &im + &im }
+    }
+}
+
+impl Add for &BigComplex {
+    type Output = BigComplex;
+    fn add(self, rhs: Self) -> BigComplex {
+        BigComplex { re: &self.re + &rhs.re, im: &self.im + &rhs.im }
+    }
+}
+
+impl Sub for &BigComplex {
+    type Output = BigComplex;
+    fn sub(self, rhs: Self) -> BigComplex {
+        BigComplex { re: &self.re - &rhs.re, im: &self.im - &rhs.im }
+    }
+}
+
+/// fractional bits needed to tell apart points at the given scale, rounded up so zooming doesn't change it every frame
+pub fn precision_for_scale(scale: f64) -> u32 {
+    let bits = (-scale.log2()).max(0.).ceil() as u32 + 64;
+    bits.next_multiple_of(64)
+}
+
+pub fn needs_perturbation(scale: DVec2, size: [u32; 2]) -> bool {
+    let pixel_size = (2. * scale.x / size[0] as f64).min(2. * scale.y / size[1] as f64);
+    pixel_size < MAX_DOUBLE_PRECISION_PIXEL_SIZE
+}
+
+/// Iteration formulas that can be rendered using perturbation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Formula {
+    /// z^2 + c
+    Mandelbrot,
+    /// z^2 - z + c
+    Modified,
+}
+
+/// Returned by fractals that support perturbation, check [FractalTrait::perturbation]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Perturbation {
+    /// shader that iterates the deltas, it needs the reference orbit bound in group 1
+    pub shader: Shader,
+    pub formula: Formula,
+    /// None for the Mandelbrot set, where c is the position and z starts at 0
+    pub julia_c: Option<Complex32>,
+    pub iterations: u32,
+    pub escape_radius: f32,
+}
+
+#[derive(Debug)]
+pub struct ReferenceOrbit {
+    pub perturbation: Perturbation,
+    pub center: BigComplex,
+    /// z for every iteration until it escapes, the first one is the starting z
+    pub points: Vec<[f32; 2]>,
+}
+
+impl ReferenceOrbit {
+    pub fn compute(perturbation: Perturbation, center: BigComplex) -> Self {
+        let frac_bits = center.frac_bits();
+        let (mut z, c) = match perturbation.julia_c {
+            None => (BigComplex::zero(frac_bits), center.clone()),
+            Some(c) => (center.clone(), BigComplex::from_dvec2(DVec2::new(c.re as f64, c.im as f64), frac_bits)),
+        };
+
+        let r_sq = (perturbation.escape_radius as f64).powi(2);
+        let mut points = Vec::with_capacity(perturbation.iterations as usize + 1);
+        let mut z_f64 = z.to_dvec2();
+        points.push([z_f64.x as f32, z_f64.y as f32]);
+        // the point that escapes is kept too, the shader needs it to check for escape
+        while points.len() <= perturbation.iterations as usize && z_f64.length_squared() <= r_sq {
+            z = match perturbation.formula {
+                Formula::Mandelbrot => &z.square() + &c,
+                Formula::Modified => &(&z.square() - &z) + &c,
+            };
+            z_f64 = z.to_dvec2();
+            points.push([z_f64.x as f32, z_f64.y as f32]);
+        }
+
+        Self { perturbation, center, points }
+    }
+}
+
+/// Reference orbit computed on another thread so deep zooms don't freeze the ui
+#[derive(Debug, Clone)]
+pub struct PendingReference(Arc<Mutex<Option<ReferenceOrbit>>>);
+
+impl PendingReference {
+    /// `on_done` is called from the other thread once the orbit is ready.
+    /// There are no threads on the web so it's computed right away
+    pub fn spawn(perturbation: Perturbation, center: BigComplex, on_done: impl FnOnce() + Send + 'static) -> Self {
+        let orbit = Arc::new(Mutex::new(None));
+        let compute = {
+            let orbit = orbit.clone();
+            move || {
+                *orbit.lock().unwrap() = Some(ReferenceOrbit::compute(perturbation, center));
+                on_done();
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(compute);
+        #[cfg(target_arch = "wasm32")]
+        compute();
+        Self(orbit)
+    }
+
+    /// Some once the orbit is ready
+    pub fn take(&self) -> Option<ReferenceOrbit> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Everything needed to render a view of a fractal, check [prepare_view]
+#[derive(Debug, Clone)]
+pub struct PreparedView {
+    pub shader: Shader,
+    pub scale: DVec2,
+    /// relative to the reference orbit's center if there is one, otherwise relative to 0
+    pub offset: DVec2,
+    pub reference: Option<Arc<ReferenceOrbit>>,
+}
+
+/// Picks the shader able to render the view with enough precision and computes a reference orbit if perturbation is needed.
+/// `cached` is reused if it's still good enough for the view
+pub fn prepare_view(fractal: &Fractal, center: &BigComplex, scale: DVec2, size: [u32; 2], supports_storage_buffers: bool, cached: Option<&Arc<ReferenceOrbit>>) -> PreparedView {
+    try_prepare_view(fractal, center, scale, size, supports_storage_buffers, cached,
+                     |perturbation, center| Some(Arc::new(ReferenceOrbit::compute(perturbation, center))))
+        .unwrap()
+}
+
+/// Same as [prepare_view] but `reference` is asked for the orbit when `cached` isn't good enough.
+/// Returns None if it doesn't have the orbit yet
+pub fn try_prepare_view(
+    fractal: &Fractal, center: &BigComplex, scale: DVec2, size: [u32; 2], supports_storage_buffers: bool, cached: Option<&Arc<ReferenceOrbit>>,
+    reference: impl FnOnce(Perturbation, BigComplex) -> Option<Arc<ReferenceOrbit>>,
+) -> Option<PreparedView> {
+    let perturbation = fractal.perturbation()
+        .filter(|_| supports_storage_buffers && needs_perturbation(scale, size));
+
+    let Some(perturbation) = perturbation else {
+        return Some(PreparedView {
+            shader: fractal.get_shader().with_precision(needs_double_precision(scale, size)).with_storage_buffers(supports_storage_buffers),
+            scale,
+            offset: center.to_dvec2() / scale,
+            reference: None,
+        });
+    };
+
+    let frac_bits = precision_for_scale(scale.min_element());
+    let reference = match cached
+        .filter(|r| r.perturbation == perturbation
+            && r.center.frac_bits() >= frac_bits
+            && ((center - &r.center).to_dvec2() / scale).abs().max_element() < MAX_REFERENCE_DISTANCE) {
+        Some(cached) => cached.clone(),
+        None => reference(perturbation, center.with_precision(frac_bits))?,
+    };
+
+    Some(PreparedView {
+        shader: perturbation.shader,
+        scale,
+        offset: (center - &reference.center).to_dvec2() / scale,
+        reference: Some(reference),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_f64_round_trips() {
+        for v in [0., 1., 0.5, -3.75, 1e-20, -123456.789, 1e-200, -f64::EPSILON] {
+            assert_eq!(BigFixed::from_f64(v, 768).to_f64(), v);
+        }
+    }
+
+    #[test]
+    fn from_f64_truncates_to_the_precision() {
+        // 2^-10 doesn't fit in 8 fractional bits
+        assert_eq!(BigFixed::from_f64(1. + 2f64.powi(-10), 8).to_f64(), 1.);
+        assert_eq!(BigFixed::from_f64(2f64.powi(-10), 8), BigFixed::zero(8));
+    }
+
+    #[test]
+    fn parse_matches_f64() {
+        for s in ["0", "1", "-1", "0.5", "-0.75", "+2.25", "1.25e2", "-1.25E-3", ".5", "3."] {
+            let v: f64 = s.parse().unwrap();
+            let parsed = BigFixed::parse(s, 128).unwrap().to_f64();
+            assert!((parsed - v).abs() <= v.abs() * 1e-15, "{s}: {parsed} != {v}");
+        }
+        assert_eq!(BigFixed::parse("-0.5", 64).unwrap(), BigFixed::from_f64(-0.5, 64));
+    }
+
+    #[test]
+    fn parse_keeps_digits_past_f64() {
+        let a = BigFixed::parse("1.00000000000000000000000001", 256).unwrap();
+        let one = BigFixed::parse("1", 256).unwrap();
+        assert_eq!(a.to_f64(), 1.);
+        let difference = (&a - &one).to_f64();
+        assert!((difference - 1e-26).abs() < 1e-40, "{difference}");
+
+        // 10^-40 * 10^40 only comes back to 1 if the small number kept enough bits
+        let product = &BigFixed::parse("1e-40", 256).unwrap() * &BigFixed::parse("1e40", 256).unwrap();
+        assert!((&product - &one).to_f64().abs() < 1e-30);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_numbers() {
+        for s in ["", "-", "abc", "1.2.3", "--1", "1e", "1e5.5", "0x10", "1 2"] {
+            assert!(BigFixed::parse(s, 64).is_err(), "{s}");
+        }
+        assert!(BigFixed::parse("1e100000", 64).is_err());
+    }
+
+    #[test]
+    fn add_carries_across_all_bits() {
+        let tiny = BigFixed::from_f64(2f64.powi(-100), 128);
+        let one = BigFixed::from_f64(1., 128);
+        // 0.111...1 in binary, one bit away from 1
+        let almost_one = &one - &tiny;
+        assert!(almost_one != one);
+        assert_eq!(&almost_one + &tiny, one);
+
+        assert_eq!((&BigFixed::from_f64(0.75, 64) + &BigFixed::from_f64(0.25, 64)).to_f64(), 1.);
+        assert_eq!((&BigFixed::from_f64(-0.25, 64) + &BigFixed::from_f64(0.75, 64)).to_f64(), 0.5);
+        assert_eq!((&BigFixed::from_f64(-1.5, 64) + &BigFixed::from_f64(-2.5, 64)).to_f64(), -4.);
+    }
+
+    #[test]
+    fn mul_handles_signs_and_precisions() {
+        let mul = |a: f64, b: f64| (&BigFixed::from_f64(a, 64) * &BigFixed::from_f64(b, 128)).to_f64();
+        assert_eq!(mul(1.5, -2.25), -3.375);
+        assert_eq!(mul(-0.5, -0.5), 0.25);
+        assert_eq!(mul(3., 0.), 0.);
+
+        let product = &BigFixed::from_f64(1., 64) * &BigFixed::from_f64(2f64.powi(-100), 128);
+        assert_eq!(product.frac_bits(), 128);
+        assert_eq!(product.to_f64(), 2f64.powi(-100));
+    }
+
+    #[test]
+    fn with_precision_keeps_the_value() {
+        let v = BigFixed::from_f64(-5.125, 64);
+        assert_eq!(v.with_precision(256).to_f64(), -5.125);
+        assert_eq!(v.with_precision(256).with_precision(64), v);
+    }
+}
@@ -1,105 +1,1493 @@
-use eframe::{egui::ahash::HashMap, egui_wgpu::CallbackTrait};
-use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, Device, FragmentState, MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor, ShaderStages, TextureFormat, VertexState};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use bytemuck::{bytes_of, cast_slice, pod_read_unaligned};
+use eframe::{egui::{ahash::HashMap, Vec2}, egui_wgpu::CallbackTrait};
+use encase::UniformBuffer;
+use glam::{DVec2, IVec2, Vec2 as GVec2};
+use wgpu::{include_wgsl, DeviceDescriptor, Instance, InstanceDescriptor, PowerPreference, Queue, RequestAdapterOptions, util::{BufferInitDescriptor, DeviceExt, TextureDataOrder}, Adapter, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Color, ColorTargetState, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, DownlevelFlags, StorageTextureAccess, TextureViewDescriptor, ColorWrites, CommandEncoder, Device, Extent3d, FragmentState, LoadOp, MultisampleState, Operations, Origin3d, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderStages, StoreOp, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureSampleType, TextureUsages, TextureView, TextureViewDimension, VertexState};
 
-use crate::wgsl::Shader;
+use crate::app::gradient::{gradients_uniform_data, GRADIENTS_UNIFORM_SIZE};
+use crate::app::perturbation::ReferenceOrbit;
+use crate::fractal::{Fractal, FractalTrait};
+use crate::wgsl::{uniform_structs, ParamsBinding, Shader};
+
+/// Samples are summed in this format, then averaged by the resolve pass
+const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Used if the adapter can't blend the accumulation format, only a single sample can be taken in that case
+const FALLBACK_ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+const ADDITIVE_BLENDING: BlendState = BlendState {
+    color: BlendComponent { src_factor: BlendFactor::One, dst_factor: BlendFactor::One, operation: BlendOperation::Add },
+    alpha: BlendComponent { src_factor: BlendFactor::One, dst_factor: BlendFactor::One, operation: BlendOperation::Add },
+};
 
 pub struct RenderData {
-    main_uniform_buffer: Buffer,
-    bind_group: BindGroup,
+    bind_group_layout: BindGroupLayout,
     pipeline_layout: PipelineLayout,
-    pipelines: HashMap<Shader, RenderPipeline>,
+    pipelines: HashMap<Shader, FractalPipeline>,
+    /// the main data of every sample has to start at a multiple of this
+    uniform_alignment: u64,
+
+    /// None if the device doesn't support storage buffers (WebGL2), perturbation can't be used then
+    storage_bind_group_layout: Option<BindGroupLayout>,
+    /// like pipeline_layout but with a storage buffer in group 1, used for the reference orbit and the fractal's parameters
+    storage_pipeline_layout: Option<PipelineLayout>,
+    /// like pipeline_layout but with the fractal's parameters in a texture in group 1
+    texture_pipeline_layout: PipelineLayout,
+    texture_bind_group_layout: BindGroupLayout,
+    /// the reference orbit used by the visualizer
+    reference: Option<UploadedReference>,
+    /// the fractal's parameters used by the visualizer
+    params: Option<UploadedParams>,
+    /// None if compute shaders or storage textures aren't supported (WebGL2), fractals are drawn in a single pass then
+    two_pass: Option<TwoPass>,
+
+    resolve_bind_group_layout: BindGroupLayout,
+    resolve_pipeline_layout: PipelineLayout,
+    resolve_shader: ShaderModule,
+    resolve_pipelines: HashMap<TextureFormat, RenderPipeline>,
+
+    accumulation_format: TextureFormat,
+    supports_supersampling: bool,
+    progressive: Option<ProgressiveRender>,
 
     target_format: TextureFormat,
 }
 
+/// Pipeline of a shader together with the uniform buffers the visualizer uses with it, sized for the fractal's data
+struct FractalPipeline {
+    /// draws the fractal, or only colors its raw data if it's rendered in two passes
+    pipeline: RenderPipeline,
+    /// Some if the fractal is rendered in two passes, computes the raw data of every pixel
+    compute: Option<ComputePipeline>,
+    /// check [FractalTrait::uniform_size]
+    uniform_size: u64,
+    /// check [FractalTrait::coloring_size]
+    coloring_size: u64,
+    /// holds one copy of the main data for each sample, each at a different offset
+    uniform_buffer: Buffer,
+    coloring_buffer: Buffer,
+    /// check gradient.wgsl
+    gradients_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+/// Bind group layouts and pipelines of the two pass path, check two_pass.wgsl and histogram.wgsl
+struct TwoPass {
+    /// the raw data as a storage texture and the origin of the tile
+    compute_layout: BindGroupLayout,
+    /// the raw data read by the colorize pass
+    colorize_layout: BindGroupLayout,
+    /// the cumulative distribution of the iterations read by the colorize pass
+    cdf_layout: BindGroupLayout,
+    /// bound in place of the histogram's cdf when it hasn't been computed
+    empty_cdf_bind_group: BindGroup,
+    /// group 1 of the shaders that don't use it, groups can't be skipped
+    empty_layout: BindGroupLayout,
+    empty_bind_group: BindGroup,
+    histogram_layout: BindGroupLayout,
+    count_pipeline: ComputePipeline,
+    accumulate_pipeline: ComputePipeline,
+}
+
+/// Raw data of every pixel written by the compute pass, with a layer for every sample
+struct RawData {
+    texture: Texture,
+    /// one for every layer
+    views: Vec<TextureView>,
+    /// one for every layer
+    compute_bind_groups: Vec<BindGroup>,
+    /// one for every layer
+    colorize_bind_groups: Vec<BindGroup>,
+    /// buffers of the histogram, created the first time one is needed
+    histogram: Option<Histogram>,
+    /// bins of the histogram that was computed from the current raw data, 0 if there's none
+    histogram_bins: u32,
+}
+
+/// Histogram of the iterations of the first layer of the raw data, check histogram.wgsl
+#[derive(Debug)]
+struct Histogram {
+    counts: Buffer,
+    cdf: Buffer,
+    props: Buffer,
+    /// max number of bins that fit in the buffers
+    capacity: u32,
+    cdf_bind_group: BindGroup,
+}
+
+/// Histogram of a whole view computed once from a low resolution render, check [RenderData::compute_view_histogram].
+/// The tiles of an export share it so they're all colored the same way
+#[derive(Debug)]
+pub struct ViewHistogram(Histogram);
+
+/// Offscreen texture that the samples are added together in
+struct Accumulation {
+    texture: Texture,
+    view: TextureView,
+    resolve_uniform_buffer: Buffer,
+    resolve_bind_group: BindGroup,
+}
+
+struct UploadedReference {
+    orbit: Arc<ReferenceOrbit>,
+    bind_group: BindGroup,
+}
+
+struct UploadedParams {
+    params: Vec<[f32; 4]>,
+    binding: ParamsBinding,
+    bind_group: BindGroup,
+}
+
+/// Square tiles the visualizer is split into so that expensive renders can be spread over multiple frames
+const TILE_SIZE: u32 = 256;
+/// same as two_pass.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+/// Format of the raw data, check two_pass.wgsl
+const RAW_DATA_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+/// State of the visualizer, which renders progressively.
+/// The work is split into units, one for every tile of every sample, and each frame only a limited number of units is rendered.
+/// Samples are accumulated in `back` and copied to `display` every time a sample finishes so the last complete image stays on screen.
+/// In the two pass path the raw data of every sample is kept so the coloring can be changed without computing the fractal again.
+struct ProgressiveRender {
+    back: Accumulation,
+    display: Accumulation,
+    raw: Option<RawData>,
+    job: Option<RenderJob>,
+    done_units: u32,
+    /// single pass only, the coloring changed while a sample was being drawn so everything is drawn again once it's complete
+    stale_coloring: bool,
+}
+
+struct RenderJob {
+    shader_code: Shader,
+    data: FractalData,
+    samples: Supersampling,
+    reference: Option<Arc<ReferenceOrbit>>,
+}
+
+/// Everything a fractal passes to its shader, computed once per frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FractalData {
+    /// check [main_uniform_data]
+    pub main_data: Vec<u8>,
+    /// check [coloring_uniform_data]
+    pub coloring_data: Vec<u8>,
+    /// check [FractalTrait::params]
+    pub params: Vec<[f32; 4]>,
+    /// check [FractalTrait::histogram_bins]
+    pub histogram_bins: u32,
+    /// check [gradients_uniform_data]
+    pub gradients: Vec<u8>,
+    /// check [FractalTrait::uniform_offsets]
+    pub uniform_offsets: &'static [u64],
+    /// check [FractalTrait::coloring_offsets]
+    pub coloring_offsets: &'static [u64],
+}
+
+impl FractalData {
+    /// scale, offset and size are the view, check [main_uniform_data].
+    /// `cycle_offset` is the position of the color cycling animation, check [gradients_uniform_data]
+    pub fn new(scale: DVec2, offset: DVec2, size: [u32; 2], fractal: &Fractal, cycle_offset: f32) -> Self {
+        Self {
+            main_data: main_uniform_data(scale, offset, size, fractal),
+            coloring_data: coloring_uniform_data(fractal),
+            params: fractal.params(),
+            histogram_bins: fractal.histogram_bins(),
+            gradients: gradients_uniform_data(&fractal.gradients(), cycle_offset),
+            uniform_offsets: fractal.uniform_offsets(),
+            coloring_offsets: fractal.coloring_offsets(),
+        }
+    }
+}
+
+impl RenderJob {
+    /// true if only the coloring can be different
+    fn same_raw_data(&self, other: &Self) -> bool {
+        // reference orbits can be huge so they're compared by address
+        let same_reference = match (&self.reference, &other.reference) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.shader_code == other.shader_code && self.data.main_data == other.data.main_data && self.data.params == other.data.params && self.samples == other.samples && same_reference
+    }
+
+    /// true if the raw data has to be colored again, the histogram is part of the coloring
+    fn same_coloring(&self, other: &Self) -> bool {
+        self.data.coloring_data == other.data.coloring_data && self.data.gradients == other.data.gradients && self.data.histogram_bins == other.data.histogram_bins
+    }
+}
+
+/// Progress of the visualizer's render, shared with the renderer callback
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderProgress {
+    pub done_units: u32,
+    pub total_units: u32,
+}
+
+impl RenderProgress {
+    pub fn is_done(&self) -> bool { self.done_units >= self.total_units }
+    pub fn fraction(&self) -> f32 { self.done_units as f32 / self.total_units.max(1) as f32 }
+}
+
 impl RenderData {
-    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+    pub fn new(device: &Device, adapter: &Adapter, target_format: TextureFormat) -> Self {
+        let supports_two_pass = adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_textures_per_shader_stage > 0
+            && adapter.get_texture_format_features(RAW_DATA_FORMAT).allowed_usages.contains(TextureUsages::STORAGE_BINDING)
+            // the histogram is read from a storage buffer
+            && device.limits().max_storage_buffers_per_shader_stage > 0;
+        // the fractal's resources are also used by the compute pass
+        let fractal_stages = if supports_two_pass { ShaderStages::VERTEX_FRAGMENT | ShaderStages::COMPUTE } else { ShaderStages::VERTEX_FRAGMENT };
 
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Fractal bind group layout"),
-            entries: &[BindGroupLayoutEntry{
+            entries: &[
+                BindGroupLayoutEntry{
+                    binding: 0,
+                    visibility: fractal_stages,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        // every sample uses a different offset
+                        has_dynamic_offset: true,
+                        // every fractal has a different size
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry{
+                    binding: 1,
+                    visibility: fractal_stages,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry{
+                    binding: 2,
+                    visibility: fractal_stages,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(GRADIENTS_UNIFORM_SIZE as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Fractal visualizer layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let storage_bind_group_layout = (device.limits().max_storage_buffers_per_shader_stage > 0).then(|| {
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Storage bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: fractal_stages,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+        });
+
+        let storage_pipeline_layout = storage_bind_group_layout.as_ref().map(|storage_bind_group_layout| {
+            device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("Fractal visualizer storage layout"),
+                bind_group_layouts: &[&bind_group_layout, storage_bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        });
+
+        let texture_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Params texture bind group layout"),
+            entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX_FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+                visibility: fractal_stages,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
                 },
                 count: None,
             }],
         });
 
-        let main_uniform_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("Fractal main uniform"),
-            size: MAIN_UNIFORM_BUFFER_SIZE as u64,
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+        let texture_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Fractal visualizer params texture layout"),
+            bind_group_layouts: &[&bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
         });
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Fractal bind group"),
-            layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::Buffer(main_uniform_buffer.as_entire_buffer_binding()),
-            }],
+        let two_pass = supports_two_pass.then(|| TwoPass::new(device));
+
+        let resolve_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Resolve bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Fractal visualizer layout"),
-            bind_group_layouts: &[&bind_group_layout],
+        let resolve_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Resolve layout"),
+            bind_group_layouts: &[&resolve_bind_group_layout],
             push_constant_ranges: &[],
         });
 
+        let resolve_shader = device.create_shader_module(include_wgsl!("../wgsl/resolve.wgsl"));
+
+        let features = adapter.get_texture_format_features(ACCUMULATION_FORMAT);
+        let supports_supersampling = features.allowed_usages.contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING)
+            && features.flags.contains(TextureFormatFeatureFlags::BLENDABLE);
+
         Self {
-            main_uniform_buffer,
-            bind_group,
+            bind_group_layout,
             pipeline_layout,
             pipelines: HashMap::default(),
+            uniform_alignment: device.limits().min_uniform_buffer_offset_alignment as u64,
+            storage_bind_group_layout,
+            storage_pipeline_layout,
+            texture_pipeline_layout,
+            texture_bind_group_layout,
+            reference: None,
+            params: None,
+            two_pass,
+            resolve_bind_group_layout,
+            resolve_pipeline_layout,
+            resolve_shader,
+            resolve_pipelines: HashMap::default(),
+            accumulation_format: if supports_supersampling { ACCUMULATION_FORMAT } else { FALLBACK_ACCUMULATION_FORMAT },
+            supports_supersampling,
+            progressive: None,
             target_format,
         }
     }
 
-    fn ensure_pipeline_created(&mut self, device: &Device, shader_code: Shader) {
-        let descriptor = shader_code.get_shader();
+    /// perturbation needs storage buffers, other shaders fall back to textures without them
+    pub fn supports_storage_buffers(&self) -> bool { self.storage_bind_group_layout.is_some() }
+
+    /// the uniform buffers are sized for `data`, every shader is only used by one kind of fractal so the sizes never change
+    fn ensure_pipeline_created(&mut self, device: &Device, shader_code: Shader, data: &FractalData) {
+        let (uniform_size, coloring_size) = (data.main_data.len() as u64, data.coloring_data.len() as u64);
+        if let Some(pipeline) = self.pipelines.get(&shader_code) {
+            debug_assert_eq!(pipeline.uniform_size, uniform_size, "{shader_code:?} was used with main data of different sizes");
+            debug_assert_eq!(pipeline.coloring_size, coloring_size, "{shader_code:?} was used with coloring data of different sizes");
+            return;
+        }
+
+        let (single_pass_layout, group1_layout) = match (shader_code.uses_reference_orbit(), shader_code.params_binding()) {
+            (true, _) | (_, Some(ParamsBinding::StorageBuffer)) => (
+                self.storage_pipeline_layout.as_ref().expect("Storage buffers should only be used if they are supported"),
+                self.storage_bind_group_layout.as_ref(),
+            ),
+            (_, Some(ParamsBinding::Texture)) => (&self.texture_pipeline_layout, Some(&self.texture_bind_group_layout)),
+            (false, None) => (&self.pipeline_layout, None),
+        };
+
+        let descriptor = if self.two_pass.is_some() { shader_code.get_two_pass_shader() } else { shader_code.get_shader() };
+        #[cfg(debug_assertions)]
+        check_uniform_layout(shader_code, &descriptor, data);
         let label=  format!("Pipeline visualizer {:?}", descriptor.label);
         let shader_module = device.create_shader_module(descriptor);
 
-        self.pipelines.entry(shader_code).or_insert_with(||
-            device.create_render_pipeline(&RenderPipelineDescriptor {
-                label: Some(&label),
-                layout: Some(&self.pipeline_layout),
-                vertex: VertexState {
+        // the colorize pass also needs the raw data
+        let (colorize_layout, compute) = match &self.two_pass {
+            None => (None, None),
+            Some(two_pass) => {
+                let group1_layout = group1_layout.unwrap_or(&two_pass.empty_layout);
+                let compute_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("Fractal compute layout"),
+                    bind_group_layouts: &[&self.bind_group_layout, group1_layout, &two_pass.compute_layout],
+                    push_constant_ranges: &[],
+                });
+                let compute = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: Some(&format!("Compute pipeline {:?}", shader_code)),
+                    layout: Some(&compute_layout),
                     module: &shader_module,
-                    entry_point: None, // picks the default one
-                    buffers: &[],
+                    entry_point: Some("compute"),
                     compilation_options: Default::default(),
+                    cache: None,
+                });
+                let colorize_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("Fractal colorize layout"),
+                    bind_group_layouts: &[&self.bind_group_layout, group1_layout, &two_pass.colorize_layout, &two_pass.cdf_layout],
+                    push_constant_ranges: &[],
+                });
+                (Some(colorize_layout), Some(compute))
+            }
+        };
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&label),
+            layout: Some(colorize_layout.as_ref().unwrap_or(single_pass_layout)),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: None, // picks the default one
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                // the two pass shader has both fragment entry points
+                entry_point: compute.is_some().then_some("colorize_fragment"),
+                targets: &[Some(ColorTargetState {
+                    format: self.accumulation_format,
+                    blend: Some(ADDITIVE_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Fractal main uniform"),
+            size: self.uniform_stride(uniform_size) * Supersampling::MAX_SAMPLES as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let coloring_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Fractal coloring uniform"),
+            size: coloring_buffer_size(coloring_size),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let gradients_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Fractal gradients uniform"),
+            size: GRADIENTS_UNIFORM_SIZE as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = create_bind_group(device, &self.bind_group_layout, &uniform_buffer, uniform_size, &coloring_buffer, &gradients_buffer);
+        self.pipelines.insert(shader_code, FractalPipeline { pipeline, compute, uniform_size, coloring_size, uniform_buffer, coloring_buffer, gradients_buffer, bind_group });
+    }
+
+    fn ensure_resolve_pipeline_created(&mut self, device: &Device, format: TextureFormat) {
+        if self.resolve_pipelines.contains_key(&format) { return; }
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Pipeline resolve"),
+            layout: Some(&self.resolve_pipeline_layout),
+            vertex: VertexState {
+                module: &self.resolve_shader,
+                entry_point: None,
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &self.resolve_shader,
+                entry_point: None,
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        self.resolve_pipelines.insert(format, pipeline);
+    }
+
+    fn sample_count(&self, samples: Supersampling) -> u32 {
+        if self.supports_supersampling { samples.sample_count() } else { 1 }
+    }
+
+    /// distance between the main data of two samples
+    fn uniform_stride(&self, uniform_size: u64) -> u64 {
+        uniform_size.next_multiple_of(self.uniform_alignment)
+    }
+
+    /// Copies the main data once for every sample and moves each copy by a sub-pixel amount
+    fn sample_uniform_data(&self, main_data: &[u8], samples: Supersampling, size: [u32; 2]) -> Vec<u8> {
+        let sample_count = self.sample_count(samples);
+        let stride = self.uniform_stride(main_data.len() as u64) as usize;
+        let mut data = vec![0u8; stride * sample_count as usize];
+        // check ViewUniform for the layout
+        let offset = join_f64(GVec2::from_array(pod_read_unaligned(&main_data[8..16])), GVec2::from_array(pod_read_unaligned(&main_data[24..32])));
+        // the size of a pixel in clip space is 2 / size
+        let pixel_size = DVec2::new(2. / size[0] as f64, 2. / size[1] as f64);
+
+        let jitter = if sample_count == 1 { Supersampling::X1.jitter() } else { samples.jitter() };
+        for (i, jitter) in jitter.enumerate() {
+            let slot = &mut data[i * stride..][..main_data.len()];
+            slot.copy_from_slice(main_data);
+            let (hi, lo) = split_f64(offset + DVec2::new(jitter.x as f64, jitter.y as f64) * pixel_size);
+            slot[8..16].copy_from_slice(bytes_of(&hi.to_array()));
+            slot[24..32].copy_from_slice(bytes_of(&lo.to_array()));
+        }
+        data
+    }
+
+    /// Clears the accumulation texture and adds all the samples to it.
+    /// If `histogram_bins` isn't 0 the histogram is computed from the first sample, unless `view_histogram` is given
+    #[allow(clippy::too_many_arguments)]
+    fn accumulate(&self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, shader_code: Shader, bind_group: &BindGroup, group1: Option<&BindGroup>, histogram_bins: u32, view_histogram: Option<&ViewHistogram>, sample_count: u32, target: &TextureView, size: [u32; 2]) {
+        let pipeline = &self.pipelines[&shader_code];
+        let stride = self.uniform_stride(pipeline.uniform_size);
+
+        let Some(compute) = &pipeline.compute else {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Fractal accumulation pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations { load: LoadOp::Clear(Color::TRANSPARENT), store: StoreOp::Store },
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(&pipeline.pipeline);
+            if let Some(group1) = group1 {
+                pass.set_bind_group(1, group1, &[]);
+            }
+            for i in 0..sample_count {
+                pass.set_bind_group(0, bind_group, &[(i as u64 * stride) as u32]);
+                // vertex coordinates are hardcoded in the shader so a vertex buffer is not needed
+                pass.draw(0..6, 0..1);
+            }
+            return;
+        };
+
+        // the coloring doesn't change so only one sample has to be stored at a time
+        let mut raw = RawData::new(device, self, size, 1, &[[0, 0]]);
+        let two_pass = self.two_pass.as_ref().unwrap();
+        let group1 = group1.unwrap_or(&two_pass.empty_bind_group);
+        for i in 0..sample_count {
+            let offset = (i as u64 * stride) as u32;
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(compute);
+                pass.set_bind_group(0, bind_group, &[offset]);
+                pass.set_bind_group(1, group1, &[]);
+                pass.set_bind_group(2, &raw.compute_bind_groups[0], &[0]);
+                pass.dispatch_workgroups(size[0].div_ceil(WORKGROUP_SIZE), size[1].div_ceil(WORKGROUP_SIZE), 1);
+            }
+            if i == 0 && histogram_bins > 0 && view_histogram.is_none() {
+                raw.compute_histogram(device, queue, encoder, two_pass, histogram_bins, size);
+            }
+
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Fractal colorize pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations { load: if i == 0 { LoadOp::Clear(Color::TRANSPARENT) } else { LoadOp::Load }, store: StoreOp::Store },
+                })],
+                ..Default::default()
+            });
+            pass.set_pipeline(&pipeline.pipeline);
+            pass.set_bind_group(0, bind_group, &[offset]);
+            pass.set_bind_group(1, group1, &[]);
+            pass.set_bind_group(2, &raw.colorize_bind_groups[0], &[]);
+            pass.set_bind_group(3, view_histogram.map_or_else(|| raw.cdf_bind_group(two_pass), |histogram| &histogram.0.cdf_bind_group), &[]);
+            pass.draw(0..6, 0..1);
+        }
+    }
+
+    /// Renders the next units of the visualizer's job within the budget, returns the progress
+    #[allow(clippy::too_many_arguments)]
+    fn render_progressive(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, job: RenderJob, size: [u32; 2], budget: f32, cost: u32) -> RenderProgress {
+        let mut progressive = match self.progressive.take() {
+            Some(progressive) if progressive.back.size() == size => progressive,
+            _ => ProgressiveRender {
+                back: Accumulation::new(device, self, size),
+                display: Accumulation::new(device, self, size),
+                raw: None,
+                job: None,
+                done_units: 0,
+                stale_coloring: false,
+            },
+        };
+
+        let sample_count = self.sample_count(job.samples);
+        let tiles = [size[0].div_ceil(TILE_SIZE), size[1].div_ceil(TILE_SIZE)];
+        let tile_count = tiles[0] * tiles[1];
+        let total_units = tile_count * sample_count;
+
+        let two_pass = self.pipelines[&job.shader_code].compute.is_some();
+        if two_pass && progressive.raw.as_ref().is_none_or(|raw| raw.layers() != sample_count) {
+            let origins = (0..tile_count).map(|tile| [(tile % tiles[0]) * TILE_SIZE, (tile / tiles[0]) * TILE_SIZE]).collect::<Vec<_>>();
+            progressive.raw = Some(RawData::new(device, self, size, sample_count, &origins));
+            progressive.job = None;
+        }
+
+        let mut recolor = false;
+        match &progressive.job {
+            Some(previous) if previous.same_raw_data(&job) => {
+                if !previous.same_coloring(&job) {
+                    let pipeline = &self.pipelines[&job.shader_code];
+                    queue.write_buffer(&pipeline.coloring_buffer, 0, &job.data.coloring_data);
+                    queue.write_buffer(&pipeline.gradients_buffer, 0, &job.data.gradients);
+                    // single pass shaders have to draw everything again, the sample being drawn is finished first
+                    // so that the display keeps changing when the colors are cycled faster than a sample is drawn
+                    if two_pass {
+                        recolor = true;
+                    } else if progressive.done_units % tile_count == 0 {
+                        progressive.done_units = 0;
+                    } else {
+                        progressive.stale_coloring = true;
+                    }
+                }
+            }
+            _ => {
+                let pipeline = &self.pipelines[&job.shader_code];
+                queue.write_buffer(&pipeline.uniform_buffer, 0, &self.sample_uniform_data(&job.data.main_data, job.samples, size));
+                queue.write_buffer(&pipeline.coloring_buffer, 0, &job.data.coloring_data);
+                queue.write_buffer(&pipeline.gradients_buffer, 0, &job.data.gradients);
+                if let Some(orbit) = &job.reference
+                    && !self.reference.as_ref().is_some_and(|r| Arc::ptr_eq(&r.orbit, orbit)) {
+                    let bind_group = self.create_reference_bind_group(device, orbit);
+                    self.reference = Some(UploadedReference { orbit: orbit.clone(), bind_group });
+                }
+                if let Some(binding) = job.shader_code.params_binding()
+                    && !self.params.as_ref().is_some_and(|p| p.binding == binding && p.params == job.data.params) {
+                    let bind_group = self.create_params_bind_group(device, queue, binding, &job.data.params);
+                    self.params = Some(UploadedParams { params: job.data.params.clone(), binding, bind_group });
+                }
+                if let Some(raw) = &mut progressive.raw {
+                    raw.histogram_bins = 0;
+                }
+                progressive.done_units = 0;
+                progressive.stale_coloring = false;
+            }
+        }
+        progressive.job = Some(job);
+        let job = progressive.job.as_ref().unwrap();
+
+        if recolor {
+            // the histogram can only be computed once the first sample is complete
+            if progressive.done_units >= tile_count {
+                self.ensure_histogram(device, queue, encoder, job, progressive.raw.as_mut().unwrap(), size);
+            }
+            // the display only shows the samples that were complete
+            let complete_units = progressive.done_units / tile_count * tile_count;
+            self.draw_units(encoder, job, progressive.raw.as_ref(), &progressive.back.view, true, 0..progressive.done_units, tiles, size);
+            self.draw_units(encoder, job, progressive.raw.as_ref(), &progressive.display.view, true, 0..complete_units, tiles, size);
+        }
+
+        if progressive.done_units < total_units {
+            let unit_cost = (TILE_SIZE * TILE_SIZE) as f32 * cost.max(1) as f32;
+            let units = ((budget / unit_cost) as u32).clamp(1, total_units - progressive.done_units);
+            let units = progressive.done_units..progressive.done_units + units;
+
+            if let Some(raw) = &progressive.raw && two_pass {
+                self.compute_units(encoder, job, raw, units.clone(), tiles, size);
+            }
+            self.draw_units(encoder, job, progressive.raw.as_ref(), &progressive.back.view, progressive.done_units == 0, units.clone(), tiles, size);
+            progressive.done_units = units.end;
+
+            // once the first sample is computed everything drawn so far is colored again with its histogram
+            if two_pass && job.data.histogram_bins > 0 && units.contains(&(tile_count - 1)) {
+                self.ensure_histogram(device, queue, encoder, job, progressive.raw.as_mut().unwrap(), size);
+                self.draw_units(encoder, job, progressive.raw.as_ref(), &progressive.back.view, true, 0..progressive.done_units, tiles, size);
+            }
+
+            // a sample is complete so the display can be updated
+            let completed_samples = progressive.done_units / tile_count;
+            if progressive.done_units % tile_count == 0 {
+                encoder.copy_texture_to_texture(
+                    TexelCopyTextureInfo { texture: &progressive.back.texture, mip_level: 0, origin: Origin3d::ZERO, aspect: TextureAspect::All },
+                    TexelCopyTextureInfo { texture: &progressive.display.texture, mip_level: 0, origin: Origin3d::ZERO, aspect: TextureAspect::All },
+                    progressive.back.texture.size(),
+                );
+                queue.write_buffer(&progressive.display.resolve_uniform_buffer, 0, &resolve_uniform_data(completed_samples));
+                if progressive.stale_coloring {
+                    progressive.stale_coloring = false;
+                    progressive.done_units = 0;
+                }
+            }
+        }
+
+        let progress = RenderProgress { done_units: progressive.done_units, total_units };
+        self.progressive = Some(progressive);
+        progress
+    }
+
+    /// Computes the histogram of the raw data if the job's coloring needs one and it isn't already there
+    fn ensure_histogram(&self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, job: &RenderJob, raw: &mut RawData, size: [u32; 2]) {
+        let bins = job.data.histogram_bins;
+        if bins > 0 && raw.histogram_bins != bins {
+            raw.compute_histogram(device, queue, encoder, self.two_pass.as_ref().unwrap(), bins, size);
+        }
+    }
+
+    /// Bind group 1 of the visualizer's job, which holds the reference orbit or the fractal's parameters
+    fn group1(&self, job: &RenderJob) -> Option<&BindGroup> {
+        if job.reference.is_some() {
+            Some(&self.reference.as_ref().unwrap().bind_group)
+        } else if job.shader_code.params_binding().is_some() {
+            Some(&self.params.as_ref().unwrap().bind_group)
+        } else if self.pipelines[&job.shader_code].compute.is_some() {
+            Some(&self.two_pass.as_ref().unwrap().empty_bind_group)
+        } else {
+            None
+        }
+    }
+
+    /// Computes the raw data of the units' tiles, each sample is stored in its own layer
+    #[allow(clippy::too_many_arguments)]
+    fn compute_units(&self, encoder: &mut CommandEncoder, job: &RenderJob, raw: &RawData, units: Range<u32>, tiles: [u32; 2], size: [u32; 2]) {
+        let pipeline = &self.pipelines[&job.shader_code];
+        let stride = self.uniform_stride(pipeline.uniform_size);
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: Some("Fractal progressive compute pass"), timestamp_writes: None });
+        pass.set_pipeline(pipeline.compute.as_ref().unwrap());
+        pass.set_bind_group(1, self.group1(job), &[]);
+        for unit in units {
+            let (sample, tile, [_, _, width, height]) = unit_rect(unit, tiles, size);
+            pass.set_bind_group(0, &pipeline.bind_group, &[(sample as u64 * stride) as u32]);
+            pass.set_bind_group(2, &raw.compute_bind_groups[sample as usize], &[tile * self.uniform_alignment as u32]);
+            pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+        }
+    }
+
+    /// Adds the units' tiles to the target, in the two pass path this only colors the raw data
+    #[allow(clippy::too_many_arguments)]
+    fn draw_units(&self, encoder: &mut CommandEncoder, job: &RenderJob, raw: Option<&RawData>, target: &TextureView, clear: bool, units: Range<u32>, tiles: [u32; 2], size: [u32; 2]) {
+        let pipeline = &self.pipelines[&job.shader_code];
+        let stride = self.uniform_stride(pipeline.uniform_size);
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Fractal progressive pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: if clear { LoadOp::Clear(Color::TRANSPARENT) } else { LoadOp::Load },
+                    store: StoreOp::Store,
                 },
-                fragment: Some(FragmentState {
-                    module: &shader_module,
-                    entry_point: None,
-                    targets: &[Some(ColorTargetState {
-                        format: self.target_format,
-                        blend: None,
-                        write_mask: ColorWrites::ALL,
-                    })],
-                    compilation_options: Default::default(),
+            })],
+            ..Default::default()
+        });
+
+        pass.set_pipeline(&pipeline.pipeline);
+        if let Some(group1) = self.group1(job) {
+            pass.set_bind_group(1, group1, &[]);
+        }
+        for unit in units {
+            let (sample, _, [x, y, width, height]) = unit_rect(unit, tiles, size);
+            pass.set_scissor_rect(x, y, width, height);
+            pass.set_bind_group(0, &pipeline.bind_group, &[(sample as u64 * stride) as u32]);
+            if let Some(raw) = raw && let Some(two_pass) = &self.two_pass && pipeline.compute.is_some() {
+                pass.set_bind_group(2, &raw.colorize_bind_groups[sample as usize], &[]);
+                pass.set_bind_group(3, raw.cdf_bind_group(two_pass), &[]);
+            }
+            pass.draw(0..6, 0..1);
+        }
+    }
+
+    /// Renders the fractal into an arbitrary texture instead of the egui render pass, used for exporting images.
+    /// The uniforms get their own buffers so they won't clash with whatever the visualizer is drawing.
+    /// `histogram` replaces the one that would be computed from this render, check [Self::compute_view_histogram]
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_to_texture(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, shader_code: Shader, data: &FractalData, reference: Option<&ReferenceOrbit>, histogram: Option<&ViewHistogram>, samples: Supersampling, target: &TextureView, format: TextureFormat, size: [u32; 2]) {
+        self.ensure_pipeline_created(device, shader_code, data);
+        self.ensure_resolve_pipeline_created(device, format);
+
+        let sample_count = self.sample_count(samples);
+        let (bind_group, group1) = self.create_offscreen_bind_groups(device, queue, shader_code, data, reference, samples, size);
+        let accumulation = Accumulation::new(device, self, size);
+        let resolve_uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Resolve offscreen uniform"),
+            contents: &resolve_uniform_data(sample_count),
+            usage: BufferUsages::UNIFORM,
+        });
+        let resolve_bind_group = create_resolve_bind_group(device, &self.resolve_bind_group_layout, &accumulation.view, &resolve_uniform);
+
+        self.accumulate(device, queue, encoder, shader_code, &bind_group, group1.as_ref(), data.histogram_bins, histogram, sample_count, &accumulation.view, size);
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Fractal offscreen resolve pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations { load: LoadOp::Clear(Color::BLACK), store: StoreOp::Store },
+            })],
+            ..Default::default()
+        });
+        pass.set_pipeline(&self.resolve_pipelines[&format]);
+        pass.set_bind_group(0, &resolve_bind_group, &[]);
+        pass.draw(0..6, 0..1);
+    }
+
+    /// Computes the histogram of the first sample of a `size` render of the view, which can be much smaller than the exported image.
+    /// None if the fractal's coloring doesn't need one or it isn't rendered in two passes
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute_view_histogram(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, shader_code: Shader, data: &FractalData, reference: Option<&ReferenceOrbit>, size: [u32; 2]) -> Option<ViewHistogram> {
+        self.ensure_pipeline_created(device, shader_code, data);
+        let compute = self.pipelines[&shader_code].compute.as_ref()?;
+        if data.histogram_bins == 0 { return None; }
+
+        let (bind_group, group1) = self.create_offscreen_bind_groups(device, queue, shader_code, data, reference, Supersampling::X1, size);
+        let two_pass = self.two_pass.as_ref().unwrap();
+        let mut raw = RawData::new(device, self, size, 1, &[[0, 0]]);
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(compute);
+            pass.set_bind_group(0, &bind_group, &[0]);
+            pass.set_bind_group(1, group1.as_ref().unwrap_or(&two_pass.empty_bind_group), &[]);
+            pass.set_bind_group(2, &raw.compute_bind_groups[0], &[0]);
+            pass.dispatch_workgroups(size[0].div_ceil(WORKGROUP_SIZE), size[1].div_ceil(WORKGROUP_SIZE), 1);
+        }
+        raw.compute_histogram(device, queue, encoder, two_pass, data.histogram_bins, size);
+        raw.histogram.take().map(ViewHistogram)
+    }
+
+    /// group 0 with its own uniform buffers and group 1 if the shader has one
+    #[allow(clippy::too_many_arguments)]
+    fn create_offscreen_bind_groups(&self, device: &Device, queue: &Queue, shader_code: Shader, data: &FractalData, reference: Option<&ReferenceOrbit>, samples: Supersampling, size: [u32; 2]) -> (BindGroup, Option<BindGroup>) {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Fractal offscreen uniform"),
+            contents: &self.sample_uniform_data(&data.main_data, samples, size),
+            usage: BufferUsages::UNIFORM,
+        });
+        let mut coloring_data = data.coloring_data.clone();
+        coloring_data.resize(coloring_buffer_size(coloring_data.len() as u64) as usize, 0);
+        let coloring_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Fractal offscreen coloring uniform"),
+            contents: &coloring_data,
+            usage: BufferUsages::UNIFORM,
+        });
+        let gradients_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Fractal offscreen gradients uniform"),
+            contents: &data.gradients,
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = create_bind_group(device, &self.bind_group_layout, &uniform_buffer, data.main_data.len() as u64, &coloring_buffer, &gradients_buffer);
+        let group1 = match (reference, shader_code.params_binding()) {
+            (Some(orbit), _) => Some(self.create_reference_bind_group(device, orbit)),
+            (None, Some(binding)) => Some(self.create_params_bind_group(device, queue, binding, &data.params)),
+            (None, None) => None,
+        };
+        (bind_group, group1)
+    }
+
+    fn create_reference_bind_group(&self, device: &Device, orbit: &ReferenceOrbit) -> BindGroup {
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Reference orbit"),
+            contents: cast_slice(&orbit.points),
+            usage: BufferUsages::STORAGE,
+        });
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Reference orbit bind group"),
+            layout: self.storage_bind_group_layout.as_ref().expect("Perturbation shaders should only be used if they are supported"),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        })
+    }
+
+    fn create_params_bind_group(&self, device: &Device, queue: &Queue, binding: ParamsBinding, params: &[[f32; 4]]) -> BindGroup {
+        // empty buffers and textures can't be bound
+        let params = if params.is_empty() { &[[0.; 4]] } else { params };
+        match binding {
+            ParamsBinding::StorageBuffer => {
+                let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Fractal params"),
+                    contents: cast_slice(params),
+                    usage: BufferUsages::STORAGE,
+                });
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Fractal params bind group"),
+                    layout: self.storage_bind_group_layout.as_ref().expect("Storage buffers should only be used if they are supported"),
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                })
+            }
+            ParamsBinding::Texture => {
+                // one texel per element, wrapped into rows
+                let width = (params.len() as u32).min(device.limits().max_texture_dimension_2d);
+                let height = (params.len() as u32).div_ceil(width);
+                let mut data = params.to_vec();
+                data.resize((width * height) as usize, [0.; 4]);
+                let texture = device.create_texture_with_data(queue, &TextureDescriptor {
+                    label: Some("Fractal params texture"),
+                    size: Extent3d { width, height, depth_or_array_layers: 1 },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba32Float,
+                    usage: TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                }, TextureDataOrder::LayerMajor, cast_slice(&data));
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Fractal params bind group"),
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture.create_view(&Default::default())),
+                    }],
+                })
+            }
+        }
+    }
+}
+
+impl TwoPass {
+    fn new(device: &Device) -> Self {
+        let compute_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Raw data compute bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: RAW_DATA_FORMAT,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        // every tile uses a different offset
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(TILE_UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let colorize_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Raw data colorize bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let cdf_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Histogram cdf bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let empty_cdf = device.create_buffer(&BufferDescriptor {
+            label: Some("Empty histogram cdf"),
+            size: 4,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let empty_cdf_bind_group = create_cdf_bind_group(device, &cdf_layout, &empty_cdf);
+
+        let empty_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor { label: Some("Empty bind group layout"), entries: &[] });
+        let empty_bind_group = device.create_bind_group(&BindGroupDescriptor { label: Some("Empty bind group"), layout: &empty_layout, entries: &[] });
+
+        let storage_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let histogram_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Histogram bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(HISTOGRAM_UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let histogram_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Histogram layout"),
+            bind_group_layouts: &[&histogram_layout],
+            push_constant_ranges: &[],
+        });
+        let histogram_shader = device.create_shader_module(include_wgsl!("../wgsl/histogram.wgsl"));
+        let histogram_pipeline = |entry_point| device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(&format!("Histogram {entry_point} pipeline")),
+            layout: Some(&histogram_pipeline_layout),
+            module: &histogram_shader,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let count_pipeline = histogram_pipeline("count");
+        let accumulate_pipeline = histogram_pipeline("accumulate");
+
+        Self { compute_layout, colorize_layout, cdf_layout, empty_cdf_bind_group, empty_layout, empty_bind_group, histogram_layout, count_pipeline, accumulate_pipeline }
+    }
+}
+
+impl Histogram {
+    fn new(device: &Device, two_pass: &TwoPass, capacity: u32) -> Self {
+        let storage = |label, usage| device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: capacity as u64 * 4,
+            usage: BufferUsages::STORAGE | usage,
+            mapped_at_creation: false,
+        });
+        let counts = storage("Histogram counts", BufferUsages::COPY_DST);
+        let cdf = storage("Histogram cdf", BufferUsages::empty());
+        let props = device.create_buffer(&BufferDescriptor {
+            label: Some("Histogram uniform"),
+            size: HISTOGRAM_UNIFORM_SIZE,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cdf_bind_group = create_cdf_bind_group(device, &two_pass.cdf_layout, &cdf);
+        Self { counts, cdf, props, capacity, cdf_bind_group }
+    }
+}
+
+impl RawData {
+    /// `tile_origins` are the top left corners of the tiles that will be computed
+    fn new(device: &Device, render_data: &RenderData, size: [u32; 2], layers: u32, tile_origins: &[[u32; 2]]) -> Self {
+        let two_pass = render_data.two_pass.as_ref().expect("Only used if two passes are supported");
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Raw data texture"),
+            size: Extent3d { width: size[0], height: size[1], depth_or_array_layers: layers },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: RAW_DATA_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let stride = render_data.uniform_alignment as usize;
+        let mut tiles = vec![0u8; stride * tile_origins.len()];
+        for (i, origin) in tile_origins.iter().enumerate() {
+            tiles[i * stride..][..8].copy_from_slice(bytes_of(origin));
+        }
+        // origin of every tile, each at a different offset
+        let tile_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Tile uniform"),
+            contents: &tiles,
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let views = (0..layers).map(|layer| texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })).collect::<Vec<_>>();
+        let compute_bind_groups = views.iter().map(|view| device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Raw data compute bind group"),
+            layout: &two_pass.compute_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: BindingResource::TextureView(view) },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding { buffer: &tile_buffer, offset: 0, size: BufferSize::new(TILE_UNIFORM_SIZE) }),
+                },
+            ],
+        })).collect();
+        let colorize_bind_groups = views.iter().map(|view| device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Raw data colorize bind group"),
+            layout: &two_pass.colorize_layout,
+            entries: &[BindGroupEntry { binding: 2, resource: BindingResource::TextureView(view) }],
+        })).collect();
+
+        Self { texture, views, compute_bind_groups, colorize_bind_groups, histogram: None, histogram_bins: 0 }
+    }
+
+    fn layers(&self) -> u32 { self.texture.depth_or_array_layers() }
+
+    /// Counts the iterations of the first layer into `bins` bins and turns them into a cumulative distribution
+    fn compute_histogram(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, two_pass: &TwoPass, bins: u32, size: [u32; 2]) {
+        if self.histogram.as_ref().is_none_or(|histogram| histogram.capacity < bins) {
+            self.histogram = Some(Histogram::new(device, two_pass, bins));
+        }
+        let histogram = self.histogram.as_ref().unwrap();
+        queue.write_buffer(&histogram.props, 0, bytes_of(&[bins, 0, 0, 0]));
+        encoder.clear_buffer(&histogram.counts, 0, None);
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Histogram bind group"),
+            layout: &two_pass.histogram_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: BindingResource::TextureView(&self.views[0]) },
+                BindGroupEntry { binding: 1, resource: histogram.counts.as_entire_binding() },
+                BindGroupEntry { binding: 2, resource: histogram.cdf.as_entire_binding() },
+                BindGroupEntry { binding: 3, resource: histogram.props.as_entire_binding() },
+            ],
+        });
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: Some("Histogram pass"), timestamp_writes: None });
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_pipeline(&two_pass.count_pipeline);
+        pass.dispatch_workgroups(size[0].div_ceil(WORKGROUP_SIZE), size[1].div_ceil(WORKGROUP_SIZE), 1);
+        pass.set_pipeline(&two_pass.accumulate_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+        drop(pass);
+        self.histogram_bins = bins;
+    }
+
+    /// group 3 of the colorize pass
+    fn cdf_bind_group<'a>(&'a self, two_pass: &'a TwoPass) -> &'a BindGroup {
+        self.histogram.as_ref().map_or(&two_pass.empty_cdf_bind_group, |histogram| &histogram.cdf_bind_group)
+    }
+}
+
+/// Sample, tile and scissor rect (x, y, width, height) of a unit of the progressive render
+fn unit_rect(unit: u32, tiles: [u32; 2], size: [u32; 2]) -> (u32, u32, [u32; 4]) {
+    let tile_count = tiles[0] * tiles[1];
+    let (sample, tile) = (unit / tile_count, unit % tile_count);
+    let (x, y) = ((tile % tiles[0]) * TILE_SIZE, (tile / tiles[0]) * TILE_SIZE);
+    (sample, tile, [x, y, TILE_SIZE.min(size[0] - x), TILE_SIZE.min(size[1] - y)])
+}
+
+impl Accumulation {
+    fn new(device: &Device, render_data: &RenderData, size: [u32; 2]) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Accumulation texture"),
+            size: Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: render_data.accumulation_format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+
+        let resolve_uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Resolve uniform"),
+            size: RESOLVE_UNIFORM_BUFFER_SIZE as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let resolve_bind_group = create_resolve_bind_group(device, &render_data.resolve_bind_group_layout, &view, &resolve_uniform_buffer);
+
+        Self { texture, view, resolve_uniform_buffer, resolve_bind_group }
+    }
+
+    fn size(&self) -> [u32; 2] { [self.texture.width(), self.texture.height()] }
+}
+
+fn create_bind_group(device: &Device, layout: &BindGroupLayout, main_uniform_buffer: &Buffer, uniform_size: u64, coloring_buffer: &Buffer, gradients_buffer: &Buffer) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Fractal bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: main_uniform_buffer,
+                    offset: 0,
+                    size: BufferSize::new(uniform_size),
                 }),
-                primitive: PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: MultisampleState::default(),
-                multiview: None,
-                cache: None,
-            })
-        );
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: coloring_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: gradients_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+fn create_cdf_bind_group(device: &Device, layout: &BindGroupLayout, cdf: &Buffer) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Histogram cdf bind group"),
+        layout,
+        entries: &[BindGroupEntry { binding: 0, resource: cdf.as_entire_binding() }],
+    })
+}
+
+/// buffers can't be empty, fractals without coloring data still get a small one
+fn coloring_buffer_size(coloring_size: u64) -> u64 {
+    coloring_size.max(16)
+}
+
+fn create_resolve_bind_group(device: &Device, layout: &BindGroupLayout, accumulation: &TextureView, resolve_uniform_buffer: &Buffer) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Resolve bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(accumulation),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: resolve_uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// size of ViewUniform
+const VIEW_UNIFORM_SIZE: usize = 48;
+const RESOLVE_UNIFORM_BUFFER_SIZE: usize = 16;
+/// size of Tile in two_pass.wgsl
+const TILE_UNIFORM_SIZE: u64 = 16;
+/// size of Props in histogram.wgsl
+const HISTOGRAM_UNIFORM_SIZE: u64 = 16;
+/// smaller scales get an exponent, f32 can't go much lower
+const MIN_F32_SCALE: f64 = 1e-30;
+
+uniform_structs! {
+    /// Start of every shader's uniform, the fractal's own data follows it.
+    /// The view is stored in double precision as the sum of a high and a low f32.
+    /// Tiny scales are also multiplied by 2^scale_exp, only perturbation shaders are used that deep.
+    struct ViewUniform {
+        scale: GVec2 = 0,
+        offset: GVec2 = 8,
+        scale_lo: GVec2 = 16,
+        offset_lo: GVec2 = 24,
+        scale_exp: i32 = 32,
+        /// height of a pixel in the same units as scale
+        pixel_size: f32 = 36,
+        _padding: IVec2 = 40,
     }
 }
 
-pub const MAIN_UNIFORM_BUFFER_SIZE: usize = 224;
+/// Packs the view (scale and offset) followed by the fractal's own data, `size` is the size of the image in pixels
+pub fn main_uniform_data(scale: DVec2, offset: DVec2, size: [u32; 2], fractal: &Fractal) -> Vec<u8> {
+    let mut buffer = vec![0u8; VIEW_UNIFORM_SIZE + fractal.uniform_size() as usize];
+    let scale_exp = if scale.min_element() < MIN_F32_SCALE { scale.min_element().log2().floor() as i32 } else { 0 };
+    let (scale_hi, scale_lo) = split_f64(scale * 2f64.powi(-scale_exp));
+    let (offset_hi, offset_lo) = split_f64(offset);
+    UniformBuffer::new(&mut buffer[..VIEW_UNIFORM_SIZE]).write(&ViewUniform {
+        scale: scale_hi,
+        offset: offset_hi,
+        scale_lo,
+        offset_lo,
+        scale_exp,
+        // clip space goes from -1 to 1
+        pixel_size: 2. * scale_hi.y / size[1].max(1) as f32,
+        _padding: IVec2::ZERO,
+    }).unwrap();
+    fractal.fill_uniform_buffer(UniformBuffer::new(&mut buffer[VIEW_UNIFORM_SIZE..]));
+    buffer
+}
+
+/// Packs the fractal's coloring data, check [FractalTrait::coloring_size]
+fn coloring_uniform_data(fractal: &Fractal) -> Vec<u8> {
+    let mut buffer = vec![0u8; fractal.coloring_size() as usize];
+    fractal.fill_coloring_buffer(UniformBuffer::new(&mut buffer));
+    buffer
+}
+
+/// Panics if the uniforms declared by the shader don't have the same size and member offsets as the data written by the fractal,
+/// which means that the ShaderTypes don't match the shader's Props and Coloring
+#[cfg(debug_assertions)]
+fn check_uniform_layout(shader_code: Shader, descriptor: &wgpu::ShaderModuleDescriptor, data: &FractalData) {
+    use wgpu::{naga::{front::wgsl, proc::Layouter, ResourceBinding, TypeInner}, ShaderSource};
+    use crate::wgsl::MemberOffsets;
+
+    let ShaderSource::Wgsl(source) = &descriptor.source else { return; };
+    let module = wgsl::parse_str(source).unwrap_or_else(|e| panic!("{shader_code:?} failed to parse: {e}"));
+    let mut layouter = Layouter::default();
+    layouter.update(module.to_ctx()).unwrap();
+    let check = |binding, kind, size: u64, offsets: Vec<u64>| {
+        let Some((_, var)) = module.global_variables.iter()
+            .find(|(_, var)| var.binding == Some(ResourceBinding { group: 0, binding })) else {
+            assert_eq!(size, 0, "{shader_code:?} doesn't have a uniform for the {kind} data");
+            return;
+        };
+        let expected = layouter[var.ty].size as u64;
+        assert_eq!(expected, size, "{shader_code:?} expects {expected} bytes of {kind} data but the fractal writes {size} bytes");
+
+        let TypeInner::Struct { members, .. } = &module.types[var.ty].inner else { panic!("{shader_code:?}'s {kind} uniform isn't a struct") };
+        assert_eq!(members.len(), offsets.len(), "{shader_code:?}'s {kind} uniform has {} members but the fractal writes {}", members.len(), offsets.len());
+        for (member, offset) in members.iter().zip(offsets) {
+            let name = member.name.as_deref().unwrap_or("?");
+            assert_eq!(member.offset as u64, offset, "{shader_code:?} expects {name} at offset {} of the {kind} data but the fractal writes it at {offset}", member.offset);
+        }
+    };
+
+    // the main data starts with the view
+    let main_offsets = ViewUniform::MEMBER_OFFSETS.iter().copied()
+        .chain(data.uniform_offsets.iter().map(|offset| offset + VIEW_UNIFORM_SIZE as u64))
+        .collect();
+    check(0, "main", data.main_data.len() as u64, main_offsets);
+    check(1, "coloring", data.coloring_data.len() as u64, data.coloring_offsets.to_vec());
+}
+
+/// f32 starts to pixelate when a pixel gets close to its precision (around 1e-7 near 1), after that shaders have to switch to double precision
+pub fn needs_double_precision(scale: DVec2, size: [u32; 2]) -> bool {
+    const MIN_SINGLE_PRECISION_PIXEL_SIZE: f64 = 1e-5;
+    let pixel_size = (2. * scale.x / size[0] as f64).min(2. * scale.y / size[1] as f64);
+    pixel_size < MIN_SINGLE_PRECISION_PIXEL_SIZE
+}
+
+/// splits a double into the nearest f32 and the remainder
+fn split_f64(v: DVec2) -> (GVec2, GVec2) {
+    let hi = v.as_vec2();
+    (hi, (v - hi.as_dvec2()).as_vec2())
+}
+
+fn join_f64(hi: GVec2, lo: GVec2) -> DVec2 {
+    hi.as_dvec2() + lo.as_dvec2()
+}
+
+/// Creates a device without a window, falls back to a software adapter if there's no gpu.
+/// `software` skips the gpu, software adapters give the same results everywhere
+pub async fn create_headless_device(software: bool) -> Result<(Adapter, Device, Queue)> {
+    let instance = Instance::new(&InstanceDescriptor::from_env_or_default());
+    let mut options = RequestAdapterOptions {
+        power_preference: PowerPreference::HighPerformance,
+        force_fallback_adapter: software,
+        compatible_surface: None,
+    };
+    let adapter = match instance.request_adapter(&options).await {
+        Some(adapter) => adapter,
+        None => {
+            options.force_fallback_adapter = true;
+            instance.request_adapter(&options).await.ok_or_else(|| anyhow!("No adapter found"))?
+        }
+    };
+
+    // the error isn't Send on the web so it can't be converted with ?
+    let (device, queue) = adapter.request_device(&DeviceDescriptor {
+        label: Some("Headless device"),
+        required_limits: adapter.limits(),
+        ..Default::default()
+    }, None).await.map_err(|e| anyhow!("{e}"))?;
+    Ok((adapter, device, queue))
+}
+
+fn resolve_uniform_data(sample_count: u32) -> [u8; RESOLVE_UNIFORM_BUFFER_SIZE] {
+    let mut buffer = [0u8; RESOLVE_UNIFORM_BUFFER_SIZE];
+    buffer[0..4].copy_from_slice(bytes_of(&(1. / sample_count as f32)));
+    buffer
+}
+
+/// Number of jittered samples taken for each pixel
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Supersampling {
+    X1,
+    #[default]
+    X4,
+    X9,
+    X16,
+}
+
+impl Supersampling {
+    pub const ALL: [Supersampling; 4] = [Self::X1, Self::X4, Self::X9, Self::X16];
+    const MAX_SAMPLES: u32 = 16;
+
+    /// the samples are arranged in a grid_size x grid_size grid
+    pub fn grid_size(self) -> u32 {
+        match self {
+            Self::X1 => 1,
+            Self::X4 => 2,
+            Self::X9 => 3,
+            Self::X16 => 4,
+        }
+    }
+
+    pub fn sample_count(self) -> u32 { self.grid_size() * self.grid_size() }
+
+    /// sub-pixel offsets of the samples, each sample is placed randomly in its own cell of the grid to avoid regular patterns
+    pub(crate) fn jitter(self) -> impl Iterator<Item = Vec2> {
+        let grid_size = self.grid_size();
+        (0..self.sample_count()).map(move |i| {
+            if grid_size == 1 { return Vec2::ZERO; }
+            let cell = Vec2::new((i % grid_size) as f32, (i / grid_size) as f32);
+            // R2 low discrepancy sequence, deterministic so the image doesn't flicker between frames
+            let random = Vec2::new((i as f32 * 0.754_877_7).fract(), (i as f32 * 0.569_840_3).fract()) - Vec2::splat(0.5);
+            (cell + Vec2::splat(0.5) + random * 0.5) / grid_size as f32 - Vec2::splat(0.5)
+        })
+    }
+}
+
+impl Display for Supersampling {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x", self.sample_count())
+    }
+}
 
 pub struct RendererCallback {
     pub shader_code: Shader,
-    pub main_data: [u8; MAIN_UNIFORM_BUFFER_SIZE],
+    pub data: FractalData,
+    pub reference: Option<Arc<ReferenceOrbit>>,
+    pub samples: Supersampling,
+    /// size of the visualizer in physical pixels
+    pub size: [u32; 2],
+    /// amount of work (pixels * cost) that can be done this frame
+    pub budget: f32,
+    /// check [crate::fractal::FractalTrait::cost]
+    pub cost: u32,
+    pub progress: Arc<Mutex<RenderProgress>>,
 }
 
 impl CallbackTrait for RendererCallback {
@@ -108,12 +1496,16 @@ impl CallbackTrait for RendererCallback {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         _screen_descriptor: &eframe::egui_wgpu::ScreenDescriptor,
-        _egui_encoder: &mut wgpu::CommandEncoder,
+        egui_encoder: &mut wgpu::CommandEncoder,
         callback_resources: &mut eframe::egui_wgpu::CallbackResources,
     ) -> Vec<wgpu::CommandBuffer> {
         let render_data = callback_resources.get_mut::<RenderData>().expect("Should be created and inserted when creating the app");
-        render_data.ensure_pipeline_created(device, self.shader_code);
-        queue.write_buffer(&render_data.main_uniform_buffer, 0, &self.main_data);
+        render_data.ensure_pipeline_created(device, self.shader_code, &self.data);
+        render_data.ensure_resolve_pipeline_created(device, render_data.target_format);
+
+        let job = RenderJob { shader_code: self.shader_code, data: self.data.clone(), samples: self.samples, reference: self.reference.clone() };
+        let progress = render_data.render_progressive(device, queue, egui_encoder, job, self.size, self.budget, self.cost);
+        *self.progress.lock().unwrap() = progress;
         vec![]
     }
 
@@ -124,11 +1516,10 @@ impl CallbackTrait for RendererCallback {
         callback_resources: &eframe::egui_wgpu::CallbackResources,
     ) {
         let Some(render_data) = callback_resources.get::<RenderData>() else {return};
+        let Some(progressive) = &render_data.progressive else {return};
 
-        pass.set_pipeline(render_data.pipelines.get(&self.shader_code).unwrap());
-        pass.set_bind_group(0, &render_data.bind_group, &[]);
-
-        // vertex coordinates are hardcoded in the shader so a vertex buffer is not needed
+        pass.set_pipeline(render_data.resolve_pipelines.get(&render_data.target_format).unwrap());
+        pass.set_bind_group(0, &progressive.display.resolve_bind_group, &[]);
         pass.draw(0..6, 0..1);
     }
 }
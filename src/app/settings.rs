@@ -1,8 +1,13 @@
-use crate::app::library::Library;
+use crate::app::export::Export;
+use crate::app::gradient::ColorCycling;
+use crate::app::library::{Library, PaletteLibrary};
+use crate::app::rendering::Supersampling;
 use crate::app::widgets::error_toast;
+use crate::fractal::domain_coloring::DomainColoring;
 use crate::fractal::lyapunov::Lyapunov;
 use crate::fractal::mandelbrot::MandelbrotFamily;
 use crate::fractal::newtons::Newtons;
+use crate::fractal::phoenix::Phoenix;
 use crate::fractal::test_grid::TestGrid;
 use crate::fractal::{Fractal, FractalDiscriminants, FractalTrait};
 use eframe::egui::{self, vec2, Align, Align2, Area, Button, CollapsingHeader, ComboBox, Id, Layout, Modal, RichText, SidePanel, Sides, TextEdit, Ui, UiBuilder, Vec2, Widget, Window};
@@ -14,9 +19,17 @@ use crate::app::library;
 pub struct Settings {
     pub fractal: Fractal,
     pub library: Library,
+    #[serde(default)]
+    pub palettes: PaletteLibrary,
     pub debug_label: bool,
     pub library_window_open: bool,
     pub welcome_window_open: bool,
+    #[serde(default)]
+    pub supersampling: Supersampling,
+    #[serde(default)]
+    pub export: Export,
+    #[serde(default)]
+    pub color_cycling: ColorCycling,
     #[serde(skip)]
     pub hide: bool,
     #[serde(skip)]
@@ -28,9 +41,13 @@ impl Default for Settings {
         Self {
             fractal: Default::default(),
             library: Default::default(),
+            palettes: Default::default(),
             welcome_window_open: true,
             library_window_open: false,
             debug_label: true,
+            supersampling: Default::default(),
+            export: Default::default(),
+            color_cycling: Default::default(),
             hide: false,
             import_modal: (false, String::new())
         }
@@ -130,11 +147,41 @@ impl Settings {
                         ).clicked() {
                         self.fractal = Fractal::Lyapunov(Lyapunov::default());
                     }
+
+                    if ui.selectable_label(
+                            fractal_d == FD::Phoenix,
+                            "Phoenix Fractal",
+                        ).clicked() {
+                        self.fractal = Fractal::Phoenix(Phoenix::default());
+                    }
+
+                    ui.small("Complex functions");
+                    if ui.selectable_label(
+                            fractal_d == FD::DomainColoring,
+                            "Domain Coloring",
+                        ).clicked() {
+                        self.fractal = Fractal::DomainColoring(DomainColoring::default());
+                    }
                     ui.small("More coming soon...")
                 });
         });
 
-        self.fractal.settings_ui(ui);
+        self.fractal.settings_ui(ui, &mut self.palettes);
+
+        ui.separator();
+        self.color_cycling.ui(ui, !self.fractal.gradients().is_empty());
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Anti-aliasing");
+            ComboBox::from_id_salt("supersampling_selector")
+                .selected_text(self.supersampling.to_string())
+                .show_ui(ui, |ui| {
+                    for s in Supersampling::ALL {
+                        ui.selectable_value(&mut self.supersampling, s, s.to_string());
+                    }
+                });
+        });
     }
 
     fn hamburger_menu_ui(&mut self, ui: &mut Ui, toasts: &mut Toasts) {
@@ -1,90 +1,222 @@
 use std::fmt::Write as _;
-use bytemuck::bytes_of;
-use eframe::egui::{vec2, Align, Align2, Button, Layout, Sense, Ui, UiBuilder, Vec2, ViewportCommand, Widget};
+use std::sync::{Arc, Mutex};
+use eframe::egui::{vec2, Align, Align2, Button, Layout, Pos2, ProgressBar, Rect, Sense, Ui, UiBuilder, Vec2, ViewportCommand, Widget};
 use eframe::egui_wgpu::Callback;
-use encase::UniformBuffer;
 use crate::app::settings::Settings;
 use crate::app::widgets::get_transparent_button_fill;
-use crate::fractal::FractalTrait;
+use crate::app::perturbation::{precision_for_scale, prepare_view, try_prepare_view, BigComplex, PendingReference, PreparedView, ReferenceOrbit};
+use crate::fractal::{Fractal, FractalTrait};
+use glam::DVec2;
 
-use super::rendering::{RendererCallback, MAIN_UNIFORM_BUFFER_SIZE};
+use super::rendering::{FractalData, RenderProgress, RendererCallback};
 // todo: reset zoom and offset when changing fractal
 #[derive(Debug, Clone)]
 pub struct Visualizer {
-    scale: f32,
-    offset: Vec2,
+    scale: f64,
+    /// arbitrary precision so perturbation can zoom past what doubles can represent
+    center: BigComplex,
+    /// reused between frames while it stays close to the center
+    reference: Option<Arc<ReferenceOrbit>>,
+    /// replaces `reference` once it's computed
+    pending_reference: Option<PendingReference>,
+    /// shown again while the reference orbit for the current view is being computed
+    last_frame: Option<(PreparedView, FractalData)>,
+    /// false if the device can't render using perturbation
+    supports_storage_buffers: bool,
+    /// size of the visualizer in physical pixels
+    pub viewport_size: Vec2,
+    /// how much work the renderer can do in a frame, adjusted based on the frame time
+    render_budget: f32,
+    render_progress: Arc<Mutex<RenderProgress>>,
+    /// from 0 to 1, how far the colors moved along the gradients, check [crate::app::gradient::ColorCycling]
+    cycle_offset: f32,
+    /// true while one of the fractal's handles is dragged instead of the view, check [FractalTrait::grab_handle]
+    dragging_handle: bool,
 
     pub screenshot_triggered: bool,
 }
 
-const ZOOM_FACTOR: f32 = -0.001;
+const ZOOM_FACTOR: f64 = -0.001;
+const MAX_SCALE: f64 = 1e4;
+// double precision shaders start to pixelate around here
+const MIN_SCALE: f64 = 1e-12;
+// the scale is a double so it can't go much further
+const MIN_PERTURBATION_SCALE: f64 = 1e-300;
+
+const INITIAL_RENDER_BUDGET: f32 = 5e8;
+const RENDER_BUDGET_RANGE: std::ops::RangeInclusive<f32> = 1e6..=1e11;
+// the budget shrinks if frames take longer than the max and grows if they take less than the min
+const MAX_FRAME_TIME: f32 = 1. / 25.;
+const MIN_FRAME_TIME: f32 = 1. / 40.;
 
 impl Default for Visualizer {
     fn default() -> Self {
-        Self { scale: 1., offset: Vec2::ZERO, screenshot_triggered: false, }
+        Self {
+            scale: 1.,
+            center: BigComplex::zero(precision_for_scale(1.)),
+            reference: None,
+            pending_reference: None,
+            last_frame: None,
+            supports_storage_buffers: false,
+            viewport_size: Vec2::ZERO,
+            render_budget: INITIAL_RENDER_BUDGET,
+            render_progress: Default::default(),
+            cycle_offset: 0.,
+            dragging_handle: false,
+            screenshot_triggered: false,
+        }
     }
 }
 
 // todo: completely refactor
 impl Visualizer {
+    pub fn new(supports_storage_buffers: bool) -> Self {
+        Self { supports_storage_buffers, ..Default::default() }
+    }
+
+    /// from 0 to 1, how far the colors moved along the gradients
+    pub fn cycle_offset(&self) -> f32 { self.cycle_offset }
+
+    /// maps the current view to `rect`, which is where the visualizer is drawn
+    fn screen_transform(&self, rect: Rect) -> ScreenTransform {
+        let aspect_ratio_correction = DVec2::new(rect.aspect_ratio() as f64, 1.);
+        ScreenTransform { rect, center: self.center.to_dvec2(), scale: self.scale * aspect_ratio_correction }
+    }
+
+    /// picks how to render the current view of the fractal for an image of the given size in pixels
+    pub fn prepare_view(&self, fractal: &Fractal, size: [u32; 2]) -> PreparedView {
+        let scale = self.scale * DVec2::new(size[0] as f64 / size[1] as f64, 1.);
+        prepare_view(fractal, &self.center, scale, size, self.supports_storage_buffers, self.reference.as_ref())
+    }
+
+    fn move_center(&mut self, delta: DVec2) {
+        self.center = &self.center + &BigComplex::from_dvec2(delta, self.center.frac_bits());
+    }
+
     pub fn ui(&mut self, settings: &mut Settings, ui: &mut Ui) {
         let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
 
-        let aspect_ratio_correction = Vec2::new(painter.clip_rect().aspect_ratio(), 1.);
+        let aspect_ratio_correction = DVec2::new(painter.clip_rect().aspect_ratio() as f64, 1.);
+        self.viewport_size = painter.clip_rect().size() * ui.ctx().pixels_per_point();
 
-        // changing zoom and offset
-        let mut cursor_shader_space: Option<Vec2> = None;
-        self.offset += response.drag_delta() / painter.clip_rect().size() * vec2(-1.,1.) * 2.0;
+        // fractals can have handles that are dragged instead of the view
+        if response.drag_started() {
+            let transform = self.screen_transform(painter.clip_rect());
+            self.dragging_handle = ui.input(|input| input.pointer.press_origin())
+                .is_some_and(|origin| settings.fractal.grab_handle(&transform, origin));
+        }
+        if !response.dragged() {
+            self.dragging_handle = false;
+        }
+
+        // changing zoom and center
+        let drag = response.drag_delta() / painter.clip_rect().size() * vec2(-1.,1.) * 2.0;
+        if drag != Vec2::ZERO && !self.dragging_handle {
+            self.move_center(DVec2::new(drag.x as f64, drag.y as f64) * self.scale * aspect_ratio_correction);
+        }
+        let min_scale = if self.supports_storage_buffers && settings.fractal.perturbation().is_some() { MIN_PERTURBATION_SCALE } else { MIN_SCALE };
         if let Some(hover_pos) = response.hover_pos() {
             ui.input(|input| {
                 // from -1 to 1
-                let mut cursor_clip_space = 2. * (hover_pos-painter.clip_rect().min) / painter.clip_rect().size() - vec2(1., 1.);
-                cursor_clip_space.y *= -1.;
+                let cursor_clip_space = 2. * (hover_pos-painter.clip_rect().min) / painter.clip_rect().size() - vec2(1., 1.);
+                let cursor_clip_space = DVec2::new(cursor_clip_space.x as f64, -cursor_clip_space.y as f64);
 
                 let zoom = input
                     .multi_touch()
-                    .map(|mt| 1. / mt.zoom_delta)
+                    .map(|mt| 1. / mt.zoom_delta as f64)
                     .unwrap_or_else(|| {
-                        1. + input.smooth_scroll_delta.y * ZOOM_FACTOR
+                        1. + input.smooth_scroll_delta.y as f64 * ZOOM_FACTOR
                     });
 
                 let mut new_scale = self.scale * zoom;
-                new_scale = new_scale.clamp(0.0000000001, 10000.); // prevent zoom from becoming 0 or inf
-                let delta_scale = self.scale / new_scale;
-                // rescale to make zooming centered on the screen
-                self.offset *= delta_scale;
-                // calculate an offset to the offset that will center the zooming on the cursor
-                self.offset += cursor_clip_space * (delta_scale-1.);
+                new_scale = new_scale.clamp(min_scale, MAX_SCALE); // prevent zoom from becoming 0 or inf
+                if new_scale != self.scale {
+                    // move the center so the point under the cursor stays in place
+                    self.move_center(cursor_clip_space * (self.scale - new_scale) * aspect_ratio_correction);
+                    self.scale = new_scale;
+                    self.center = self.center.with_precision(precision_for_scale(new_scale));
+                }
+            });
+        }
+
+        let transform = self.screen_transform(painter.clip_rect());
+        let cursor_shader_space = response.hover_pos().map(|pos| transform.to_shader(pos));
+        if self.dragging_handle && let Some(pos) = response.interact_pointer_pos() {
+            settings.fractal.drag_handle(transform.to_shader(pos));
+        }
 
-                self.scale = new_scale;
+        // the progress is from the previous frame, if the render isn't done we keep on repainting
+        let progress = *self.render_progress.lock().unwrap();
+        if !progress.is_done() {
+            let frame_time = ui.input(|input| input.stable_dt);
+            if frame_time > MAX_FRAME_TIME {
+                self.render_budget *= 0.7;
+            } else if frame_time < MIN_FRAME_TIME {
+                self.render_budget *= 1.2;
+            }
+            self.render_budget = self.render_budget.clamp(*RENDER_BUDGET_RANGE.start(), *RENDER_BUDGET_RANGE.end());
+            ui.ctx().request_repaint();
+        }
 
-                cursor_shader_space = Some((cursor_clip_space + self.offset) * self.scale * aspect_ratio_correction);
-            });
+        // color cycling, only fractals with gradients can be cycled
+        let cycling = settings.color_cycling;
+        if !cycling.enabled || settings.fractal.gradients().is_empty() {
+            self.cycle_offset = 0.;
+        } else if cycling.is_animating() {
+            self.cycle_offset = cycling.advance(self.cycle_offset, ui.input(|input| input.stable_dt));
+            ui.ctx().request_repaint();
         }
 
-        // preparing data for writing to the uniform buffer
-        let mut buffer = [0u8; MAIN_UNIFORM_BUFFER_SIZE];
-        buffer[0.. 8].copy_from_slice(bytes_of(&(self.scale * aspect_ratio_correction)));
-        buffer[8..16].copy_from_slice(bytes_of(&self.offset));
-        let settings_buffer = UniformBuffer::new(&mut buffer[16..]);
-        settings.fractal.fill_uniform_buffer(settings_buffer);
+        // a finished orbit replaces the cached one even if the view moved on, it might still be close enough
+        if let Some(orbit) = self.pending_reference.as_ref().and_then(|pending| pending.take()) {
+            self.reference = Some(Arc::new(orbit));
+            self.pending_reference = None;
+        }
 
         // rendering
-        let callback = RendererCallback {
-            shader_code: settings.fractal.get_shader(),
-            main_data: buffer,
-        };
-
-        painter.add(Callback::new_paint_callback(painter.clip_rect(), callback));
+        let size = [self.viewport_size.x.round().max(1.) as u32, self.viewport_size.y.round().max(1.) as u32];
+        let scale = self.scale * DVec2::new(size[0] as f64 / size[1] as f64, 1.);
+        let view = try_prepare_view(&settings.fractal, &self.center, scale, size, self.supports_storage_buffers, self.reference.as_ref(), |perturbation, center| {
+            if self.pending_reference.is_none() {
+                let ctx = ui.ctx().clone();
+                self.pending_reference = Some(PendingReference::spawn(perturbation, center, move || ctx.request_repaint()));
+            }
+            None
+        });
+        if let Some(view) = view {
+            self.reference.clone_from(&view.reference);
+            let data = FractalData::new(view.scale, view.offset, size, &settings.fractal, self.cycle_offset);
+            self.last_frame = Some((view, data));
+        }
+        // the last image stays until the reference orbit is ready
+        if let Some((view, data)) = &self.last_frame {
+            let callback = RendererCallback {
+                shader_code: view.shader,
+                data: data.clone(),
+                reference: view.reference.clone(),
+                samples: settings.supersampling,
+                size,
+                budget: self.render_budget,
+                cost: settings.fractal.cost(),
+                progress: self.render_progress.clone(),
+            };
+            painter.add(Callback::new_paint_callback(painter.clip_rect(), callback));
+        }
 
         // if a screenshot is being taken don't draw anything extra
         if self.screenshot_triggered { return; }
 
         // fractals can draw extra stuff
-        settings.fractal.draw_extra(&painter, cursor_shader_space);
+        settings.fractal.draw_extra(&painter, &transform, cursor_shader_space);
 
         if settings.debug_label {
-            let mut text = format!("scale:{}, offset:{:?}", self.scale, self.offset);
+            let mut text = format!("scale:{}, center:{}", self.scale, self.center.to_dvec2());
+            if let Some((view, _)) = &self.last_frame {
+                write!(text, ", shader:{:?}", view.shader).unwrap();
+                if let Some(reference) = &view.reference {
+                    write!(text, ", reference orbit:{}", reference.points.len()).unwrap();
+                }
+            }
             if let Some(cursor) = cursor_shader_space {
                 write!(text, ", cursor:{cursor}").unwrap();
             }
@@ -100,14 +232,72 @@ impl Visualizer {
             ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
                 if Button::new("🏠").fill(get_transparent_button_fill(ui.visuals(), 0.7)).ui(ui).clicked() {
                     self.scale = 1.;
-                    self.offset = Vec2::ZERO;
+                    self.center = BigComplex::zero(precision_for_scale(1.));
                 }
 
                 if Button::new("⛶").fill(get_transparent_button_fill(ui.visuals(), 0.7)).ui(ui).clicked() {
                     ui.ctx().send_viewport_cmd(ViewportCommand::Screenshot(Default::default()));
                     self.screenshot_triggered = true;
                 }
+
+                if Button::new("💾").fill(get_transparent_button_fill(ui.visuals(), 0.7)).ui(ui).on_hover_text("Export image").clicked() {
+                    settings.export.open = true;
+                }
+
+                if self.pending_reference.is_some() {
+                    ui.spinner().on_hover_text("Computing the reference orbit, the last image is shown until it's done");
+                } else if !progress.is_done() {
+                    ProgressBar::new(progress.fraction()).desired_width(100.).show_percentage().ui(ui)
+                        .on_hover_text("Rendering, the last complete image is shown until a pass finishes");
+                }
             });
         });
     }
 }
+
+/// Converts between the coordinates of the fractal (shader space) and the screen, y goes up in the fractal and down on the screen
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenTransform {
+    rect: Rect,
+    center: DVec2,
+    /// half of the size of the view in shader space
+    scale: DVec2,
+}
+
+impl ScreenTransform {
+    pub fn to_shader(&self, pos: Pos2) -> Vec2 {
+        // from -1 to 1
+        let clip = 2. * (pos - self.rect.min) / self.rect.size() - vec2(1., 1.);
+        let pos = self.center + DVec2::new(clip.x as f64, -clip.y as f64) * self.scale;
+        Vec2::new(pos.x as f32, pos.y as f32)
+    }
+
+    pub fn to_screen(&self, pos: Vec2) -> Pos2 {
+        let clip = (DVec2::new(pos.x as f64, pos.y as f64) - self.center) / self.scale;
+        let clip = vec2(clip.x as f32, -clip.y as f32);
+        self.rect.min + (clip + vec2(1., 1.)) / 2. * self.rect.size()
+    }
+
+    /// length on the screen of a unit of shader space, the same on both axes
+    pub fn points_per_unit(&self) -> f32 {
+        self.rect.height() / (2. * self.scale.y) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui::pos2;
+    use super::*;
+
+    #[test]
+    fn screen_transform_round_trips() {
+        let transform = ScreenTransform { rect: Rect::from_min_size(pos2(10., 20.), vec2(400., 200.)), center: DVec2::new(-0.5, 0.25), scale: DVec2::new(2., 1.) };
+        // the center of the view is the center of the rect and y goes up
+        assert_eq!(transform.to_screen(Vec2::new(-0.5, 0.25)), pos2(210., 120.));
+        assert_eq!(transform.to_screen(Vec2::new(-0.5, 1.25)), pos2(210., 20.));
+        assert_eq!(transform.points_per_unit(), 100.);
+        for pos in [pos2(10., 20.), pos2(123., 45.), pos2(410., 220.)] {
+            assert!(transform.to_screen(transform.to_shader(pos)).distance(pos) < 1e-3);
+        }
+    }
+}
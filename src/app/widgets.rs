@@ -1,10 +1,14 @@
 use std::fmt::Display;
 use std::ops::RangeInclusive;
-use eframe::egui::{CollapsingHeader, ComboBox, CornerRadius, Frame, Rect, Sense, UiBuilder, Vec2};
+use eframe::egui::{pos2, vec2, CollapsingHeader, ComboBox, CornerRadius, Frame, Mesh, Painter, Rect, Sense, Shape, TextEdit, UiBuilder, Vec2};
 use eframe::egui::{color_picker::{self, Alpha}, Button, Color32, DragValue, Response, Ui, Visuals, Widget, WidgetText};
 use eframe::epaint::RectShape;
 use egui_notify::{Toast, ToastLevel};
 use num_complex::Complex32;
+use crate::app::files::{pick_file, save_file, PickedFile};
+use crate::app::gradient::{Gradient, GradientStop, Interpolation};
+use crate::app::library::PaletteLibrary;
+use crate::app::palette_files::{import_palette, PaletteFormat, PALETTE_FILTER};
 
 pub fn c32_ui(ui: &mut Ui, v: &mut Complex32, speed: Option<f32>, range: Option<RangeInclusive<f32>>) {
     ui.horizontal(|ui| {
@@ -54,61 +58,286 @@ pub fn get_transparent_button_fill(visuals: &Visuals, gamma_mul: f32) -> Color32
     visuals.widgets.noninteractive.weak_bg_fill.gamma_multiply(gamma_mul)
 }
 
-pub fn palette_editor<const N: usize>(ui: &mut Ui, colors: &mut[Color32; N], label: impl Into<WidgetText>, dropdown_palettes: &[[Color32;N]]) {
+pub fn palette_editor<const N: usize>(ui: &mut Ui, colors: &mut [Color32], label: impl Into<WidgetText>, dropdown_palettes: &[[Color32;N]], palettes: &mut PaletteLibrary) {
     CollapsingHeader::new(label).show_unindented(ui, |ui| {
-        ui.horizontal(|ui| {
+        ui.horizontal_wrapped(|ui| {
             ui.spacing_mut().item_spacing = Vec2::ZERO;
             for c in colors.iter_mut() {
                 color_picker::color_edit_button_srgba(ui, c, Alpha::Opaque);
             }
         });
-        ComboBox::new("dropdown", "").selected_text("Pick a palette").show_ui(ui, |ui| {
-            for palette in dropdown_palettes.iter() {
-                let palette = &palette[0..colors.len()];
-
-                // adapted from one of the examples
-                let resp = ui.scope_builder(
-                    UiBuilder::new().sense(Sense::click()),
-                    |ui| {
-                        let resp = ui.response();
-                        let visuals = ui.style().interact(&resp);
-
-                        Frame::canvas(ui.style())
-                            .fill(visuals.bg_fill.gamma_multiply(0.3))
-                            .stroke(visuals.bg_stroke)
-                            .inner_margin(ui.spacing().menu_margin)
-                            .show(ui, |ui| {
-                                const RECT_SIZE: Vec2 = Vec2::new(30., 15.);
-                                let (_, rect) = ui.allocate_space(Vec2::new(RECT_SIZE.x * palette.len() as f32, RECT_SIZE.y));
-                                let painter = ui.painter();
-                                for (i, c) in palette.iter().enumerate() {
-                                    let shape_rect = Rect::from_min_size(rect.left_top() + Vec2::new(RECT_SIZE.x * i as f32, 0.), RECT_SIZE);
-
-                                    let mut corner_radius = CornerRadius::ZERO;
-                                    if i == 0 {
-                                        corner_radius.nw = 5;
-                                        corner_radius.sw = 5;
-                                    }
-                                    if i == palette.len() - 1 {
-                                        corner_radius.ne = 5;
-                                        corner_radius.se = 5;
-                                    }
-
-                                    let shape = RectShape::filled(shape_rect, corner_radius, *c);
-                                    painter.add(shape);
-                                }
-                            });
-                    }
-                );
+        const RECT_SIZE: Vec2 = Vec2::new(30., 15.);
+        let picked = palette_dropdown(ui, palettes, dropdown_palettes.len(), |_| Vec2::new(RECT_SIZE.x * N as f32, RECT_SIZE.y), |i, painter, rect| {
+            let palette = &dropdown_palettes[i];
+            for (i, c) in palette.iter().enumerate() {
+                let shape_rect = Rect::from_min_size(rect.left_top() + Vec2::new(RECT_SIZE.x * i as f32, 0.), RECT_SIZE);
 
-                if resp.response.clicked() {
-                    colors.copy_from_slice(palette);
+                let mut corner_radius = CornerRadius::ZERO;
+                if i == 0 {
+                    corner_radius.nw = 5;
+                    corner_radius.sw = 5;
+                }
+                if i == palette.len() - 1 {
+                    corner_radius.ne = 5;
+                    corner_radius.se = 5;
                 }
+
+                let shape = RectShape::filled(shape_rect, corner_radius, *c);
+                painter.add(shape);
             }
         });
+        match picked {
+            Some(PalettePick::BuiltIn(i)) => apply_palette(&dropdown_palettes[i], colors),
+            Some(PalettePick::User(gradient)) => sample_evenly(&gradient, colors),
+            None => {}
+        }
+        save_palette_ui(ui, palettes, || Gradient::new(colors));
+        if let Some(imported) = palette_files_ui(ui, || Gradient::new(colors)) {
+            sample_evenly(&imported, colors);
+        }
     });
 }
 
+/// copies the palette's colors, a longer palette is cut and a shorter one is stretched like a gradient
+fn apply_palette(palette: &[Color32], colors: &mut [Color32]) {
+    if palette.len() >= colors.len() {
+        colors.copy_from_slice(&palette[..colors.len()]);
+    } else {
+        sample_evenly(&Gradient::new(palette), colors);
+    }
+}
+
+/// fills a palette with a fixed number of colors from a gradient
+fn sample_evenly(gradient: &Gradient, colors: &mut [Color32]) {
+    let last = (colors.len() - 1).max(1) as f32;
+    for (i, c) in colors.iter_mut().enumerate() {
+        *c = gradient.sample(i as f32 / last);
+    }
+}
+
+/// Editor for gradients with any number of stops.
+/// Double clicking the preview adds a stop, the handles under it can be dragged and clicking one selects it
+pub fn gradient_editor(ui: &mut Ui, gradient: &mut Gradient, label: impl Into<WidgetText>, dropdown_gradients: &[Gradient], palettes: &mut PaletteLibrary) {
+    CollapsingHeader::new(label).show_unindented(ui, |ui| {
+        // share codes can't have empty gradients but the stops are public
+        if gradient.stops.is_empty() {
+            gradient.stops = Gradient::grayscale().stops;
+        }
+        let id = ui.id().with("gradient_stop");
+        let mut selected = ui.data(|data| data.get_temp::<usize>(id)).unwrap_or(0).min(gradient.stops.len() - 1);
+
+        let width = ui.available_width().min(250.);
+        let (bar_rect, bar_response) = ui.allocate_exact_size(vec2(width, 20.), Sense::click());
+        paint_gradient(ui.painter(), bar_rect, gradient);
+        if bar_response.double_clicked() && let Some(pointer) = bar_response.interact_pointer_pos() {
+            let position = ((pointer.x - bar_rect.left()) / width).clamp(0., 1.);
+            gradient.stops.push(GradientStop { position, color: gradient.sample(position) });
+            selected = gradient.stops.len() - 1;
+        }
+        bar_response.on_hover_text("Double click to add a color");
+
+        // triangles pointing at the stops
+        let (handles_rect, _) = ui.allocate_exact_size(vec2(width, 12.), Sense::hover());
+        for (i, stop) in gradient.stops.iter_mut().enumerate() {
+            let x = handles_rect.left() + stop.position * width;
+            let handle = Rect::from_center_size(pos2(x, handles_rect.center().y), handles_rect.size() * vec2(0., 1.) + vec2(10., 0.));
+            let response = ui.interact(handle, id.with(i), Sense::click_and_drag());
+            if response.clicked() || response.drag_started() {
+                selected = i;
+            }
+            if response.dragged() {
+                stop.position = (stop.position + response.drag_delta().x / width).clamp(0., 1.);
+            }
+            let stroke = if i == selected { ui.visuals().selection.stroke } else { ui.visuals().widgets.inactive.fg_stroke };
+            ui.painter().add(Shape::convex_polygon(vec![pos2(x, handle.top()), handle.right_bottom(), handle.left_bottom()], stop.color, stroke));
+        }
+
+        ui.horizontal(|ui| {
+            let stop = &mut gradient.stops[selected];
+            color_picker::color_edit_button_srgba(ui, &mut stop.color, Alpha::Opaque);
+            DragValue::new(&mut stop.position).speed(0.005).range(0.0..=1.).ui(ui);
+            if ui.add_enabled(gradient.stops.len() > 2, Button::new("🗑").small()).on_hover_text("Remove the color").clicked() {
+                gradient.stops.remove(selected);
+                selected = selected.min(gradient.stops.len() - 1);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Interpolation");
+            let arr = Interpolation::ALL;
+            let mut index = arr.iter().position(|i| *i == gradient.interpolation).unwrap();
+            ComboBox::from_id_salt("interpolation_selector")
+                .selected_text(gradient.interpolation)
+                .show_index(ui, &mut index, arr.len(), |i| arr[i]);
+            gradient.interpolation = arr[index];
+        });
+
+        let picked = palette_dropdown(ui, palettes, dropdown_gradients.len(), |_| Vec2::new(150., 15.), |i, painter, rect| {
+            paint_gradient(painter, rect, &dropdown_gradients[i]);
+        });
+        match picked {
+            Some(PalettePick::BuiltIn(i)) => gradient.clone_from(&dropdown_gradients[i]),
+            Some(PalettePick::User(picked)) => *gradient = picked,
+            None => {}
+        }
+        save_palette_ui(ui, palettes, || gradient.clone());
+        if let Some(imported) = palette_files_ui(ui, || gradient.clone()) {
+            *gradient = imported;
+            selected = 0;
+        }
+        ui.data_mut(|data| data.insert_temp(id, selected));
+    });
+}
+
+/// Lets the user name the palette and save it to their library
+fn save_palette_ui(ui: &mut Ui, palettes: &mut PaletteLibrary, palette: impl Fn() -> Gradient) {
+    let id = ui.id().with("palette_name");
+    let mut name = ui.data(|data| data.get_temp::<String>(id)).unwrap_or_default();
+    ui.horizontal(|ui| {
+        TextEdit::singleline(&mut name)
+            .hint_text("Name")
+            .desired_width(130.)
+            .show(ui);
+        if ui.add_enabled(!name.is_empty(), Button::new("Save palette")).clicked() {
+            palettes.user_palettes.push((std::mem::take(&mut name), palette()));
+        }
+    });
+    ui.data_mut(|data| data.insert_temp(id, name));
+}
+
+/// Buttons to import and export the palette files of other fractal programs, errors are shown under them.
+/// Returns the imported palette, on the web it arrives a few frames after the file was picked
+fn palette_files_ui(ui: &mut Ui, palette: impl Fn() -> Gradient) -> Option<Gradient> {
+    let id = ui.id().with("palette_files");
+    let picked = ui.data_mut(|data| data.get_temp_mut_or_default::<PickedFile>(id).clone());
+    let mut error = None;
+
+    ui.horizontal(|ui| {
+        if ui.button("Import").on_hover_text("Fractint .map, Ultra Fractal .ugr or GIMP .ggr").clicked() {
+            pick_file(ui.ctx(), &PALETTE_FILTER, picked.clone());
+        }
+        ui.menu_button("Export", |ui| {
+            for format in PaletteFormat::ALL {
+                if ui.button(format.name()).clicked() {
+                    ui.close_menu();
+                    let saved = format.write(&palette(), "Fractal Studio")
+                        .and_then(|text| save_file(text.as_bytes(), &format!("palette.{}", format.extension()), &format.filter()));
+                    if let Err(e) = saved {
+                        error = Some(format!("Failed to export the palette: {e}"));
+                    }
+                }
+            }
+        });
+    });
+
+    let imported = match picked.take() {
+        Some(Ok((name, bytes))) => import_palette(&name, &bytes).map_err(|e| error = Some(format!("{e:#}"))).ok(),
+        Some(Err(e)) => {
+            error = Some(format!("{e:#}"));
+            None
+        }
+        None => None,
+    };
+
+    let error_id = id.with("error");
+    if imported.is_some() {
+        ui.data_mut(|data| data.remove::<String>(error_id));
+    }
+    if let Some(error) = error {
+        ui.data_mut(|data| data.insert_temp(error_id, error));
+    }
+    if let Some(error) = ui.data(|data| data.get_temp::<String>(error_id)) {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    imported
+}
+
+/// draws the gradient from the left to the right of the rect
+pub fn paint_gradient(painter: &Painter, rect: Rect, gradient: &Gradient) {
+    const SEGMENTS: usize = 64;
+    let mut mesh = Mesh::default();
+    for i in 0..=SEGMENTS {
+        let t = i as f32 / SEGMENTS as f32;
+        let color = gradient.sample(t);
+        let x = rect.left() + t * rect.width();
+        mesh.colored_vertex(pos2(x, rect.top()), color);
+        mesh.colored_vertex(pos2(x, rect.bottom()), color);
+        if i > 0 {
+            let v = (2 * i) as u32;
+            mesh.add_triangle(v - 2, v - 1, v);
+            mesh.add_triangle(v - 1, v, v + 1);
+        }
+    }
+    painter.add(Shape::mesh(mesh));
+}
+
+/// palette clicked in [palette_dropdown]
+enum PalettePick {
+    /// index of the palette that comes with the fractal
+    BuiltIn(usize),
+    User(Gradient),
+}
+
+/// Dropdown with a preview of every palette, `paint` draws the preview of the i-th built-in palette in a rect of `size(i)`.
+/// The user's palettes are listed after the built-in ones and can be deleted from there
+fn palette_dropdown(ui: &mut Ui, palettes: &mut PaletteLibrary, count: usize, size: impl Fn(usize) -> Vec2, paint: impl Fn(usize, &Painter, Rect)) -> Option<PalettePick> {
+    let mut picked = None;
+    ComboBox::new("dropdown", "").selected_text("Pick a palette").show_ui(ui, |ui| {
+        for index in 0..count {
+            let clicked = palette_button(ui, |ui| {
+                let (_, rect) = ui.allocate_space(size(index));
+                paint(index, ui.painter(), rect);
+            });
+            if clicked {
+                picked = Some(PalettePick::BuiltIn(index));
+            }
+        }
+
+        if palettes.user_palettes.is_empty() { return; }
+        ui.separator();
+        ui.small("Your palettes");
+        let mut delete = None;
+        for (i, (name, gradient)) in palettes.user_palettes.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let clicked = palette_button(ui, |ui| {
+                    ui.label(name);
+                    let (_, rect) = ui.allocate_space(vec2(150., 15.));
+                    paint_gradient(ui.painter(), rect, gradient);
+                });
+                if clicked {
+                    picked = Some(PalettePick::User(gradient.clone()));
+                }
+                if ui.small_button("x").on_hover_text("Delete the palette").clicked() {
+                    delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = delete {
+            palettes.user_palettes.remove(i);
+        }
+    });
+    picked
+}
+
+/// a framed area that can be clicked, returns true if it was
+fn palette_button(ui: &mut Ui, add_contents: impl FnOnce(&mut Ui)) -> bool {
+    // adapted from one of the examples
+    let resp = ui.scope_builder(
+        UiBuilder::new().sense(Sense::click()),
+        |ui| {
+            let resp = ui.response();
+            let visuals = ui.style().interact(&resp);
+
+            Frame::canvas(ui.style())
+                .fill(visuals.bg_fill.gamma_multiply(0.3))
+                .stroke(visuals.bg_stroke)
+                .inner_margin(ui.spacing().menu_margin)
+                .show(ui, add_contents);
+        }
+    );
+    resp.response.clicked()
+}
+
 #[macro_export]
 macro_rules! __count {
     () => (0usize);
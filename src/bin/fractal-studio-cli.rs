@@ -0,0 +1,155 @@
+// Renders fractals to png files without opening a window
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> anyhow::Result<()> {
+    native::main()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    panic!("The cli isn't supported on the web");
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::path::{Path, PathBuf};
+    use anyhow::{anyhow, bail, Context, Result};
+    use clap::Parser;
+    use glam::DVec2;
+    use log::{info, warn};
+    use wgpu::{Device, Queue, TextureFormat};
+    use fractal_studio::app::cpu_rendering::render_cpu;
+    use fractal_studio::app::export::{encode_png, ExportJob, MAX_TILE_SIZE};
+    use fractal_studio::app::perturbation::{precision_for_scale, prepare_view, BigComplex, BigFixed};
+    use fractal_studio::app::rendering::{create_headless_device, RenderData, Supersampling};
+    use fractal_studio::fractal::Fractal;
+
+    #[derive(Debug, Parser)]
+    #[command(version, about = "Renders fractals to png files without opening a window")]
+    struct Args {
+        /// Share code or link of the fractal
+        #[arg(required_unless_present = "batch", conflicts_with = "batch")]
+        fractal: Option<String>,
+        /// File with a share code or link on every line, optionally followed by the output file name.
+        /// Empty lines and lines starting with # are ignored
+        #[arg(long)]
+        batch: Option<PathBuf>,
+        /// Output png, or the output directory when using --batch
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Center of the view as re,im, decimals are parsed exactly so deep zooms work
+        #[arg(long, default_value = "0,0", allow_hyphen_values = true)]
+        center: String,
+        /// Half of the height of the view
+        #[arg(long, default_value_t = 1.)]
+        scale: f64,
+        #[arg(long, default_value_t = 1920)]
+        width: u32,
+        #[arg(long, default_value_t = 1080)]
+        height: u32,
+        /// Samples per pixel: 1, 4, 9 or 16
+        #[arg(long, default_value_t = 16)]
+        samples: u32,
+        /// Render on the cpu, also used if there's no adapter at all. Deep zooms aren't supported
+        #[arg(long)]
+        cpu: bool,
+        /// Moves the colors along the gradients like color cycling does, from 0 to 1
+        #[arg(long, default_value_t = 0.)]
+        cycle_offset: f32,
+    }
+
+    pub fn main() -> Result<()> {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+        let args = Args::parse();
+
+        if args.width == 0 || args.height == 0 { bail!("The image size can't be 0"); }
+        if !(args.scale > 0. && args.scale.is_finite()) { bail!("The scale must be a positive number"); }
+        if !(0. ..1.).contains(&args.cycle_offset) { bail!("The cycle offset must be between 0 and 1"); }
+        let samples = Supersampling::ALL.into_iter()
+            .find(|s| s.sample_count() == args.samples)
+            .ok_or_else(|| anyhow!("Unsupported number of samples: {}", args.samples))?;
+        let center = parse_center(&args.center, precision_for_scale(args.scale))?;
+
+        // (fractal, output path)
+        let jobs = match &args.batch {
+            None => vec![(args.fractal.clone().unwrap(), args.output.clone())],
+            Some(batch) => read_batch(batch, &args.output)?,
+        };
+
+        let mut gpu = None;
+        if !args.cpu {
+            match pollster::block_on(create_headless_device(false)) {
+                Ok((adapter, device, queue)) => {
+                    let info = adapter.get_info();
+                    info!("Using {} ({:?})", info.name, info.backend);
+                    let render_data = RenderData::new(&device, &adapter, TextureFormat::Rgba8Unorm);
+                    gpu = Some((device, queue, render_data));
+                }
+                Err(e) => warn!("Rendering on the cpu: {e:#}"),
+            }
+        }
+
+        let mut failed = 0;
+        for (code, output) in jobs {
+            let result = Fractal::from_link(&code).and_then(|fractal| {
+                let png = match &mut gpu {
+                    Some((device, queue, render_data)) => render(device, queue, render_data, fractal, &center, &args, samples)?,
+                    None => render_on_cpu(&fractal, &center, &args, samples)?,
+                };
+                std::fs::write(&output, png).with_context(|| format!("Failed to write {}", output.display()))
+            });
+            match result {
+                Ok(()) => info!("Rendered {}", output.display()),
+                Err(e) => {
+                    log::error!("Failed to render {}: {e:#}", output.display());
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 { bail!("{failed} fractal(s) failed to render"); }
+        Ok(())
+    }
+
+    fn view_scale(args: &Args) -> DVec2 {
+        DVec2::new(args.scale * args.width as f64 / args.height as f64, args.scale)
+    }
+
+    fn render(device: &Device, queue: &Queue, render_data: &mut RenderData, fractal: Fractal, center: &BigComplex, args: &Args, samples: Supersampling) -> Result<Vec<u8>> {
+        let size = [args.width, args.height];
+        let view = prepare_view(&fractal, center, view_scale(args), size, render_data.supports_storage_buffers(), None);
+
+        let max_tile_size = device.limits().max_texture_dimension_2d.min(MAX_TILE_SIZE);
+        let mut job = ExportJob::new(args.width, args.height, max_tile_size, view, fractal, samples, args.cycle_offset);
+        job.finish_blocking(device, queue, render_data)?;
+        job.into_png()
+    }
+
+    fn render_on_cpu(fractal: &Fractal, center: &BigComplex, args: &Args, samples: Supersampling) -> Result<Vec<u8>> {
+        let scale = view_scale(args);
+        let pixels = render_cpu(fractal, scale, center.to_dvec2() / scale, [args.width, args.height], samples, args.cycle_offset);
+        encode_png(pixels, args.width, args.height)
+    }
+
+    fn parse_center(center: &str, frac_bits: u32) -> Result<BigComplex> {
+        let (re, im) = center.split_once(',').ok_or_else(|| anyhow!("The center must be formatted as re,im"))?;
+        Ok(BigComplex { re: BigFixed::parse(re, frac_bits)?, im: BigFixed::parse(im, frac_bits)? })
+    }
+
+    fn read_batch(batch: &Path, output_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+        let contents = std::fs::read_to_string(batch).with_context(|| format!("Failed to read {}", batch.display()))?;
+        std::fs::create_dir_all(output_dir)?;
+
+        Ok(contents.lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                let mut parts = line.split_whitespace();
+                let code = parts.next().unwrap().to_string();
+                // named after the line number by default
+                let file_name = parts.next().map_or_else(|| format!("{}.png", i + 1), str::to_string);
+                (code, output_dir.join(file_name))
+            })
+            .collect())
+    }
+}
@@ -2,18 +2,27 @@ pub mod test_grid;
 pub mod mandelbrot;
 pub mod newtons;
 pub mod lyapunov;
+pub mod phoenix;
+pub mod domain_coloring;
 
-use eframe::egui::{Context, Id, Painter, Ui, Vec2};
+use eframe::egui::{Context, Id, Painter, Pos2, Ui, Vec2};
 use strum::{EnumDiscriminants, EnumMessage};
 use anyhow::{anyhow, bail, Result};
 use base64::prelude::*;
 use encase::UniformBuffer;
 use enum_dispatch::enum_dispatch;
+use glam::{Vec2 as GVec2, Vec4 as GVec4};
 use url::Url;
 use test_grid::TestGrid;
 use mandelbrot::MandelbrotFamily;
 use newtons::Newtons;
 use lyapunov::Lyapunov;
+use phoenix::Phoenix;
+use domain_coloring::DomainColoring;
+use crate::app::gradient::Gradient;
+use crate::app::library::PaletteLibrary;
+use crate::app::perturbation::Perturbation;
+use crate::app::visualizer::ScreenTransform;
 use crate::wgsl::Shader;
 
 #[enum_dispatch]
@@ -24,16 +33,51 @@ pub enum Fractal {
     MandelbrotFamily,
     Newtons,
     Lyapunov,
+    Phoenix,
+    // --- Complex functions ---
+    DomainColoring,
 }
 
 #[enum_dispatch(Fractal)]
 pub trait FractalTrait {
     fn label(&mut self) -> &'static str;
-    fn settings_ui(&mut self, _ui: &mut Ui) { }
+    /// `palettes` are the user's palettes, offered by the palette editors
+    fn settings_ui(&mut self, _ui: &mut Ui, _palettes: &mut PaletteLibrary) { }
     fn get_shader(&self) -> Shader;
+    /// size of the data written by fill_uniform_buffer, usually `min_size()` of the fractal's ShaderType.
+    /// Together with the view it has to match the shader's Props, which is checked in debug builds
+    fn uniform_size(&self) -> u64 { 0 }
     fn fill_uniform_buffer(&self, _buffer: UniformBuffer<&mut [u8]>) {}
+    /// size of the data written by fill_coloring_buffer, it's bound separately from the main data and is only used to color the fractal,
+    /// so changing it doesn't compute the fractal again when the shader is rendered in two passes
+    fn coloring_size(&self) -> u64 { 0 }
+    fn fill_coloring_buffer(&self, _buffer: UniformBuffer<&mut [u8]>) {}
+    /// offsets of the members written by fill_uniform_buffer and fill_coloring_buffer, usually [crate::wgsl::MemberOffsets] of the ShaderTypes.
+    /// Checked against the shader's Props and Coloring in debug builds
+    fn uniform_offsets(&self) -> &'static [u64] { &[] }
+    fn coloring_offsets(&self) -> &'static [u64] { &[] }
+    /// gradients sampled by the shader with gradient_color, at most [crate::app::gradient::MAX_GRADIENTS].
+    /// They're also coloring data so changing them doesn't compute the fractal again
+    fn gradients(&self) -> Vec<&Gradient> { vec![] }
+    /// number of bins of the histogram of the iterations stored in the raw data, 0 if the coloring doesn't need one.
+    /// Check histogram.wgsl, it's only computed when rendering in two passes
+    fn histogram_bins(&self) -> u32 { 0 }
+    /// data that doesn't fit in the uniform, bound in group 1 if the shader has [crate::wgsl::Shader::params_binding]
+    fn params(&self) -> Vec<[f32; 4]> { vec![] }
+    /// cpu version of the single precision fragment shader, returns the color at uv.
+    /// `pixel_size` is the height of a pixel in the same coordinates as uv, `cycle_offset` is the same as in [crate::app::gradient::gradients_uniform_data]
+    fn evaluate(&self, uv: GVec2, pixel_size: f32, cycle_offset: f32) -> GVec4;
+    /// rough amount of work done for each pixel, used to spread expensive renders over multiple frames
+    fn cost(&self) -> u32 { 1 }
     /// mouse_pos will be Some if the mouse is hovering over the visualizer
-    fn draw_extra(&mut self, _painter: &Painter, _mouse_pos: Option<Vec2>) {}
+    fn draw_extra(&mut self, _painter: &Painter, _transform: &ScreenTransform, _mouse_pos: Option<Vec2>) {}
+    /// called when a drag starts on the visualizer at `pos`, true if it's on one of the handles drawn by draw_extra.
+    /// The handle then follows [FractalTrait::drag_handle] instead of the view moving
+    fn grab_handle(&mut self, _transform: &ScreenTransform, _pos: Pos2) -> bool { false }
+    /// `pos` is where the grabbed handle was dragged to
+    fn drag_handle(&mut self, _pos: Vec2) {}
+    /// Some if the fractal can be rendered using perturbation, which allows zooming way past double precision
+    fn perturbation(&self) -> Option<Perturbation> { None }
 }
 
 impl Default for Fractal {
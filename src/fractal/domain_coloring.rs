@@ -0,0 +1,286 @@
+use std::f32::consts::PI;
+use std::fmt::{Display, Formatter};
+use eframe::egui::{Button, ComboBox, CursorIcon, DragValue, Grid, Painter, Slider, Ui, vec2, Vec2, Widget};
+use encase::{ShaderType, UniformBuffer};
+use glam::{UVec2, Vec2 as GVec2, Vec3, Vec4 as GVec4};
+use num_complex::Complex32;
+use rand::Rng;
+use crate::app::library::PaletteLibrary;
+use crate::app::widgets::c32_ui_full;
+use crate::app::visualizer::ScreenTransform;
+use crate::fractal::FractalTrait;
+use crate::wgsl::{uniform_structs, ComplexFunction, DataSource, DomainColoringShader, MemberOffsets, Shader, Vec2Ext};
+
+/// Plots a complex function, the hue is the argument of f(z) and the lightness its modulus
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct DomainColoring {
+    function: ComplexFunction,
+    /// zeros of the polynomial, 1..=MAX_ROOTS
+    roots: Vec<Complex32>,
+    /// from 0 to 1, how dark the lines where |f(z)| is a power of 2 are
+    modulus_contours: f32,
+    /// number of lines around the zeros and poles where the argument is constant, 0 if there are none
+    phase_lines: u32,
+    /// from 0 to 1
+    phase_strength: f32,
+
+    #[serde(skip)]
+    pick_root_using_cursor: Option<usize>,
+}
+
+/// the roots are passed in a storage buffer like the ones of Newton's fractal
+const MAX_ROOTS: usize = 32;
+
+// check shader
+uniform_structs! {
+    struct DomainColoringUniform {
+        nr_roots: u32 = 0,
+        _padding0: u32 = 4,
+        _padding1: UVec2 = 8,
+    }
+
+    struct DomainColoringColoring {
+        modulus_contours: f32 = 0,
+        phase_lines: u32 = 4,
+        phase_strength: f32 = 8,
+        _padding: f32 = 12,
+    }
+}
+
+impl Default for DomainColoring {
+    fn default() -> Self {
+        Self {
+            function: ComplexFunction::Polynomial,
+            roots: vec![Complex32::new(1., 0.), Complex32::new(-0.5, 0.866), Complex32::new(-0.5, -0.866)],
+            modulus_contours: 0.5,
+            phase_lines: 12,
+            phase_strength: 0.3,
+            pick_root_using_cursor: None,
+        }
+    }
+}
+
+impl DomainColoring {
+    /// same as evaluate in domain_coloring.wgsl
+    fn function(&self, z: Complex32) -> Complex32 {
+        use ComplexFunction as CF;
+        match self.function {
+            CF::Polynomial => self.roots.iter().fold(Complex32::ONE, |w, root| w * (z - root)),
+            CF::Rational => (z * z - 1.) * (z - Complex32::new(2., 1.)).powi(2) / (z * z + Complex32::new(2., 2.)),
+            CF::Sine => z.sin(),
+            CF::Exponential => z.exp(),
+            CF::Logarithm => z.ln(),
+            CF::EssentialSingularity => z.inv().exp(),
+        }
+    }
+
+    /// same as colorize in domain_coloring.wgsl
+    fn colorize(&self, log_modulus: f32, argument: f32) -> GVec4 {
+        let hue = (argument / (2. * PI)).rem_euclid(1.);
+        let lightness = log_modulus.exp2().atan() * 2. / PI;
+        let mut color = hsl_to_rgb(hue, 1., lightness);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        color *= lerp(1., 0.6 + 0.4 * log_modulus.rem_euclid(1.), self.modulus_contours);
+        if self.phase_lines != 0 {
+            color *= lerp(1., 0.6 + 0.4 * (hue * self.phase_lines as f32).rem_euclid(1.), self.phase_strength);
+        }
+        color.extend(1.)
+    }
+
+    fn roots_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui|{
+            ui.label("Roots");
+            if ui.add_enabled(self.roots.len() < MAX_ROOTS, Button::new("+").small().min_size(vec2(15.,0.))).clicked() {
+                let mut rand = rand::rng();
+                self.roots.push(Complex32::new(rand.random::<f32>() * 2. - 1., rand.random::<f32>() * 2. - 1.));
+            }
+            if ui.add_enabled(self.roots.len() > 1, Button::new("-").small().min_size(vec2(15.,0.))).clicked() {
+                self.roots.pop();
+                self.pick_root_using_cursor = None;
+            }
+        });
+        Grid::new("domain coloring roots grid").min_col_width(0.).num_columns(3).striped(true).show(ui, |ui| {
+            for (i, root) in self.roots.iter_mut().enumerate() {
+                if c32_ui_full(ui, format!("{}", i+1), root, Some(0.02), None).clicked() {
+                    self.pick_root_using_cursor = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
+
+// same as domain_coloring.wgsl
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> Vec3 {
+    let rgb = ((Vec3::splat(h) + Vec3::new(0., 2. / 3., 1. / 3.)).map(|x| x.rem_euclid(1.)) * 6. - 3.).abs() - 1.;
+    let rgb = rgb.clamp(Vec3::ZERO, Vec3::ONE);
+    l + s * (rgb - 0.5) * (1. - (2. * l - 1.).abs())
+}
+
+impl FractalTrait for DomainColoring {
+    fn label(&mut self) -> &'static str { "Domain Coloring" }
+
+    fn settings_ui(&mut self, ui: &mut Ui, _palettes: &mut PaletteLibrary) {
+        if self.pick_root_using_cursor.is_some() {
+            ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+            if ui.input(|input| input.pointer.any_down()) { self.pick_root_using_cursor = None; }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Function");
+            use ComplexFunction as CF;
+            let functions = [CF::Polynomial, CF::Rational, CF::Sine, CF::Exponential, CF::Logarithm, CF::EssentialSingularity];
+            ComboBox::from_id_salt("complex function selector")
+                .selected_text(self.function.to_string())
+                .show_ui(ui, |ui| {
+                    for function in functions {
+                        ui.selectable_value(&mut self.function, function, function.to_string());
+                    }
+                });
+        });
+        if self.function == ComplexFunction::Polynomial {
+            self.roots_ui(ui);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Modulus contours");
+            Slider::new(&mut self.modulus_contours, 0.0..=1.).ui(ui)
+                .on_hover_text("Lines where |f(z)| is a power of 2");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Phase lines");
+            DragValue::new(&mut self.phase_lines).speed(0.1).range(0..=64).ui(ui)
+                .on_hover_text("Lines where the argument of f(z) is constant");
+        });
+        ui.add_enabled_ui(self.phase_lines != 0, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Phase line strength");
+                Slider::new(&mut self.phase_strength, 0.0..=1.).ui(ui);
+            });
+        });
+    }
+
+    fn get_shader(&self) -> Shader { Shader::DomainColoring(DomainColoringShader::Product(self.function, DataSource::Storage)) }
+
+    fn uniform_size(&self) -> u64 { DomainColoringUniform::min_size().get() }
+
+    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&DomainColoringUniform {
+            nr_roots: self.roots.len() as u32,
+            _padding0: 0,
+            _padding1: UVec2::ZERO,
+        }).unwrap();
+    }
+
+    fn coloring_size(&self) -> u64 { DomainColoringColoring::min_size().get() }
+
+    fn fill_coloring_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&DomainColoringColoring {
+            modulus_contours: self.modulus_contours,
+            phase_lines: self.phase_lines,
+            phase_strength: self.phase_strength,
+            _padding: 0.,
+        }).unwrap();
+    }
+
+    fn uniform_offsets(&self) -> &'static [u64] { DomainColoringUniform::MEMBER_OFFSETS }
+
+    fn coloring_offsets(&self) -> &'static [u64] { DomainColoringColoring::MEMBER_OFFSETS }
+
+    fn params(&self) -> Vec<[f32; 4]> {
+        self.roots.iter().map(|root| [root.re, root.im, 0., 0.]).collect()
+    }
+
+    fn evaluate(&self, uv: GVec2, _pixel_size: f32, _cycle_offset: f32) -> GVec4 {
+        let w = self.function(uv.to_c32());
+        self.colorize(w.norm().log2(), w.im.atan2(w.re))
+    }
+
+    fn cost(&self) -> u32 {
+        if self.function == ComplexFunction::Polynomial { self.roots.len() as u32 } else { 1 }
+    }
+
+    fn draw_extra(&mut self, _painter: &Painter, _transform: &ScreenTransform, mouse_pos: Option<Vec2>) {
+        if let (Some(mouse_pos), Some(index)) = (mouse_pos, self.pick_root_using_cursor) && let Some(root) = self.roots.get_mut(index) {
+            *root = mouse_pos.to_c32();
+        }
+    }
+}
+
+impl Display for ComplexFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use ComplexFunction as CF;
+        match self {
+            CF::Polynomial           => write!(f, "Polynomial"),
+            CF::Rational             => write!(f, "(z²-1)(z-2-i)²/(z²+2+2i)"),
+            CF::Sine                 => write!(f, "sin z"),
+            CF::Exponential          => write!(f, "exp z"),
+            CF::Logarithm            => write!(f, "log z"),
+            CF::EssentialSingularity => write!(f, "exp(1/z)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn hsl_primaries() {
+        assert_close(hsl_to_rgb(0., 1., 0.5), Vec3::X);
+        assert_close(hsl_to_rgb(1. / 3., 1., 0.5), Vec3::Y);
+        assert_close(hsl_to_rgb(2. / 3., 1., 0.5), Vec3::Z);
+        assert_close(hsl_to_rgb(0.5, 1., 0.5), Vec3::new(0., 1., 1.));
+        assert_close(hsl_to_rgb(0.3, 1., 0.), Vec3::ZERO);
+        assert_close(hsl_to_rgb(0.3, 1., 1.), Vec3::ONE);
+        assert_close(hsl_to_rgb(0.3, 0., 0.25), Vec3::splat(0.25));
+    }
+
+    #[test]
+    fn functions_at_known_points() {
+        use ComplexFunction as CF;
+        let fractal = DomainColoring::default();
+        for root in &fractal.roots {
+            assert!(fractal.function(*root).norm() < 1e-6, "{root}");
+        }
+        for (function, z, expected) in [
+            (CF::Rational, Complex32::ONE, Complex32::ZERO),
+            (CF::Rational, Complex32::new(2., 1.), Complex32::ZERO),
+            (CF::Sine, Complex32::ZERO, Complex32::ZERO),
+            (CF::Exponential, Complex32::new(0., PI), -Complex32::ONE),
+            (CF::Logarithm, Complex32::new(-1., 0.), Complex32::new(0., PI)),
+            (CF::EssentialSingularity, Complex32::ONE, Complex32::new(std::f32::consts::E, 0.)),
+        ] {
+            let w = DomainColoring { function, ..DomainColoring::default() }.function(z);
+            assert!((w - expected).norm() < 1e-5, "{function}: {w} != {expected}");
+        }
+    }
+
+    #[test]
+    fn colorize_hue_and_lightness() {
+        let plain = DomainColoring { modulus_contours: 0., phase_lines: 0, ..DomainColoring::default() };
+        // |f(z)| = 1 is fully saturated, the argument is the hue
+        assert_close(plain.colorize(0., 0.).truncate(), Vec3::X);
+        assert_close(plain.colorize(0., -PI / 3. * 2.).truncate(), Vec3::Z);
+        // zeros are black and poles are white
+        assert_close(plain.colorize(-100., 1.).truncate(), Vec3::ZERO);
+        assert_close(plain.colorize(100., 1.).truncate(), Vec3::ONE);
+        assert_eq!(plain.colorize(0., 0.).w, 1.);
+    }
+
+    #[test]
+    fn contours_darken_between_powers_of_two() {
+        let fractal = DomainColoring { modulus_contours: 1., phase_lines: 0, ..DomainColoring::default() };
+        // right below a power of 2 it's the plain color, right above it's the darkest
+        assert_close(fractal.colorize(0.5, 0.).truncate(), hsl_to_rgb(0., 1., 2f32.sqrt().atan() * 2. / PI) * 0.8);
+        assert_close(fractal.colorize(0., 0.).truncate(), Vec3::X * 0.6);
+        let lines = DomainColoring { modulus_contours: 0., phase_lines: 4, phase_strength: 1., ..DomainColoring::default() };
+        // an eighth of a turn is half way between two phase lines
+        assert_close(lines.colorize(0., PI / 4.).truncate(), hsl_to_rgb(0.125, 1., 0.5) * 0.8);
+    }
+}
@@ -1,52 +1,205 @@
+use std::f32::consts::PI;
 use std::fmt::{Display, Formatter};
 use std::sync::LazyLock;
 use ecolor::{hex_color, Color32};
-use eframe::egui::{ComboBox, DragValue, TextEdit, Ui, Widget};
+use eframe::egui::{pos2, vec2, Align2, ComboBox, DragValue, FontId, Sense, Slider, TextEdit, Ui, Widget};
 use encase::{ShaderType, UniformBuffer};
 use rand::{Rng, rng};
-use glam::Vec4 as GVec4;
-use crate::app::widgets::palette_editor;
+use glam::{UVec2, Vec2 as GVec2, Vec4 as GVec4};
+use crate::app::gradient::{cycle_position, Gradient, GRADIENT_PALETTES};
+use crate::app::widgets::{gradient_editor, option_checkbox, paint_gradient, palette_editor};
+use crate::app::library::PaletteLibrary;
 use crate::fractal::FractalTrait;
-use crate::wgsl::{LyapunovShader, Shader};
+use crate::wgsl::{uniform_structs, DataSource, LyapunovFunction, LyapunovShader, MemberOffsets, Shader};
 
 // todo: other functions?
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Lyapunov {
     iterations: u32,
-    /// must only contain 'A', 'a', 'B'. 'b'; max length is 16
+    /// must only contain 'A', 'a', 'B'. 'b'; max length is MAX_SEQUENCE_LENGTH
     sequence: String,
-    variant: LyapunovShader,
+    variant: LyapunovFunction,
     // todo: c: f32
     #[serde(default="default_palette")]
     colors: [Color32; 2],
+    /// Some if the exponents are mapped to gradients instead of darkening the two colors
+    #[serde(default)]
+    gradients: Option<ExponentGradients>,
 }
 
-#[derive(ShaderType)]
-struct LyapunovUniform {
-    stable_col: GVec4,
-    unstable_col: GVec4,
-    iterations: u32,
-    // 1..=16
-    seq_len: u32,
-    // array packed in an integer, 0 is A and 1 is B
-    sequence: u32,
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExponentGradients {
+    /// from exponent 0 to min_exponent
+    negative: Gradient,
+    /// from exponent 0 to max_exponent
+    positive: Gradient,
+    /// exponents past the range get the end of the gradients
+    min_exponent: f32,
+    max_exponent: f32,
+    /// the position in the gradients is raised to this power, lower values show more detail close to 0
+    gamma: f32,
+}
+
+impl ExponentGradients {
+    /// looks close to the two darkened colors
+    fn from_colors(colors: [Color32; 2]) -> Self {
+        Self {
+            negative: Gradient::new(&[colors[0], Color32::BLACK]),
+            positive: Gradient::new(&[colors[1], Color32::BLACK]),
+            min_exponent: -3.,
+            max_exponent: 3.,
+            gamma: 0.5,
+        }
+    }
+
+    /// same as exponent_to_color in lyapunov.wgsl
+    fn color(&self, exponent: f32, cycle_offset: f32) -> Color32 {
+        if exponent <= 0. {
+            self.negative.sample(cycle_position((exponent / self.min_exponent).clamp(0., 1.).powf(self.gamma), cycle_offset))
+        } else {
+            self.positive.sample(cycle_position((exponent / self.max_exponent).clamp(0., 1.).powf(self.gamma), cycle_offset))
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
+        gradient_editor(ui, &mut self.negative, "Negative exponents", GRADIENT_PALETTES.as_slice(), palettes);
+        gradient_editor(ui, &mut self.positive, "Positive exponents", GRADIENT_PALETTES.as_slice(), palettes);
+        ui.horizontal(|ui| {
+            ui.label("Range");
+            DragValue::new(&mut self.min_exponent).speed(0.02).range(-100.0..=-0.01).ui(ui);
+            DragValue::new(&mut self.max_exponent).speed(0.02).range(0.01..=100.).ui(ui);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Gamma");
+            Slider::new(&mut self.gamma, 0.1..=4.).logarithmic(true).ui(ui);
+        });
+        self.legend_ui(ui);
+    }
+
+    /// the colors from the lowest to the highest exponent, with the exponents under them
+    fn legend_ui(&self, ui: &mut Ui) {
+        const SAMPLES: usize = 128;
+        let width = ui.available_width().min(250.);
+        let (rect, _) = ui.allocate_exact_size(vec2(width, 16.), Sense::hover());
+        let range = self.max_exponent - self.min_exponent;
+        let colors: Vec<_> = (0..=SAMPLES).map(|i| self.color(self.min_exponent + range * i as f32 / SAMPLES as f32, 0.)).collect();
+        paint_gradient(ui.painter(), rect, &Gradient::new(&colors));
+
+        let (labels_rect, _) = ui.allocate_exact_size(vec2(width, 14.), Sense::hover());
+        let font = FontId::proportional(11.);
+        let text_color = ui.visuals().text_color();
+        for (exponent, align) in [(self.min_exponent, Align2::LEFT_TOP), (0., Align2::CENTER_TOP), (self.max_exponent, Align2::RIGHT_TOP)] {
+            let x = rect.left() + (exponent - self.min_exponent) / range * width;
+            ui.painter().vline(x, rect.bottom()..=labels_rect.top() + 2., (1., text_color));
+            ui.painter().text(pos2(x, labels_rect.top() + 2.), align, format!("{exponent:.2}"), font.clone(), text_color);
+        }
+    }
+}
+
+uniform_structs! {
+    struct LyapunovUniform {
+        iterations: u32 = 0,
+        seq_len: u32 = 4,
+        // keeps the size a multiple of 16
+        _padding: UVec2 = 8,
+    }
+
+    struct LyapunovColoring {
+        stable_col: GVec4 = 0,
+        unstable_col: GVec4 = 16,
+        gradients: u32 = 32,
+        min_exponent: f32 = 36,
+        max_exponent: f32 = 40,
+        gamma: f32 = 44,
+    }
 }
 
+/// the sequence is passed in a storage buffer so it can be long
+const MAX_SEQUENCE_LENGTH: usize = 1024;
+
 impl Default for Lyapunov {
     fn default() -> Self {
         Self {
             iterations: 300,
             sequence: String::from("AB"),
-            variant: LyapunovShader::LogisticMap,
+            variant: LyapunovFunction::LogisticMap,
             colors: default_palette(),
+            gradients: Some(ExponentGradients::from_colors(default_palette())),
         }
     }
 }
 
+// same as lyapunov.wgsl
+const ALPHA: f32 = 1.;
+const IGNORE_DIV: u32 = 10;
+
+impl Lyapunov {
+    /// the sequence where A is 0 and B is 1
+    fn symbols(&self) -> Vec<f32> {
+        if self.sequence.is_empty() {
+            return vec![0., 1.]; // default AB sequence
+        }
+        self.sequence.chars().map(|c| match c {
+            'A' | 'a' => 0.,
+            'B' | 'b' => 1.,
+            _ => unreachable!(),
+        }).collect()
+    }
+
+    /// same as compute_exponent in lyapunov.wgsl
+    fn compute_exponent(&self, ab: GVec2) -> f32 {
+        let symbols = self.symbols();
+        let mut xi = 0.5;
+        let mut exp = 0.;
+        let ignore_iter = self.iterations / IGNORE_DIV;
+        for i in 1..=self.iterations {
+            let r = if symbols[(i - 1) as usize % symbols.len()] == 1. { ab.x } else { ab.y };
+            xi = self.func(xi, r);
+            // ignore the first iterations to avoid instability
+            if i > ignore_iter {
+                exp += self.exponent(xi, r);
+            }
+        }
+        exp / (self.iterations - ignore_iter) as f32
+    }
+
+    // f(x)
+    fn func(&self, x: f32, r: f32) -> f32 {
+        use LyapunovFunction as LC;
+        match self.variant {
+            LC::LogisticMap => r * x * (1. - x),
+            LC::SinMap => r * (x * PI).sin(),
+            LC::GaussMap => (-5. * x * x).exp() + r,
+            LC::Exponential => r.abs().powf(x.sin()),
+            LC::CircleMap1 => fract(x + 1./3. - r * (2. * PI * x).sin() / (2. * PI)),
+            LC::CircleMap2 => fract(x + r - 2. * (2. * PI * x).sin() / (2. * PI)),
+        }
+    }
+
+    // log(abs(f`(x)))
+    fn exponent(&self, x: f32, r: f32) -> f32 {
+        use LyapunovFunction as LC;
+        match self.variant {
+            LC::LogisticMap => (r - 2. * r * x).abs().ln(),
+            LC::SinMap => (r * (x * PI).cos() * PI).abs().ln(),
+            LC::GaussMap => ((-5. * x * x).exp() * -10. * x).abs().ln(),
+            LC::Exponential => {
+                let r = r.abs();
+                (r.powf(x.sin()) * r.ln() * x.cos()).abs().ln()
+            }
+            LC::CircleMap1 => (r * (2. * PI * x).cos() + 1.).abs().ln(),
+            LC::CircleMap2 => (2. * (2. * PI * x).cos() + 1.).abs().ln(),
+        }
+    }
+}
+
+// wgsl's fract is x - floor(x), rust's fract is x - trunc(x)
+fn fract(x: f32) -> f32 { x - x.floor() }
+
 impl FractalTrait for Lyapunov {
     fn label(&mut self) ->  &'static str { "Lyapunov's Fractal" }
 
-    fn settings_ui(&mut self, ui: &mut Ui) {
+    fn settings_ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
         ui.horizontal(|ui|{
             ui.label("Iterations");
             DragValue::new(&mut self.iterations).speed(1).range(0..=3000).ui(ui);
@@ -54,7 +207,7 @@ impl FractalTrait for Lyapunov {
 
         ui.horizontal(|ui|{
             ui.label("Function");
-            use LyapunovShader as LC;
+            use LyapunovFunction as LC;
             let variants = [LC::LogisticMap, LC::SinMap, LC::GaussMap, LC::Exponential, LC::CircleMap1, LC::CircleMap2];
             ComboBox::from_id_salt("variant selector")
                 .selected_text(self.variant.to_string())
@@ -70,7 +223,7 @@ impl FractalTrait for Lyapunov {
         ui.label("Sequence (A and B only)");
         ui.horizontal(|ui|{
             TextEdit::singleline(&mut self.sequence).hint_text("AB")
-                .desired_width(135.).char_limit(16).ui(ui);
+                .desired_width(135.).char_limit(MAX_SEQUENCE_LENGTH).ui(ui);
             self.sequence.retain(|c| c == 'A' || c == 'B' || c == 'a' || c == 'b');
             if ui.button("🔁").clicked() {
                 let mut rng = rng();
@@ -87,40 +240,73 @@ impl FractalTrait for Lyapunov {
             }
         });
 
-        palette_editor(ui, &mut self.colors, "Colors", COLOR_PALETTES.as_slice());
+        let colors = self.colors;
+        option_checkbox(ui, &mut self.gradients, "Gradients", || ExponentGradients::from_colors(colors));
+        match &mut self.gradients {
+            Some(gradients) => gradients.ui(ui, palettes),
+            None => palette_editor(ui, &mut self.colors, "Colors", COLOR_PALETTES.as_slice(), palettes),
+        }
     }
 
-    fn get_shader(&self) -> Shader { Shader::Lyapunov(self.variant) }
+    fn get_shader(&self) -> Shader { Shader::Lyapunov(LyapunovShader::Product(self.variant, DataSource::Storage)) }
 
-    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
-        let (seq_len, sequence) = if self.sequence.is_empty() {
-            (2u32, 0b10) // default AB sequence
-        } else {
-            (self.sequence.len() as u32,
-                // packs the sequence into an u32 where 0 is A and 1 is B
-             self.sequence.chars().enumerate().fold(0u32,|seq,(i,c)|{
-                 seq | ( match c {
-                     'A' | 'a' => 0b0,
-                     'B' | 'b' => 0b1,
-                        _ => unreachable!(),
-                 } << i)
-            }))
-        };
-        //println!("seq_len: {}, sequence: {:b}", seq_len, sequence);
+    fn evaluate(&self, uv: GVec2, _pixel_size: f32, cycle_offset: f32) -> GVec4 {
+        let gamma = self.compute_exponent(uv);
+        if let Some(gradients) = &self.gradients {
+            return gradients.color(gamma, cycle_offset).to_normalized_gamma_f32().into();
+        }
+        let color = if gamma > 0. { self.colors[1] } else { self.colors[0] };
+        let color: GVec4 = color.to_normalized_gamma_f32().into();
+        (color.truncate() * (-ALPHA * gamma.abs()).exp()).extend(1.)
+    }
+
+    // both the function and the exponent are evaluated each iteration
+    fn cost(&self) -> u32 { self.iterations * 2 }
 
+    fn uniform_size(&self) -> u64 { LyapunovUniform::min_size().get() }
+
+    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
         buffer.write(&LyapunovUniform {
+            iterations: self.iterations,
+            seq_len: self.symbols().len() as u32,
+            _padding: UVec2::ZERO,
+        }).unwrap();
+    }
+
+    fn coloring_size(&self) -> u64 { LyapunovColoring::min_size().get() }
+
+    fn fill_coloring_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        let gradients = self.gradients.clone().unwrap_or_else(|| ExponentGradients::from_colors(self.colors));
+        buffer.write(&LyapunovColoring {
             stable_col: self.colors[0].to_normalized_gamma_f32().into(),
             unstable_col: self.colors[1].to_normalized_gamma_f32().into(),
-            iterations: self.iterations,
-            seq_len,
-            sequence,
+            gradients: self.gradients.is_some() as u32,
+            min_exponent: gradients.min_exponent,
+            max_exponent: gradients.max_exponent,
+            gamma: gradients.gamma,
         }).unwrap();
     }
+
+    fn uniform_offsets(&self) -> &'static [u64] { LyapunovUniform::MEMBER_OFFSETS }
+
+    fn coloring_offsets(&self) -> &'static [u64] { LyapunovColoring::MEMBER_OFFSETS }
+
+    fn gradients(&self) -> Vec<&Gradient> {
+        match &self.gradients {
+            Some(gradients) => vec![&gradients.negative, &gradients.positive],
+            None => vec![],
+        }
+    }
+
+    // 4 symbols per element
+    fn params(&self) -> Vec<[f32; 4]> {
+        self.symbols().chunks(4).map(|chunk| std::array::from_fn(|i| chunk.get(i).copied().unwrap_or_default())).collect()
+    }
 }
 
-impl Display for LyapunovShader {
+impl Display for LyapunovFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        use LyapunovShader as LC;
+        use LyapunovFunction as LC;
         match self {
             LC::LogisticMap => write!(f, "Logistic map"),
             LC::SinMap      => write!(f, "Sine Map"),
@@ -1,11 +1,15 @@
 use std::ops::Not;
-use eframe::egui::{Button, ComboBox, CursorIcon, DragValue, Painter, Slider, SliderClamping, Ui, Vec2, Widget, WidgetText};
+use eframe::egui::{vec2, Button, Color32, ComboBox, CursorIcon, DragValue, Painter, Pos2, Slider, SliderClamping, Stroke, Ui, Vec2, Widget, WidgetText};
 use encase::{ShaderType, UniformBuffer};
 use num_complex::{Complex32, ComplexFloat};
-use glam::Vec2 as GVec2;
-use crate::app::widgets::{c32_ui_full, option_checkbox};
+use glam::{Vec2 as GVec2, Vec4 as GVec4};
+use crate::app::gradient::{cycle_position, Gradient, GRADIENT_PALETTES};
+use crate::app::library::PaletteLibrary;
+use crate::app::perturbation::{Formula, Perturbation};
+use crate::app::widgets::{c32_ui_full, gradient_editor, option_checkbox};
+use crate::app::visualizer::ScreenTransform;
 use crate::fractal::FractalTrait;
-use crate::wgsl::{mandelbrot::*, Complex32Ext, Vec2Ext};
+use crate::wgsl::{uniform_structs, Complex32Ext, MemberOffsets, Vec2Ext, mandelbrot::*};
 use crate::wgsl::Shader;
 
 /// Handles  all the mandelbrot-type fractals including Julia variations and Burning ship
@@ -21,10 +25,197 @@ pub struct MandelbrotFamily {
     // if None e will be 2
     // z = z^e + c
     multi_e: Option<f32>,
+    #[serde(default)]
+    coloring: Coloring,
+    /// number of stripes in a turn around the origin, only used by the stripe average
+    #[serde(default = "default_stripe_density")]
+    stripe_density: f32,
+    /// continuous iteration count instead of whole iterations, which removes the bands
+    #[serde(default)]
+    smooth: bool,
+    // older fractals were grayscale
+    #[serde(default = "Gradient::grayscale")]
+    gradient: Gradient,
+    /// added to the gradient's position
+    #[serde(default)]
+    gradient_offset: f32,
+    /// how many times the gradient is repeated from 0 to max iterations
+    #[serde(default = "default_gradient_repeat")]
+    gradient_repeat: f32,
+    /// Some if the pixels are colored by how close their orbit got to the trap
+    #[serde(default)]
+    orbit_trap: Option<OrbitTrap>,
+    #[serde(skip)]
+    pick_trap_using_cursor: bool,
+    /// Some if the distance to the set is estimated, which shows filaments that are thinner than a pixel
+    #[serde(default)]
+    distance_estimation: Option<DistanceEstimation>,
+    /// Some if the points that didn't escape are colored with their own gradient
+    #[serde(default)]
+    interior: Option<InteriorColoring>,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Coloring {
+    /// iterations / max iterations
+    #[default]
+    Linear,
+    /// the cumulative distribution of the iterations of the current frame, spreads the colors evenly at any depth.
+    /// Needs the raw data so it falls back to linear if fractals are drawn in a single pass
+    Histogram,
+    /// average of sin(density * arg z) over the orbit
+    StripeAverage,
+    /// average of where |z| lies between the bounds given by the triangle inequality
+    TriangleInequality,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OrbitTrap {
+    shape: TrapShape,
+    center: Complex32,
+    /// angle of the line and the cross in degrees
+    angle: f32,
+    /// only used by the circle
+    radius: f32,
+    /// the gradient goes from 0 to this distance, changing it doesn't compute the fractal again
+    distance: f32,
+}
+
+impl Default for OrbitTrap {
+    fn default() -> Self {
+        Self { shape: TrapShape::Point, center: Complex32::ZERO, angle: 0., radius: 0.5, distance: 1. }
+    }
+}
+
+// check shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[repr(u32)]
+pub enum TrapShape {
+    Point = 1,
+    /// through the center
+    Line = 2,
+    Cross = 3,
+    Circle = 4,
+}
+
+/// radius of the handle drawn on the trap's center, in points
+const TRAP_HANDLE_RADIUS: f32 = 5.;
+
+impl OrbitTrap {
+    /// cos and sin of the angle
+    fn direction(&self) -> Complex32 {
+        Complex32::from_polar(1., self.angle.to_radians())
+    }
+
+    /// draws the trap over the fractal with a handle on its center, lines cross the whole visualizer
+    fn draw(&self, painter: &Painter, transform: &ScreenTransform) {
+        let center = transform.to_screen(Vec2::new(self.center.re, self.center.im));
+        // the screen's y goes down
+        let direction = vec2(self.direction().re, -self.direction().im);
+        let length = painter.clip_rect().size().length();
+        let lines = match self.shape {
+            TrapShape::Line => vec![direction],
+            TrapShape::Cross => vec![direction, direction.rot90()],
+            TrapShape::Point | TrapShape::Circle => vec![],
+        };
+        let outline = Stroke::new(3_f32, Color32::from_black_alpha(160));
+        // the dark outline under the light line stays visible on any color
+        for stroke in [outline, Stroke::new(1.5_f32, Color32::WHITE)] {
+            for &line in &lines {
+                painter.line_segment([center - line * length, center + line * length], stroke);
+            }
+            if self.shape == TrapShape::Circle {
+                painter.circle_stroke(center, self.radius * transform.points_per_unit(), stroke);
+            }
+        }
+        painter.circle(center, TRAP_HANDLE_RADIUS, Color32::WHITE, Stroke { width: 1.5, ..outline });
+    }
+
+    /// true if `pos` is on the handle drawn by [OrbitTrap::draw]
+    fn handle_contains(&self, transform: &ScreenTransform, pos: Pos2) -> bool {
+        // a bit bigger than the handle so it's easy to grab
+        transform.to_screen(Vec2::new(self.center.re, self.center.im)).distance(pos) <= TRAP_HANDLE_RADIUS * 2.
+    }
+
+    /// same as trap_distance in mandelbrot_coloring.wgsl
+    fn distance(&self, z: Complex32) -> f32 {
+        let p = z - self.center;
+        // rotated so the line lies on the x axis
+        let q = p * self.direction().conj();
+        match self.shape {
+            TrapShape::Point => p.norm(),
+            TrapShape::Line => q.im.abs(),
+            TrapShape::Cross => q.re.abs().min(q.im.abs()),
+            TrapShape::Circle => (p.norm() - self.radius).abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DistanceEstimation {
+    mode: DistanceMode,
+    /// in pixels
+    thickness: f32,
+    /// how much it's mixed with the iteration coloring, from 0 to 1
+    strength: f32,
+}
+
+impl Default for DistanceEstimation {
+    fn default() -> Self {
+        Self { mode: DistanceMode::Outline, thickness: 1., strength: 1. }
+    }
+}
+
+// check shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[repr(u32)]
+pub enum DistanceMode {
+    /// lines of the interior color on the boundary
+    Outline = 0,
+    /// the gradient goes from 0 to the thickness
+    Gradient = 1,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InteriorColoring {
+    mode: InteriorMode,
+    gradient: Gradient,
+}
+
+impl Default for InteriorColoring {
+    fn default() -> Self {
+        Self { mode: InteriorMode::Period, gradient: GRADIENT_PALETTES[3].clone() }
+    }
+}
+
+// check shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[repr(u32)]
+pub enum InteriorMode {
+    /// period of the attracting cycle found with periodicity checking
+    Period = 1,
+    /// final |z|
+    Magnitude = 2,
+    /// final argument of z
+    Angle = 3,
+    /// distance to the boundary, only in the parameter plane
+    Distance = 4,
+}
+
+/// number of periods before the interior gradient starts over, same as mandelbrot_coloring.wgsl
+const PERIOD_COLORS: u32 = 12;
+/// z has to get this close to the z saved by the periodicity checking, same as mandelbrot_coloring.wgsl
+const PERIOD_EPSILON: f32 = 1e-6;
+
 fn pick_c_default() -> (bool, PickCMode) {(false, PickCMode::Both)}
 
+fn default_gradient_repeat() -> f32 { 1. }
+
+fn default_stripe_density() -> f32 { 5. }
+
+/// the continuous iteration count and the distance estimation are only accurate if z got a lot bigger than the escape radius
+const LARGE_ESCAPE_RADIUS: f32 = 256.;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[repr(u32)]
 pub enum PickCMode {
@@ -35,13 +226,54 @@ pub enum PickCMode {
 }
 
 // check shader
-#[derive(ShaderType)]
-struct MandelbrotUniform {
-    c: GVec2,
+uniform_structs! {
+    struct MandelbrotUniform {
+        c: GVec2 = 0,
+        iterations: u32 = 8,
+        escape_radius: f32 = 12,
+        exp: f32 = 16,
+        julia: u32 = 20,
+        // used by the double precision shaders, check mandelbrot.wgsl
+        one: f32 = 24,
+        distance_estimation: u32 = 28,
+        // 0 if there's no orbit trap
+        trap_shape: u32 = 32,
+        trap_radius: f32 = 36,
+        trap_center: GVec2 = 40,
+        trap_direction: GVec2 = 48,
+        interior_mode: u32 = 56,
+        average_mode: u32 = 60,
+        stripe_density: f32 = 64,
+    }
+
+    struct MandelbrotColoring {
+        mode: u32 = 0,
+        smooth_iterations: u32 = 4,
+        offset: f32 = 8,
+        repeat: f32 = 12,
+        trap_distance: f32 = 16,
+        de_mode: u32 = 20,
+        de_thickness: f32 = 24,
+        de_strength: f32 = 28,
+    }
+}
+
+/// same as Escape in mandelbrot_coloring.wgsl
+struct Escape {
     iterations: u32,
-    escape_radius: f32,
-    exp: f32,
-    julia: u32,
+    z: Complex32,
+    trap: f32,
+    /// the distance to the set or the average if it escaped, the period or the interior distance if it didn't
+    distance: f32,
+}
+
+/// same as Average in mandelbrot_coloring.wgsl
+#[derive(Clone, Copy, Default)]
+struct Average {
+    sum: f32,
+    count: f32,
+    previous_sum: f32,
+    previous_count: f32,
 }
 
 impl MandelbrotFamily {
@@ -52,20 +284,362 @@ impl MandelbrotFamily {
             julia_c: None,
             pick_c_using_cursor: pick_c_default(),
             multi_e: None,
+            coloring: Coloring::Linear,
+            stripe_density: default_stripe_density(),
+            smooth: true,
+            gradient: GRADIENT_PALETTES[0].clone(),
+            gradient_offset: 0.,
+            gradient_repeat: 4.,
+            orbit_trap: None,
+            pick_trap_using_cursor: false,
+            distance_estimation: None,
+            interior: None,
         }
     }
 
     pub fn default_julia() -> Self {
         Self {
-            iterations: 300,
-            variant: Variant::Mandelbrot,
             julia_c: Some(Complex32::new(-0.76,-0.15)),
-            pick_c_using_cursor: pick_c_default(),
-            multi_e: None,
+            ..Self::default_mandelbrot()
         }
     }
 
     pub fn is_julia(&self) -> bool { self.julia_c.is_some() }
+
+    fn escape_radius(&self) -> f32 {
+        let radius = self.base_escape_radius();
+        if self.smooth || self.uses_distance_estimation() || self.average_mode() != 0 { radius.max(LARGE_ESCAPE_RADIUS) } else { radius }
+    }
+
+    fn base_escape_radius(&self) -> f32 {
+        // todo: return to this
+        match self.julia_c {
+            None => 2.,
+            Some(c) => {
+                // we solve R > 0 such that R^2 - R == |c|
+                // todo: escape radius is too small for e < 2 and (maybe) too big for e > 2
+                (1. + (1. + 4. * c.abs()).sqrt()) / 2.
+            }
+        }
+    }
+
+    /// the burning ship doesn't have a derivative and the raw data only has room for the orbit trap, the distance or the average
+    fn uses_distance_estimation(&self) -> bool {
+        self.distance_estimation.is_some() && self.orbit_trap.is_none() && self.average_mode() == 0 && self.variant != Variant::BurningShip
+    }
+
+    /// 0 if there's no average coloring, orbit traps replace it
+    fn average_mode(&self) -> u32 {
+        if self.orbit_trap.is_some() { return 0; }
+        match self.coloring {
+            Coloring::StripeAverage => 1,
+            Coloring::TriangleInequality => 2,
+            _ => 0,
+        }
+    }
+
+    /// 0 if the points that didn't escape get the last color, orbit traps color them too
+    fn interior_mode(&self) -> u32 {
+        match &self.interior {
+            Some(interior) if self.orbit_trap.is_none() => {
+                if interior.mode == InteriorMode::Distance && !self.interior_distance_available() { 0 } else { interior.mode as u32 }
+            }
+            _ => 0,
+        }
+    }
+
+    /// the interior distance needs the derivatives with respect to c
+    fn interior_distance_available(&self) -> bool {
+        !self.is_julia() && self.variant != Variant::BurningShip
+    }
+
+    // 0 if not in julia mode
+    // 2 if not picking
+    // 0,1,2 if picking
+    fn julia_mode(&self) -> u32 {
+        if self.julia_c.is_none() {0}
+        else if self.pick_c_using_cursor.0 { self.pick_c_using_cursor.1 as u32 } else { 2 }
+    }
+
+    /// same as compute_iterations in mandelbrot.wgsl, julia is true if the pixel is z0 instead of c
+    fn compute_iterations(&self, z0: Complex32, c: Complex32, escape_radius: f32, max_iterations: u32, julia: bool, pixel_size: f32) -> Escape {
+        let mut iterations = 0;
+        let mut z = z0;
+        let mut trap = 1e20_f32;
+        let mut dz = if julia { Complex32::ONE } else { Complex32::ZERO };
+        let de = self.uses_distance_estimation();
+        let r_sq = escape_radius * escape_radius;
+        let interior_mode = self.interior.as_ref().map(|interior| interior.mode).filter(|_| self.interior_mode() != 0);
+        let check_period = matches!(interior_mode, Some(InteriorMode::Period | InteriorMode::Distance));
+        let (mut saved, mut saved_at, mut period, mut found) = (z, 0, 0, false);
+        let mut average = Average::default();
+        while z.norm_sqr() <= r_sq && iterations < max_iterations {
+            if de && dz.norm_sqr() < 1e30 {
+                dz = self.equation_derivative(z) * dz + if julia { 0. } else { 1. };
+            }
+            z = self.equation(z, c);
+            iterations += 1;
+            if let Some(orbit_trap) = &self.orbit_trap && z.norm_sqr() <= r_sq {
+                trap = trap.min(orbit_trap.distance(z));
+            }
+            if self.average_mode() != 0 {
+                average = self.add_average_term(average, z, c);
+            }
+            if check_period {
+                if !found && (z - saved).norm_sqr() < PERIOD_EPSILON * PERIOD_EPSILON {
+                    period = iterations - saved_at;
+                    found = true;
+                }
+                if iterations.is_power_of_two() {
+                    (saved, saved_at, found) = (z, iterations, false);
+                }
+            }
+        }
+        let distance = if iterations >= max_iterations {
+            match interior_mode {
+                Some(InteriorMode::Period) => period as f32,
+                Some(InteriorMode::Distance) if julia || period == 0 => -1.,
+                Some(InteriorMode::Distance) => self.interior_distance(z, c, period, pixel_size),
+                _ => 0.,
+            }
+        } else if de {
+            z.norm() * z.norm().ln() / dz.norm() / pixel_size
+        } else if self.average_mode() != 0 {
+            self.smooth_average(average, z)
+        } else {
+            0.
+        };
+        Escape { iterations, z, trap, distance }
+    }
+
+    /// same as add_average_term in mandelbrot_coloring.wgsl
+    fn add_average_term(&self, average: Average, z: Complex32, c: Complex32) -> Average {
+        let term = if self.average_mode() == 1 {
+            0.5 * (self.stripe_density * z.im.atan2(z.re)).sin() + 0.5
+        } else {
+            let (a, b) = ((z - c).norm(), c.norm());
+            let (low, high) = ((a - b).abs(), a + b);
+            if high - low <= 0. {
+                return Average { previous_sum: average.sum, previous_count: average.count, ..average };
+            }
+            (z.norm() - low) / (high - low)
+        };
+        Average { sum: average.sum + term, count: average.count + 1., previous_sum: average.sum, previous_count: average.count }
+    }
+
+    /// same as smooth_average in mandelbrot_coloring.wgsl
+    fn smooth_average(&self, average: Average, z: Complex32) -> f32 {
+        let last = average.sum / average.count.max(1.);
+        let previous = average.previous_sum / average.previous_count.max(1.);
+        let log_ratio = z.norm().ln() / self.escape_radius().ln();
+        let fraction = (log_ratio.max(1.).ln() / self.multi_e.unwrap_or(2.).max(1.1).ln()).clamp(0., 1.);
+        last + (previous - last) * fraction
+    }
+
+    /// same as interior_distance in mandelbrot_coloring.wgsl, -1 if the cycle isn't attracting
+    fn interior_distance(&self, z0: Complex32, c: Complex32, period: u32, pixel_size: f32) -> f32 {
+        let mut z = z0;
+        let (mut dz, mut dc, mut dzdz, mut dcdz) = (Complex32::ONE, Complex32::ZERO, Complex32::ZERO, Complex32::ZERO);
+        for _ in 0..period {
+            let d = self.equation_derivative(z);
+            let d2 = self.equation_second_derivative(z);
+            dcdz = d * dcdz + d2 * dz * dc;
+            dzdz = d * dzdz + d2 * dz * dz;
+            dz *= d;
+            dc = d * dc + 1.;
+            z = self.equation(z, c);
+        }
+        if dz.norm_sqr() >= 1. { return -1.; }
+        let denominator = dcdz + dzdz * dc / (1. - dz);
+        (1. - dz.norm_sqr()) / denominator.norm() / pixel_size
+    }
+
+    /// same as interior_color in mandelbrot_coloring.wgsl, `extra` is the period or the interior distance
+    fn interior_color(&self, z: Complex32, extra: f32) -> GVec4 {
+        let last_color = || self.gradient.sample(1.).to_normalized_gamma_f32().into();
+        let Some(interior) = self.interior.as_ref().filter(|_| self.interior_mode() != 0) else { return last_color(); };
+        let t = match interior.mode {
+            InteriorMode::Period if extra < 1. => return last_color(),
+            InteriorMode::Period => ((extra as u32 - 1) % PERIOD_COLORS) as f32 / (PERIOD_COLORS - 1) as f32,
+            InteriorMode::Magnitude => z.norm() / 2.,
+            InteriorMode::Angle => z.im.atan2(z.re) / std::f32::consts::TAU + 0.5,
+            InteriorMode::Distance if extra < 0. => return last_color(),
+            InteriorMode::Distance => (1. + extra).log2() / 10.,
+        };
+        interior.gradient.sample(t.clamp(0., 1.)).to_normalized_gamma_f32().into()
+    }
+
+    /// same as colorize in mandelbrot_coloring.wgsl when it's drawn in a single pass, so without the histogram.
+    /// `extra` is the closest distance to the orbit trap or the distance to the set
+    fn colorize(&self, iterations: u32, z: Complex32, extra: f32, cycle_offset: f32) -> GVec4 {
+        let t = if let Some(orbit_trap) = &self.orbit_trap {
+            (extra / orbit_trap.distance).min(1.)
+        } else if self.average_mode() != 0 && iterations < self.iterations {
+            // the average is already between 0 and 1
+            extra
+        } else {
+            if iterations >= self.iterations {
+                return self.interior_color(z, extra);
+            }
+            let mut n = iterations as f32;
+            if self.smooth {
+                let log_ratio = z.norm().ln() / self.escape_radius().ln();
+                n -= log_ratio.max(1.).ln() / self.multi_e.unwrap_or(2.).max(1.1).ln();
+            }
+            n / self.iterations as f32
+        };
+        let color: GVec4 = self.gradient.sample(self.gradient_position(t, cycle_offset)).to_normalized_gamma_f32().into();
+        let Some(de) = self.distance_estimation.filter(|_| self.uses_distance_estimation()) else { return color; };
+
+        // the distance to the set in pixels is in extra
+        match de.mode {
+            DistanceMode::Outline => {
+                // 1 on the boundary and 0 further than the thickness, same as smoothstep
+                let x = (extra / de.thickness).clamp(0., 1.);
+                let line = 1. - x * x * (3. - 2. * x);
+                color.lerp(self.gradient.sample(1.).to_normalized_gamma_f32().into(), line * de.strength)
+            }
+            DistanceMode::Gradient => {
+                let distance_color = self.gradient.sample(self.gradient_position((extra / de.thickness).min(1.), cycle_offset));
+                color.lerp(distance_color.to_normalized_gamma_f32().into(), de.strength)
+            }
+        }
+    }
+
+    /// same as gradient_position in mandelbrot_coloring.wgsl
+    fn gradient_position(&self, t: f32, cycle_offset: f32) -> f32 {
+        let t = t * self.gradient_repeat + self.gradient_offset;
+        // 1 is kept so the whole gradient is used when it isn't repeated
+        cycle_position(if t > 1. { t.fract() } else { t }, cycle_offset)
+    }
+
+    fn orbit_trap_ui(&mut self, ui: &mut Ui) {
+        option_checkbox(ui, &mut self.orbit_trap, "Orbit trap", OrbitTrap::default);
+        let Some(trap) = &mut self.orbit_trap else {
+            self.pick_trap_using_cursor = false;
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Shape");
+            let arr = [TrapShape::Point, TrapShape::Line, TrapShape::Cross, TrapShape::Circle];
+            let mut index = arr.iter().position(|s| *s == trap.shape).unwrap();
+            ComboBox::from_id_salt("trap_shape_selector")
+                .selected_text(trap.shape)
+                .show_index(ui, &mut index, arr.len(), |i|arr[i]);
+            trap.shape = arr[index];
+        });
+        ui.horizontal(|ui| {
+            if c32_ui_full(ui, "Center", &mut trap.center, Some(0.01), None).clicked() {
+                self.pick_trap_using_cursor = true;
+            }
+        });
+        match trap.shape {
+            TrapShape::Point => {}
+            TrapShape::Line | TrapShape::Cross => { ui.horizontal(|ui| {
+                ui.label("Angle");
+                Slider::new(&mut trap.angle, 0.0..=180.).suffix("°").ui(ui);
+            }); }
+            TrapShape::Circle => { ui.horizontal(|ui| {
+                ui.label("Radius");
+                DragValue::new(&mut trap.radius).speed(0.01).range(0.0..=10.).ui(ui);
+            }); }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Distance");
+            DragValue::new(&mut trap.distance).speed(0.01).range(0.001..=10.).ui(ui)
+                .on_hover_text("Orbits that get this far from the trap reach the end of the gradient");
+        });
+
+        // the center follows the cursor until the next click
+        if self.pick_trap_using_cursor {
+            ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+            if ui.input(|input| input.pointer.any_down()) { self.pick_trap_using_cursor = false; }
+        }
+    }
+
+    fn distance_estimation_ui(&mut self, ui: &mut Ui) {
+        let enabled = self.orbit_trap.is_none() && self.average_mode() == 0 && self.variant != Variant::BurningShip;
+        ui.add_enabled_ui(enabled, |ui| {
+            option_checkbox(ui, &mut self.distance_estimation, "Distance estimation", DistanceEstimation::default);
+        }).response.on_disabled_hover_text("Not available with orbit traps, average coloring or the burning ship");
+        let Some(de) = &mut self.distance_estimation else { return; };
+        if !enabled { return; }
+
+        ui.horizontal(|ui| {
+            ui.label("Blend");
+            let arr = [DistanceMode::Outline, DistanceMode::Gradient];
+            let mut index = arr.iter().position(|m| *m == de.mode).unwrap();
+            ComboBox::from_id_salt("distance_mode_selector")
+                .selected_text(de.mode)
+                .show_index(ui, &mut index, arr.len(), |i|arr[i]);
+            de.mode = arr[index];
+        });
+        ui.horizontal(|ui| {
+            ui.label("Thickness");
+            DragValue::new(&mut de.thickness).speed(0.05).range(0.1..=100.).suffix(" px").ui(ui);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Strength");
+            Slider::new(&mut de.strength, 0.0..=1.).ui(ui);
+        });
+    }
+
+    fn interior_ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
+        let enabled = self.orbit_trap.is_none();
+        ui.add_enabled_ui(enabled, |ui| {
+            option_checkbox(ui, &mut self.interior, "Interior coloring", InteriorColoring::default);
+        }).response.on_disabled_hover_text("Orbit traps color the interior too");
+        let distance_available = self.interior_distance_available();
+        let Some(interior) = &mut self.interior else { return; };
+        if !enabled { return; }
+
+        ui.horizontal(|ui| {
+            ui.label("Interior");
+            ComboBox::from_id_salt("interior_mode_selector")
+                .selected_text(interior.mode)
+                .show_ui(ui, |ui| {
+                    for mode in [InteriorMode::Period, InteriorMode::Magnitude, InteriorMode::Angle, InteriorMode::Distance] {
+                        let available = mode != InteriorMode::Distance || distance_available;
+                        ui.add_enabled_ui(available, |ui| ui.selectable_value(&mut interior.mode, mode, mode))
+                            .response.on_disabled_hover_text("Not available for Julia sets or the burning ship");
+                    }
+                });
+        });
+        gradient_editor(ui, &mut interior.gradient, "Interior colors", GRADIENT_PALETTES.as_slice(), palettes);
+    }
+
+    fn equation(&self, z: Complex32, c: Complex32) -> Complex32 {
+        match self.variant {
+            Variant::Mandelbrot => self.raise_power(z) + c,
+            Variant::Modified => self.raise_power(z) - z + c,
+            Variant::BurningShip => self.raise_power(Complex32::new(z.re.abs(), z.im.abs())) + c,
+        }
+    }
+
+    /// same as equation_derivative in mandelbrot.wgsl
+    fn equation_derivative(&self, z: Complex32) -> Complex32 {
+        let d = match self.multi_e {
+            None => 2. * z,
+            Some(e) => e * Complex32::from_polar(z.norm().powf(e - 1.), z.im.atan2(z.re) * (e - 1.)),
+        };
+        if self.variant == Variant::Modified { d - 1. } else { d }
+    }
+
+    /// same as equation_second_derivative in mandelbrot.wgsl
+    fn equation_second_derivative(&self, z: Complex32) -> Complex32 {
+        match self.multi_e {
+            None => Complex32::new(2., 0.),
+            Some(e) => e * (e - 1.) * Complex32::from_polar(z.norm().powf(e - 2.), z.im.atan2(z.re) * (e - 2.)),
+        }
+    }
+
+    fn raise_power(&self, z: Complex32) -> Complex32 {
+        match self.multi_e {
+            None => z * z,
+            // same as cpowf in the shader
+            Some(e) => Complex32::from_polar(z.norm().powf(e), z.im.atan2(z.re) * e),
+        }
+    }
 }
 
 
@@ -79,10 +653,38 @@ impl FractalTrait for MandelbrotFamily {
         }
     }
 
-    fn settings_ui(&mut self, ui: &mut Ui) {
+    fn settings_ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
         ui.horizontal(|ui| {
             ui.label("Iterations");
-            DragValue::new(&mut self.iterations).speed(1).range(1..=3000).ui(ui);
+            // deep zooms need a lot of iterations
+            DragValue::new(&mut self.iterations).speed(1).range(1..=100_000).ui(ui);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Coloring");
+            let arr = [Coloring::Linear, Coloring::Histogram, Coloring::StripeAverage, Coloring::TriangleInequality];
+            let mut index = arr.iter().position(|c| *c == self.coloring).unwrap();
+            ComboBox::from_id_salt("coloring_selector")
+                .selected_text(self.coloring)
+                .show_index(ui, &mut index, arr.len(), |i|arr[i]);
+            self.coloring = arr[index];
+        });
+        if self.coloring == Coloring::StripeAverage {
+            ui.horizontal(|ui| {
+                ui.label("Stripe density");
+                DragValue::new(&mut self.stripe_density).speed(0.05).range(0.1..=100.).ui(ui);
+            });
+        }
+
+        ui.checkbox(&mut self.smooth, "Smooth coloring");
+        gradient_editor(ui, &mut self.gradient, "Colors", GRADIENT_PALETTES.as_slice(), palettes);
+        ui.horizontal(|ui| {
+            ui.label("Offset");
+            Slider::new(&mut self.gradient_offset, 0.0..=1.).ui(ui);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Repeat");
+            DragValue::new(&mut self.gradient_repeat).speed(0.05).range(0.1..=1000.).ui(ui);
         });
 
         ui.horizontal(|ui| {
@@ -130,6 +732,10 @@ impl FractalTrait for MandelbrotFamily {
             }
         }
 
+        self.orbit_trap_ui(ui);
+        self.distance_estimation_ui(ui);
+        self.interior_ui(ui, palettes);
+
         option_checkbox(ui, &mut self.multi_e, "Custom exponent",  || 2.);
         if let Some(e) = &mut self.multi_e {
             ui.horizontal(|ui| {
@@ -143,37 +749,181 @@ impl FractalTrait for MandelbrotFamily {
         Shader::Mandelbrot(MandelbrotShader::Product(
             self.variant,
             if self.multi_e.is_some() {Multi::Enabled} else {Multi::Disabled},
+            // the visualizer switches to double precision when zoomed in enough
+            Precision::Single,
         ))
     }
 
-    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
-        // todo: return to this
-        let escape_radius = match self.julia_c {
-            None => 2.,
-            Some(c) => {
-                // we solve R > 0 such that R^2 - R == |c|
-                // todo: escape radius is too small for e < 2 and (maybe) too big for e > 2
-                (1. + (1. + 4. * c.abs()).sqrt()) / 2.
-            }
-        };
+    fn uniform_size(&self) -> u64 { MandelbrotUniform::min_size().get() }
 
+    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        let trap = self.orbit_trap.unwrap_or_default();
         buffer.write(&MandelbrotUniform {
             c: self.julia_c.unwrap_or_default().to_gvec2(),
             iterations: self.iterations,
-            escape_radius,
+            escape_radius: self.escape_radius(),
             exp: self.multi_e.unwrap_or(2.),
-            // 0 if not in julia mode
-            // 2 if not picking
-            // 0,1,2 if picking
-            julia: if self.julia_c.is_none() {0}
-                else if self.pick_c_using_cursor.0 { self.pick_c_using_cursor.1 as u32 } else { 2 },
+            julia: self.julia_mode(),
+            one: 1.,
+            distance_estimation: self.uses_distance_estimation() as u32,
+            trap_shape: self.orbit_trap.map_or(0, |trap| trap.shape as u32),
+            trap_radius: trap.radius,
+            trap_center: trap.center.to_gvec2(),
+            trap_direction: trap.direction().to_gvec2(),
+            interior_mode: self.interior_mode(),
+            average_mode: self.average_mode(),
+            stripe_density: self.stripe_density,
+        }).unwrap();
+    }
+
+    fn coloring_size(&self) -> u64 { MandelbrotColoring::min_size().get() }
+
+    fn fill_coloring_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        let de = self.distance_estimation.unwrap_or_default();
+        buffer.write(&MandelbrotColoring {
+            mode: self.coloring as u32,
+            smooth_iterations: self.smooth as u32,
+            offset: self.gradient_offset,
+            repeat: self.gradient_repeat,
+            trap_distance: self.orbit_trap.map_or(1., |trap| trap.distance),
+            de_mode: de.mode as u32,
+            de_thickness: de.thickness,
+            de_strength: de.strength,
         }).unwrap();
     }
 
-    fn draw_extra(&mut self, _painter: &Painter, mouse_pos: Option<Vec2>) {
+    fn uniform_offsets(&self) -> &'static [u64] { MandelbrotUniform::MEMBER_OFFSETS }
+
+    fn coloring_offsets(&self) -> &'static [u64] { MandelbrotColoring::MEMBER_OFFSETS }
+
+    fn gradients(&self) -> Vec<&Gradient> {
+        // the interior gradient is the second one
+        let mut gradients = vec![&self.gradient];
+        gradients.extend(self.interior.as_ref().map(|interior| &interior.gradient));
+        gradients
+    }
+
+    fn histogram_bins(&self) -> u32 {
+        // orbit traps don't use the iterations
+        match (self.coloring, self.orbit_trap) {
+            (Coloring::Histogram, None) => self.iterations,
+            _ => 0,
+        }
+    }
+
+    fn evaluate(&self, uv: GVec2, pixel_size: f32, cycle_offset: f32) -> GVec4 {
+        let mut uv = uv.to_c32();
+        let julia = self.julia_mode();
+        // the burning ship is traditionally flipped on the y axis
+        if self.variant == Variant::BurningShip && julia == 0 {
+            uv = uv.conj();
+        }
+
+        let c = self.julia_c.unwrap_or_default();
+        let mut escape = match julia {
+            0 => self.compute_iterations(Complex32::ZERO, uv, self.escape_radius(), self.iterations, false, pixel_size),
+            1 => {
+                let mandelbrot = self.compute_iterations(Complex32::ZERO, uv, 2., self.iterations / 2, false, pixel_size);
+                let julia = self.compute_iterations(uv, c, self.escape_radius(), self.iterations / 2, true, pixel_size);
+                Escape { iterations: mandelbrot.iterations + julia.iterations, ..julia }
+            }
+            _ => self.compute_iterations(uv, c, self.escape_radius(), self.iterations, true, pixel_size),
+        };
+        if self.orbit_trap.is_some() {
+            escape.distance = escape.trap;
+        }
+        self.colorize(escape.iterations, escape.z, escape.distance, cycle_offset)
+    }
+
+    fn cost(&self) -> u32 {
+        // raising to a real power is a lot slower than squaring
+        self.iterations * if self.multi_e.is_some() { 4 } else { 1 }
+    }
+
+    fn draw_extra(&mut self, painter: &Painter, transform: &ScreenTransform, mouse_pos: Option<Vec2>) {
         if let (Some(mouse_pos),(true, _),Some(c)) = (mouse_pos, &self.pick_c_using_cursor, &mut self.julia_c) {
             *c = mouse_pos.to_c32();
         }
+        if let (Some(mouse_pos), true, Some(trap)) = (mouse_pos, self.pick_trap_using_cursor, &mut self.orbit_trap) {
+            trap.center = mouse_pos.to_c32();
+        }
+        if let Some(trap) = &self.orbit_trap {
+            trap.draw(painter, transform);
+            if let Some(hover) = painter.ctx().pointer_hover_pos() && trap.handle_contains(transform, hover) {
+                painter.ctx().set_cursor_icon(CursorIcon::Grab);
+            }
+        }
+    }
+
+    fn grab_handle(&mut self, transform: &ScreenTransform, pos: Pos2) -> bool {
+        self.orbit_trap.is_some_and(|trap| trap.handle_contains(transform, pos))
+    }
+
+    fn drag_handle(&mut self, pos: Vec2) {
+        if let Some(trap) = &mut self.orbit_trap {
+            trap.center = pos.to_c32();
+        }
+    }
+
+    fn perturbation(&self) -> Option<Perturbation> {
+        // rendering both at once would need two reference orbits
+        if self.multi_e.is_some() || self.julia_mode() == PickCMode::Both as u32 { return None; }
+
+        let (shader, formula) = match self.variant {
+            Variant::Mandelbrot => (MandelbrotPerturbationShader::Mandelbrot, Formula::Mandelbrot),
+            Variant::Modified => (MandelbrotPerturbationShader::Modified, Formula::Modified),
+            Variant::BurningShip => return None,
+        };
+
+        Some(Perturbation {
+            shader: Shader::MandelbrotPerturbation(shader),
+            formula,
+            julia_c: self.julia_c,
+            iterations: self.iterations,
+            escape_radius: self.escape_radius(),
+        })
+    }
+}
+
+impl From<Coloring> for WidgetText {
+    fn from(value: Coloring) -> Self {
+        match value {
+            Coloring::Linear => "Linear".into(),
+            Coloring::Histogram => "Histogram".into(),
+            Coloring::StripeAverage => "Stripe average".into(),
+            Coloring::TriangleInequality => "Triangle inequality average".into(),
+        }
+    }
+}
+
+impl From<TrapShape> for WidgetText {
+    fn from(value: TrapShape) -> Self {
+        match value {
+            TrapShape::Point => "Point".into(),
+            TrapShape::Line => "Line".into(),
+            TrapShape::Cross => "Cross".into(),
+            TrapShape::Circle => "Circle".into(),
+        }
+    }
+}
+
+impl From<DistanceMode> for WidgetText {
+    fn from(value: DistanceMode) -> Self {
+        match value {
+            DistanceMode::Outline => "Outline".into(),
+            DistanceMode::Gradient => "Distance gradient".into(),
+        }
+    }
+}
+
+impl From<InteriorMode> for WidgetText {
+    fn from(value: InteriorMode) -> Self {
+        match value {
+            InteriorMode::Period => "Period".into(),
+            InteriorMode::Magnitude => "Final |z|".into(),
+            InteriorMode::Angle => "Final angle of z".into(),
+            InteriorMode::Distance => "Interior distance".into(),
+        }
     }
 }
 
@@ -186,3 +936,140 @@ impl From<Variant> for WidgetText {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_coloring(coloring: Coloring) -> MandelbrotFamily {
+        MandelbrotFamily { coloring, stripe_density: 1., ..MandelbrotFamily::default_mandelbrot() }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn stripe_average_terms() {
+        let fractal = with_coloring(Coloring::StripeAverage);
+        // 0.5 sin(arg z) + 0.5
+        let mut average = Average::default();
+        for (z, term) in [(Complex32::I, 1.), (-Complex32::I, 0.), (Complex32::ONE, 0.5)] {
+            let next = fractal.add_average_term(average, z, Complex32::ZERO);
+            assert_close(next.sum - average.sum, term);
+            assert_eq!(next.count, average.count + 1.);
+            assert_eq!((next.previous_sum, next.previous_count), (average.sum, average.count));
+            average = next;
+        }
+        assert_close(average.sum, 1.5);
+    }
+
+    #[test]
+    fn triangle_inequality_terms() {
+        let fractal = with_coloring(Coloring::TriangleInequality);
+        // |z| between ||z - c| - |c|| and |z - c| + |c|
+        let sqrt_5 = 5f32.sqrt();
+        for (z, c, term) in [(Complex32::new(2., 0.), 1., 1.), (Complex32::ZERO, 1., 0.), (Complex32::new(0., 2.), 1., (3. - sqrt_5) / 2.)] {
+            let average = fractal.add_average_term(Average::default(), z, Complex32::new(c, 0.));
+            assert_close(average.sum, term);
+            assert_eq!(average.count, 1.);
+        }
+        // the bounds are the same so the term is skipped, but it still moves the previous average
+        let average = Average { sum: 2., count: 3., previous_sum: 1., previous_count: 2. };
+        for (z, c) in [(Complex32::new(0.5, 0.5), Complex32::ZERO), (Complex32::ONE, Complex32::ONE)] {
+            let skipped = fractal.add_average_term(average, z, c);
+            assert_eq!((skipped.sum, skipped.count, skipped.previous_sum, skipped.previous_count), (2., 3., 2., 3.));
+        }
+    }
+
+    #[test]
+    fn smooth_average_interpolates_across_the_last_iteration() {
+        let fractal = with_coloring(Coloring::StripeAverage);
+        let radius = fractal.escape_radius();
+        assert_eq!(radius, LARGE_ESCAPE_RADIUS);
+        let average = Average { sum: 3., count: 4., previous_sum: 1., previous_count: 2. };
+        // log(log|z| / log r) / log 2 is 0 when |z| is the escape radius and 1 when it's squared
+        for (z, expected) in [(radius, 0.75), (radius.powf(2f32.sqrt()), 0.625), (radius * radius, 0.5), (radius.powi(3), 0.5)] {
+            assert_close(fractal.smooth_average(average, Complex32::new(0., z)), expected);
+        }
+    }
+
+    #[test]
+    fn averages_of_a_real_orbit() {
+        // c = 1 goes 1, 2, 5, 26, 677 so every z is a positive real
+        for (coloring, expected) in [(Coloring::StripeAverage, 0.5), (Coloring::TriangleInequality, 1.)] {
+            let fractal = with_coloring(coloring);
+            let escape = fractal.compute_iterations(Complex32::ZERO, Complex32::ONE, fractal.escape_radius(), 100, false, 1.);
+            assert_eq!(escape.iterations, 5);
+            assert_close(escape.distance, expected);
+        }
+    }
+
+    #[test]
+    fn iterations_of_known_points() {
+        let fractal = MandelbrotFamily { smooth: false, ..MandelbrotFamily::default_mandelbrot() };
+        let radius = fractal.escape_radius();
+        assert_eq!(radius, 2.);
+        // c = 1 goes 1, 2, 5 and 2 is still inside the escape radius
+        let escape = fractal.compute_iterations(Complex32::ZERO, Complex32::ONE, radius, 100, false, 1.);
+        assert_eq!((escape.iterations, escape.z), (3, Complex32::new(5., 0.)));
+        // 0, -1 and i end up in cycles, -2 stays on the escape radius
+        for c in [Complex32::ZERO, -Complex32::ONE, Complex32::I, Complex32::new(-2., 0.)] {
+            assert_eq!(fractal.compute_iterations(Complex32::ZERO, c, radius, 100, false, 1.).iterations, 100, "{c}");
+        }
+        // the julia set of c = 0 is the unit circle
+        for (z0, iterations) in [(Complex32::new(0.5, 0.5), 100), (Complex32::new(1.5, 0.), 1)] {
+            assert_eq!(fractal.compute_iterations(z0, Complex32::ZERO, radius, 100, true, 1.).iterations, iterations, "{z0}");
+        }
+    }
+
+    #[test]
+    fn periods_of_known_cycles() {
+        let fractal = MandelbrotFamily {
+            interior: Some(InteriorColoring { mode: InteriorMode::Period, ..InteriorColoring::default() }),
+            ..MandelbrotFamily::default_mandelbrot()
+        };
+        // the main cardioid, the period 2 bulb and the douady rabbit
+        for (c, period) in [(Complex32::ZERO, 1.), (-Complex32::ONE, 2.), (Complex32::new(-0.1226, 0.7449), 3.)] {
+            let escape = fractal.compute_iterations(Complex32::ZERO, c, fractal.escape_radius(), 300, false, 1.);
+            assert_eq!(escape.iterations, 300);
+            assert_eq!(escape.distance, period, "{c}");
+        }
+    }
+
+    #[test]
+    fn interior_distance_of_the_center() {
+        let fractal = MandelbrotFamily {
+            interior: Some(InteriorColoring { mode: InteriorMode::Distance, ..InteriorColoring::default() }),
+            ..MandelbrotFamily::default_mandelbrot()
+        };
+        // the cycle of c = 0 is z = 0 with every derivative but the second one being 0, so it's 1 / 2 in pixels of 0.1
+        let escape = fractal.compute_iterations(Complex32::ZERO, Complex32::ZERO, fractal.escape_radius(), 300, false, 0.1);
+        assert_close(escape.distance, 5.);
+        // c = 0.25 is on the boundary, the cycle isn't attracting
+        assert_eq!(fractal.interior_distance(Complex32::new(0.5, 0.), Complex32::new(0.25, 0.), 1, 1.), -1.);
+    }
+
+    #[test]
+    fn smooth_colorize_continues_the_iterations() {
+        let fractal = MandelbrotFamily { gradient_repeat: 1., ..MandelbrotFamily::default_mandelbrot() };
+        let radius = fractal.escape_radius();
+        // squaring |z| one more time is one more iteration
+        let at_radius = fractal.colorize(10, Complex32::new(radius, 0.), 0., 0.);
+        let squared = fractal.colorize(11, Complex32::new(radius * radius, 0.), 0., 0.);
+        assert!(at_radius.abs_diff_eq(squared, 1e-5), "{at_radius} != {squared}");
+        let expected: GVec4 = fractal.gradient.sample(10. / 300.).to_normalized_gamma_f32().into();
+        assert!(at_radius.abs_diff_eq(expected, 1e-5), "{at_radius} != {expected}");
+    }
+
+    #[test]
+    fn colorize_uses_the_cycle_offset() {
+        let fractal = MandelbrotFamily { smooth: false, gradient_repeat: 1., ..MandelbrotFamily::default_mandelbrot() };
+        let color = fractal.colorize(60, Complex32::new(3., 0.), 0., 0.9);
+        let expected: GVec4 = fractal.gradient.sample(0.1).to_normalized_gamma_f32().into();
+        assert!(color.abs_diff_eq(expected, 1e-5), "{color} != {expected}");
+        // the interior isn't cycled
+        let last: GVec4 = fractal.gradient.sample(1.).to_normalized_gamma_f32().into();
+        assert_eq!(fractal.colorize(300, Complex32::ZERO, 0., 0.9), last);
+    }
+}
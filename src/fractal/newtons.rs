@@ -1,29 +1,38 @@
 use std::sync::LazyLock;
 
-use bytemuck::bytes_of;
-use ecolor::{hex_color, Color32};
-use eframe::egui::{Button, CollapsingHeader, CursorIcon, DragValue, Grid, Painter, Ui, vec2, Vec2, Widget};
+use ecolor::{hex_color, Color32, Hsva};
+use eframe::egui::{Button, CollapsingHeader, CursorIcon, DragValue, Grid, Painter, Slider, Ui, vec2, Vec2, Widget};
+use eframe::egui::color_picker::{self, Alpha};
 use encase::UniformBuffer;
 use glam::{Vec2 as GVec2, Vec4 as GVec4};
 use num_complex::Complex32;
 use rand::Rng;
 use encase::ShaderType;
-use crate::app::widgets::{c32_ui_full, palette_editor};
+use crate::app::widgets::{c32_ui_full, option_checkbox, palette_editor};
+use crate::app::library::PaletteLibrary;
+use crate::app::visualizer::ScreenTransform;
 use crate::fractal::FractalTrait;
-use crate::wgsl::{Complex32Ext, Shader, Vec2Ext};
+use crate::wgsl::{uniform_structs, Complex32Ext, DataSource, MemberOffsets, NewtonsShader, Shader, Vec2Ext};
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Newtons {
     iterations: u32,
-    /// 1..=5 roots
+    /// 2..=MAX_ROOTS roots
     roots: Vec<Complex32>,
     /// u32 is the index of the root being picked
     // extra parameters
     a: Complex32,
     c: Complex32,
     threshold: f32, // can be infinity
+    /// color of each root, roots without one get [extra_root_color]
     #[serde(default = "default_palette")]
-    colors: [Color32; 5],
+    colors: Vec<Color32>,
+    /// from -1 to 1, pixels that take longer to converge are lightened if it's negative and darkened if it's positive
+    #[serde(default)]
+    shading: f32,
+    /// Some if the pixels that didn't converge get their own color instead of the closest root's
+    #[serde(default)]
+    non_converged_color: Option<Color32>,
 
     #[serde(skip)]
     pick_using_cursor: Option<Pick>,
@@ -37,16 +46,30 @@ enum Pick {
     C,
 }
 
-#[derive(ShaderType)]
-struct NewtonsUniform {
-    colors: [GVec4;5],
-    a: GVec2,
-    c: GVec2,
-    nr_roots: u32,
-    max_iterations: u32,
-    threshold: f32,
+/// the roots are passed in a storage buffer so there can be lots of them, but the polynomial's coefficients lose precision quickly
+const MAX_ROOTS: usize = 32;
+
+uniform_structs! {
+    struct NewtonsUniform {
+        a: GVec2 = 0,
+        c: GVec2 = 8,
+        nr_roots: u32 = 16,
+        max_iterations: u32 = 20,
+        threshold: f32 = 24,
+        _padding: f32 = 28,
+    }
+
+    struct NewtonsColoring {
+        colors: [GVec4; MAX_ROOTS] = 0,
+        color_non_converged: GVec4 = 512,
+        shading: f32 = 528,
+        non_converged: u32 = 532,
+    }
 }
 
+/// the newton step has to get this small for the pixel to converge, same as newtons.wgsl
+const TOLERANCE: f32 = 1e-4;
+
 impl Default for Newtons {
     fn default() -> Self {
         Self {
@@ -56,16 +79,96 @@ impl Default for Newtons {
             c: Complex32::ZERO,
             threshold: f32::INFINITY,
             colors: default_palette(),
+            shading: 0.5,
+            non_converged_color: None,
 
             pick_using_cursor: None,
         }
     }
 }
 
+impl Newtons {
+    /// coefficients of the polynomial with the given roots, from the lowest power
+    fn polynomial_coefficients(&self) -> Vec<Complex32> {
+        let mut polynomial_coef = vec![Complex32::ZERO; self.roots.len() + 1];
+        polynomial_coef[0] = Complex32::ONE;
+        for (i,root) in self.roots.iter().enumerate() {
+            for j in (0..=i+1).rev() {
+                if j == 0 {
+                    polynomial_coef[j] = - root * polynomial_coef[j];
+                } else {
+                    polynomial_coef[j] = polynomial_coef[j-1] - root * polynomial_coef[j];
+                }
+            }
+        }
+        polynomial_coef
+    }
+
+    /// same as newtons_method in newtons.wgsl, returns the closest root and the smooth number of iterations if it converged
+    fn closest_root(&self, mut z: Complex32, coefficients: &[Complex32]) -> (Option<usize>, Option<f32>) {
+        let mut iterations = None;
+        for iteration in 0..self.iterations {
+            let mut zp = Complex32::ONE;
+            let mut prev = Complex32::ZERO;
+            let mut f = Complex32::ZERO;
+            let mut fd = Complex32::ZERO;
+            for (i, coef) in coefficients.iter().enumerate() {
+                f += coef * zp;
+                fd += coef * prev * i as f32;
+                prev = zp;
+                zp = self.a * zp * z + self.c;
+            }
+            let step = f / fd;
+            z -= step;
+            // check the shader
+            let d = step.norm();
+            if d < TOLERANCE {
+                iterations = Some((iteration + 1) as f32 - (d.max(1e-30).ln() / TOLERANCE.ln()).log2().clamp(0., 1.));
+                break;
+            }
+        }
+
+        let mut closest = None;
+        let mut closest_dist = self.threshold;
+        for (i, root) in self.roots.iter().enumerate() {
+            let d = (z - root).norm();
+            if d < closest_dist {
+                closest = Some(i);
+                closest_dist = d;
+            }
+        }
+        (closest, iterations)
+    }
+
+    fn root_color(&self, root: usize) -> Color32 {
+        self.colors.get(root).copied().unwrap_or_else(|| extra_root_color(root))
+    }
+
+    /// same as colorize in newtons.wgsl
+    fn colorize(&self, root: Option<usize>, iterations: Option<f32>) -> GVec4 {
+        if let (None, Some(color)) = (iterations, self.non_converged_color) {
+            return color.to_normalized_gamma_f32().into();
+        }
+        // the shader discards the pixel if it isn't close to any root
+        let Some(root) = root else { return GVec4::ZERO; };
+        let n = iterations.unwrap_or(self.iterations as f32);
+        let t = (1. + n).log2() / (1. + self.iterations as f32).log2();
+        let color: GVec4 = self.root_color(root).to_normalized_gamma_f32().into();
+        let shade = if self.shading > 0. { GVec4::new(0., 0., 0., 1.) } else { GVec4::ONE };
+        color.lerp(shade, self.shading.abs() * t)
+    }
+}
+
 impl FractalTrait for Newtons {
     fn label(&mut self) ->  &'static str { "Newton's Fractal" }
 
-    fn settings_ui(&mut self, ui: &mut Ui) {
+    fn settings_ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
+        // the palette editor shows one color per root
+        while self.colors.len() < self.roots.len() {
+            self.colors.push(extra_root_color(self.colors.len()));
+        }
+        self.colors.truncate(self.roots.len());
+
         ui.horizontal(|ui|{
             ui.label("Iterations");
             DragValue::new(&mut self.iterations).speed(1).range(0..=3000).ui(ui);
@@ -78,7 +181,7 @@ impl FractalTrait for Newtons {
 
         ui.horizontal(|ui|{
             ui.label("Roots");
-            if ui.add_enabled(self.roots.len() < 5, Button::new("+").small().min_size(vec2(15.,0.))).clicked() {
+            if ui.add_enabled(self.roots.len() < MAX_ROOTS, Button::new("+").small().min_size(vec2(15.,0.))).clicked() {
                 let mut rand = rand::rng();
                 self.roots.push(Complex32::new(rand.random::<f32>() * 2. - 1., rand.random::<f32>() * 2. - 1.)) ;
             }
@@ -123,50 +226,67 @@ impl FractalTrait for Newtons {
             });
         });
 
-        palette_editor(ui, &mut self.colors, "Colors", COLOR_PALETTES.as_slice());
+        palette_editor(ui, &mut self.colors, "Colors", COLOR_PALETTES.as_slice(), palettes);
+        ui.horizontal(|ui| {
+            ui.label("Shading");
+            Slider::new(&mut self.shading, -1.0..=1.).ui(ui)
+                .on_hover_text("Lightens (negative) or darkens (positive) the pixels that take longer to converge");
+        });
+        ui.horizontal(|ui| {
+            option_checkbox(ui, &mut self.non_converged_color, "Not converged", || Color32::BLACK);
+            if let Some(color) = &mut self.non_converged_color {
+                color_picker::color_edit_button_srgba(ui, color, Alpha::Opaque);
+            }
+        });
     }
 
-    fn get_shader(&self) -> Shader { Shader::Newtons }
-
-    fn fill_uniform_buffer(&self, buffer: UniformBuffer<&mut [u8]>) {
-        let mut polynomial_coef: [Complex32; 6] = [Complex32::ZERO;6];
-        polynomial_coef[0] = Complex32::ONE;
-        for (i,root) in self.roots.iter().enumerate() {
-            for j in (0..=i+1).rev() {
-                if j == 0 {
-                    polynomial_coef[j] = - root * polynomial_coef[j];
-                } else {
-                    polynomial_coef[j] = polynomial_coef[j-1] - root * polynomial_coef[j];
-                }
-            }
-        }
+    fn get_shader(&self) -> Shader { Shader::Newtons(NewtonsShader::Product(DataSource::Storage)) }
 
-        let extra_item = [Complex32::ZERO];
-        let interweaved_arrays = self.roots.iter().chain(extra_item.iter())
-            .zip(polynomial_coef);
-
-        // we manually write the array cause it's faster and simpler so we access the inner buffer
-        let buffer = buffer.into_inner();
-        let mut offset = 0;
-        for (root, coefficients) in interweaved_arrays {
-            buffer[offset..offset+8].copy_from_slice(bytes_of(&[root.re, root.im]));
-            offset+=8;
-            buffer[offset..offset+8].copy_from_slice(bytes_of(&[coefficients.re, coefficients.im]));
-            offset+=8;
-        }
+    fn uniform_size(&self) -> u64 { NewtonsUniform::min_size().get() }
 
-        // we write the rest of the buffer normally
-        UniformBuffer::new(&mut buffer[96..]).write(&NewtonsUniform {
-            colors: self.colors.map(|c|c.to_normalized_gamma_f32().into()),
+    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&NewtonsUniform {
             a: self.a.to_gvec2(),
             c: self.c.to_gvec2(),
             nr_roots: self.roots.len() as u32,
             max_iterations: self.iterations,
             threshold: self.threshold,
+            _padding: 0.,
+        }).unwrap()
+    }
+
+    fn coloring_size(&self) -> u64 { NewtonsColoring::min_size().get() }
+
+    fn fill_coloring_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&NewtonsColoring {
+            colors: std::array::from_fn(|i| self.root_color(i).to_normalized_gamma_f32().into()),
+            color_non_converged: self.non_converged_color.unwrap_or_default().to_normalized_gamma_f32().into(),
+            shading: self.shading,
+            non_converged: self.non_converged_color.is_some() as u32,
         }).unwrap()
     }
 
-    fn draw_extra(&mut self, _painter: &Painter, mouse_pos: Option<Vec2>) {
+    fn uniform_offsets(&self) -> &'static [u64] { NewtonsUniform::MEMBER_OFFSETS }
+
+    fn coloring_offsets(&self) -> &'static [u64] { NewtonsColoring::MEMBER_OFFSETS }
+
+    fn params(&self) -> Vec<[f32; 4]> {
+        // the polynomial has one more coefficient than roots
+        self.polynomial_coefficients().into_iter().enumerate().map(|(i, coefficient)| {
+            let root = self.roots.get(i).copied().unwrap_or_default();
+            [root.re, root.im, coefficient.re, coefficient.im]
+        }).collect()
+    }
+
+    fn evaluate(&self, uv: GVec2, _pixel_size: f32, _cycle_offset: f32) -> GVec4 {
+        let (root, iterations) = self.closest_root(uv.to_c32(), &self.polynomial_coefficients());
+        self.colorize(root, iterations)
+    }
+
+    // every iteration evaluates the polynomial and its derivative
+    fn cost(&self) -> u32 { self.iterations * (self.roots.len() as u32 + 1) }
+
+    fn draw_extra(&mut self, _painter: &Painter, _transform: &ScreenTransform, mouse_pos: Option<Vec2>) {
         if let (Some(mouse_pos),Some(pick)) = (mouse_pos, &self.pick_using_cursor) {
             match pick {
                 Pick::Root(index) => {
@@ -181,7 +301,12 @@ impl FractalTrait for Newtons {
     }
 }
 
-fn default_palette() -> [Color32;5] { COLOR_PALETTES[0] }
+fn default_palette() -> Vec<Color32> { COLOR_PALETTES[0].to_vec() }
+
+/// colors of roots past the end of the palette, the hue moves by the golden ratio so every root gets a different one
+fn extra_root_color(root: usize) -> Color32 {
+    Hsva::new((root as f32 * 0.618_034).fract(), 0.6, 0.9, 1.).into()
+}
 
 pub static COLOR_PALETTES: LazyLock<Vec<[Color32;5]>> = LazyLock::new(|| vec![
     // https://coolors.co/palette/f79256-fbd1a2-7dcfb6-00b2ca-1d4e89
@@ -192,3 +317,73 @@ pub static COLOR_PALETTES: LazyLock<Vec<[Color32;5]>> = LazyLock::new(|| vec![
     [hex_color!("0081a7"),hex_color!("00afb9"),hex_color!("fdfcdc"),hex_color!("fed9b7"),hex_color!("f07167")],
     // todo: more palettes
 ]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_roots(roots: &[Complex32]) -> Newtons {
+        Newtons { roots: roots.to_vec(), ..Newtons::default() }
+    }
+
+    #[test]
+    fn coefficients_from_the_roots() {
+        // (z - 1)(z + 1) = z^2 - 1
+        let coefficients = with_roots(&[Complex32::ONE, -Complex32::ONE]).polynomial_coefficients();
+        assert_eq!(coefficients, [-Complex32::ONE, Complex32::ZERO, Complex32::ONE]);
+        // the default roots are close to the cube roots of unity, z^3 - 1
+        let coefficients = Newtons::default().polynomial_coefficients();
+        for (coefficient, expected) in coefficients.iter().zip([-1., 0., 0., 1.]) {
+            assert!((coefficient - expected).norm() < 1e-3, "{coefficient} != {expected}");
+        }
+    }
+
+    #[test]
+    fn points_converge_to_the_closest_root() {
+        let fractal = Newtons::default();
+        let coefficients = fractal.polynomial_coefficients();
+        for (i, root) in fractal.roots.iter().enumerate() {
+            let (closest, iterations) = fractal.closest_root(root * 1.2, &coefficients);
+            assert_eq!(closest, Some(i));
+            assert!(iterations.is_some_and(|n| n > 0. && n < fractal.iterations as f32));
+        }
+        // the derivative of z^2 - 1 is 0 at the origin, so the step isn't finite
+        let fractal = with_roots(&[Complex32::ONE, -Complex32::ONE]);
+        assert_eq!(fractal.closest_root(Complex32::ZERO, &fractal.polynomial_coefficients()), (None, None));
+    }
+
+    #[test]
+    fn threshold_limits_the_distance_to_the_root() {
+        let fractal = Newtons { iterations: 1, threshold: 0.1, ..with_roots(&[Complex32::ONE, -Complex32::ONE]) };
+        let coefficients = fractal.polynomial_coefficients();
+        // one step from 3 lands on 5/3
+        let (closest, iterations) = fractal.closest_root(Complex32::new(3., 0.), &coefficients);
+        assert_eq!((closest, iterations), (None, None));
+        let fractal = Newtons { threshold: 1., ..fractal };
+        assert_eq!(fractal.closest_root(Complex32::new(3., 0.), &coefficients).0, Some(0));
+    }
+
+    #[test]
+    fn colorize_shades_by_iterations() {
+        let fractal = Newtons { shading: 1., ..Newtons::default() };
+        let root_color: GVec4 = fractal.root_color(1).to_normalized_gamma_f32().into();
+        assert_eq!(fractal.colorize(Some(1), Some(0.)), root_color);
+        // the pixels that take every iteration are black
+        assert_eq!(fractal.colorize(Some(1), None), GVec4::new(0., 0., 0., 1.));
+        let lightened = Newtons { shading: -1., ..Newtons::default() };
+        assert_eq!(lightened.colorize(Some(1), None), GVec4::ONE);
+        // transparent if it isn't close to any root
+        assert_eq!(fractal.colorize(None, Some(3.)), GVec4::ZERO);
+        let with_color = Newtons { non_converged_color: Some(Color32::RED), ..Newtons::default() };
+        assert_eq!(with_color.colorize(Some(1), None), GVec4::new(1., 0., 0., 1.));
+    }
+
+    #[test]
+    fn extra_roots_get_different_colors() {
+        let fractal = Newtons { colors: vec![], ..Newtons::default() };
+        let colors: Vec<Color32> = (0..MAX_ROOTS).map(|root| fractal.root_color(root)).collect();
+        for (i, color) in colors.iter().enumerate() {
+            assert!(!colors[..i].contains(color), "root {i}");
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use eframe::egui::{Button, CursorIcon, DragValue, Painter, Slider, Ui, Vec2, Widget};
+use encase::{ShaderType, UniformBuffer};
+use num_complex::Complex32;
+use glam::{Vec2 as GVec2, Vec4 as GVec4};
+use crate::app::gradient::{cycle_position, Gradient, GRADIENT_PALETTES};
+use crate::app::library::PaletteLibrary;
+use crate::app::widgets::{c32_ui_full, gradient_editor};
+use crate::app::visualizer::ScreenTransform;
+use crate::fractal::FractalTrait;
+use crate::wgsl::{uniform_structs, Complex32Ext, MemberOffsets, Shader, Vec2Ext};
+
+/// z = z^2 + c + p * previous z, the previous z gives it the feathery look
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Phoenix {
+    iterations: u32,
+    /// Some if the fractal is the julia version with the constant c, otherwise the pixel is c
+    julia_c: Option<Complex32>,
+    p: Complex32,
+    /// continuous iteration count instead of whole iterations
+    smooth: bool,
+    gradient: Gradient,
+    /// added to the gradient's position
+    gradient_offset: f32,
+    /// how many times the gradient is repeated from 0 to max iterations
+    gradient_repeat: f32,
+
+    #[serde(skip)]
+    pick_using_cursor: Option<PickParam>,
+}
+
+/// parameter that follows the cursor until the next click
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PickParam { C, P }
+
+/// the continuous iteration count is only accurate if z got a lot bigger than the escape radius, same as the mandelbrot
+const LARGE_ESCAPE_RADIUS: f32 = 256.;
+
+// check shader
+uniform_structs! {
+    struct PhoenixUniform {
+        c: GVec2 = 0,
+        p: GVec2 = 8,
+        iterations: u32 = 16,
+        escape_radius: f32 = 20,
+        julia: u32 = 24,
+        _padding: u32 = 28,
+    }
+
+    struct PhoenixColoring {
+        smooth_iterations: u32 = 0,
+        offset: f32 = 4,
+        repeat: f32 = 8,
+        _padding: f32 = 12,
+    }
+}
+
+impl Default for Phoenix {
+    // the classic phoenix julia set
+    fn default() -> Self {
+        Self {
+            iterations: 300,
+            julia_c: Some(Complex32::new(0.5667, 0.)),
+            p: Complex32::new(-0.5, 0.),
+            smooth: true,
+            gradient: GRADIENT_PALETTES[1].clone(),
+            gradient_offset: 0.,
+            gradient_repeat: 4.,
+            pick_using_cursor: None,
+        }
+    }
+}
+
+impl Phoenix {
+    fn escape_radius(&self) -> f32 {
+        // max(2, |c|, |p|), c is at most 2 away from the origin in the parameter plane
+        let c = self.julia_c.map_or(2., |c| c.norm());
+        let radius = 2f32.max(c).max(self.p.norm());
+        if self.smooth { radius.max(LARGE_ESCAPE_RADIUS) } else { radius }
+    }
+
+    /// same as compute_iterations in phoenix.wgsl
+    fn compute_iterations(&self, z0: Complex32, c: Complex32) -> (u32, Complex32) {
+        let r_sq = self.escape_radius().powi(2);
+        let (mut z, mut previous) = (z0, Complex32::ZERO);
+        let mut iterations = 0;
+        while z.norm_sqr() <= r_sq && iterations < self.iterations {
+            (z, previous) = (z * z + c + self.p * previous, z);
+            iterations += 1;
+        }
+        (iterations, z)
+    }
+
+    /// same as colorize in phoenix.wgsl
+    fn colorize(&self, iterations: u32, z: Complex32, cycle_offset: f32) -> GVec4 {
+        if iterations >= self.iterations {
+            return self.gradient.sample(1.).to_normalized_gamma_f32().into();
+        }
+        let mut n = iterations as f32;
+        if self.smooth {
+            let log_ratio = z.norm().ln() / self.escape_radius().ln();
+            n -= log_ratio.max(1.).log2();
+        }
+        let t = n / self.iterations as f32 * self.gradient_repeat + self.gradient_offset;
+        // 1 is kept so the whole gradient is used when it isn't repeated
+        let t = if t > 1. { t.fract() } else { t };
+        self.gradient.sample(cycle_position(t, cycle_offset)).to_normalized_gamma_f32().into()
+    }
+}
+
+impl FractalTrait for Phoenix {
+    fn label(&mut self) -> &'static str {
+        if self.julia_c.is_some() { "Phoenix Julia Set" } else { "Phoenix Fractal" }
+    }
+
+    fn settings_ui(&mut self, ui: &mut Ui, palettes: &mut PaletteLibrary) {
+        if self.pick_using_cursor.is_some() {
+            ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+            if ui.input(|input| input.pointer.any_down()) { self.pick_using_cursor = None; }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Iterations");
+            DragValue::new(&mut self.iterations).speed(1).range(1..=100_000).ui(ui);
+        });
+
+        ui.checkbox(&mut self.smooth, "Smooth coloring");
+        gradient_editor(ui, &mut self.gradient, "Colors", GRADIENT_PALETTES.as_slice(), palettes);
+        ui.horizontal(|ui| {
+            ui.label("Offset");
+            Slider::new(&mut self.gradient_offset, 0.0..=1.).ui(ui);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Repeat");
+            DragValue::new(&mut self.gradient_repeat).speed(0.05).range(0.1..=1000.).ui(ui);
+        });
+
+        ui.horizontal(|ui| {
+            if c32_ui_full(ui, "P", &mut self.p, Some(0.01), None).clicked() {
+                self.pick_using_cursor = Some(PickParam::P);
+            }
+        });
+        if self.julia_c.is_none() {
+            if ui.button("To Julia Set").clicked() {
+                self.julia_c = Some(Complex32::new(0.5667, 0.));
+                self.pick_using_cursor = Some(PickParam::C);
+            }
+        } else {
+            ui.horizontal(|ui| {
+                if Button::new("x").small().ui(ui).clicked() {
+                    self.julia_c = None;
+                    if self.pick_using_cursor == Some(PickParam::C) { self.pick_using_cursor = None; }
+                } else if c32_ui_full(ui, "C", self.julia_c.as_mut().unwrap(), Some(0.01), None).clicked() {
+                    self.pick_using_cursor = Some(PickParam::C);
+                }
+            });
+            ui.small("The real and imaginary axes are swapped, like the classic Phoenix images");
+        }
+    }
+
+    fn get_shader(&self) -> Shader { Shader::Phoenix }
+
+    fn uniform_size(&self) -> u64 { PhoenixUniform::min_size().get() }
+
+    fn fill_uniform_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&PhoenixUniform {
+            c: self.julia_c.unwrap_or_default().to_gvec2(),
+            p: self.p.to_gvec2(),
+            iterations: self.iterations,
+            escape_radius: self.escape_radius(),
+            julia: self.julia_c.is_some() as u32,
+            _padding: 0,
+        }).unwrap();
+    }
+
+    fn coloring_size(&self) -> u64 { PhoenixColoring::min_size().get() }
+
+    fn fill_coloring_buffer(&self, mut buffer: UniformBuffer<&mut [u8]>) {
+        buffer.write(&PhoenixColoring {
+            smooth_iterations: self.smooth as u32,
+            offset: self.gradient_offset,
+            repeat: self.gradient_repeat,
+            _padding: 0.,
+        }).unwrap();
+    }
+
+    fn uniform_offsets(&self) -> &'static [u64] { PhoenixUniform::MEMBER_OFFSETS }
+
+    fn coloring_offsets(&self) -> &'static [u64] { PhoenixColoring::MEMBER_OFFSETS }
+
+    fn gradients(&self) -> Vec<&Gradient> { vec![&self.gradient] }
+
+    fn evaluate(&self, uv: GVec2, _pixel_size: f32, cycle_offset: f32) -> GVec4 {
+        let (iterations, z) = match self.julia_c {
+            // the julia version is traditionally drawn with the axes swapped
+            Some(c) => self.compute_iterations(Complex32::new(uv.y, uv.x), c),
+            None => self.compute_iterations(Complex32::ZERO, uv.to_c32()),
+        };
+        self.colorize(iterations, z, cycle_offset)
+    }
+
+    fn cost(&self) -> u32 { self.iterations }
+
+    fn draw_extra(&mut self, _painter: &Painter, _transform: &ScreenTransform, mouse_pos: Option<Vec2>) {
+        let Some(mouse_pos) = mouse_pos else { return; };
+        match (self.pick_using_cursor, &mut self.julia_c) {
+            (Some(PickParam::C), Some(c)) => *c = mouse_pos.to_c32(),
+            (Some(PickParam::P), _) => self.p = mouse_pos.to_c32(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter_plane(p: Complex32) -> Phoenix {
+        Phoenix { julia_c: None, p, smooth: false, gradient_repeat: 1., ..Phoenix::default() }
+    }
+
+    #[test]
+    fn escape_radius_covers_c_and_p() {
+        assert_eq!(parameter_plane(Complex32::ZERO).escape_radius(), 2.);
+        assert_eq!(parameter_plane(Complex32::new(0., -3.)).escape_radius(), 3.);
+        let julia = Phoenix { julia_c: Some(Complex32::new(4., 0.)), ..parameter_plane(Complex32::ONE) };
+        assert_eq!(julia.escape_radius(), 4.);
+        assert_eq!(Phoenix::default().escape_radius(), LARGE_ESCAPE_RADIUS);
+    }
+
+    #[test]
+    fn iterations_of_known_points() {
+        // without p it's the mandelbrot set, c = 1 goes 1, 2, 5
+        let fractal = parameter_plane(Complex32::ZERO);
+        assert_eq!(fractal.compute_iterations(Complex32::ZERO, Complex32::ONE), (3, Complex32::new(5., 0.)));
+        assert_eq!(fractal.compute_iterations(Complex32::ZERO, -Complex32::ONE).0, fractal.iterations);
+        // with p = 1 it goes 1, 2, 4 + 1 + 1
+        let fractal = parameter_plane(Complex32::ONE);
+        assert_eq!(fractal.compute_iterations(Complex32::ZERO, Complex32::ONE), (3, Complex32::new(6., 0.)));
+        // p = -0.5 damps the cycle of c = -1
+        let fractal = parameter_plane(Complex32::new(-0.5, 0.));
+        assert_eq!(fractal.compute_iterations(Complex32::ZERO, -Complex32::ONE).0, fractal.iterations);
+    }
+
+    #[test]
+    fn colorize_continues_the_iterations() {
+        let fractal = Phoenix { smooth: true, ..parameter_plane(Complex32::ZERO) };
+        let radius = fractal.escape_radius();
+        let at_radius = fractal.colorize(10, Complex32::new(radius, 0.), 0.);
+        let squared = fractal.colorize(11, Complex32::new(radius * radius, 0.), 0.);
+        assert!(at_radius.abs_diff_eq(squared, 1e-5), "{at_radius} != {squared}");
+        // the points that didn't escape get the last color whatever the offset
+        let last: GVec4 = fractal.gradient.sample(1.).to_normalized_gamma_f32().into();
+        assert_eq!(fractal.colorize(fractal.iterations, Complex32::ZERO, 0.5), last);
+    }
+
+    #[test]
+    fn colorize_uses_the_cycle_offset() {
+        let fractal = parameter_plane(Complex32::ZERO);
+        let color = fractal.colorize(fractal.iterations / 5, Complex32::new(3., 0.), 0.9);
+        let expected: GVec4 = fractal.gradient.sample(0.1).to_normalized_gamma_f32().into();
+        assert!(color.abs_diff_eq(expected, 1e-5), "{color} != {expected}");
+    }
+
+    #[test]
+    fn julia_axes_are_swapped() {
+        let fractal = Phoenix { smooth: false, ..Phoenix::default() };
+        let c = fractal.julia_c.unwrap();
+        let (iterations, z) = fractal.compute_iterations(Complex32::new(0.3, 1.2), c);
+        assert_eq!(fractal.evaluate(GVec2::new(1.2, 0.3), 0.1, 0.), fractal.colorize(iterations, z, 0.));
+    }
+}
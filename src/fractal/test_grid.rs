@@ -1,4 +1,6 @@
 use eframe::egui::Ui;
+use glam::{Vec2 as GVec2, Vec4 as GVec4};
+use crate::app::library::PaletteLibrary;
 use crate::fractal::FractalTrait;
 use crate::wgsl::Shader;
 
@@ -10,8 +12,15 @@ impl FractalTrait for TestGrid {
 
     fn get_shader(&self) -> Shader { Shader::TestGrid }
 
-    fn settings_ui(&mut self, ui: &mut Ui) {
+    fn settings_ui(&mut self, ui: &mut Ui, _palettes: &mut PaletteLibrary) {
         ui.label("This is a test grid.");
 
     }
+
+    fn evaluate(&self, uv: GVec2, _pixel_size: f32, _cycle_offset: f32) -> GVec4 {
+        // wgsl's fract is x - floor(x), rust's fract is x - trunc(x)
+        let grid = uv / 2. + 0.5;
+        let grid = (grid - grid.floor()) * 2. - 1.;
+        GVec4::new(grid.x, grid.y, 0., 1.)
+    }
 }
@@ -1,8 +1,6 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod app;
-mod fractal;
-mod wgsl;
+use fractal_studio::app;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
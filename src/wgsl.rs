@@ -1,25 +1,107 @@
 use std::hash::Hash;
-use eframe::{egui::Vec2, wgpu::{include_wgsl, ShaderModuleDescriptor}};
+use eframe::{egui::Vec2, wgpu::{include_wgsl, ShaderModuleDescriptor, ShaderSource}};
 use fractal_studio_macros::wgsl_variants;
-use crate::wgsl::mandelbrot::MandelbrotShader;
+use crate::wgsl::mandelbrot::{MandelbrotPerturbationShader, MandelbrotShader};
 
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub enum Shader {
     TestGrid,
     Mandelbrot(MandelbrotShader),
-    Newtons,
+    MandelbrotPerturbation(MandelbrotPerturbationShader),
+    Newtons(NewtonsShader),
     Lyapunov(LyapunovShader),
+    DomainColoring(DomainColoringShader),
+    Phoenix,
+}
+
+/// How the fractal's parameters are bound in group 1, check [crate::fractal::FractalTrait::params]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamsBinding {
+    StorageBuffer,
+    /// WebGL2 doesn't support storage buffers so the parameters are stored in the texels of a Rgba32Float texture instead
+    Texture,
 }
 
 impl Shader {
+    /// the shader with single_pass.wgsl appended, used if compute shaders aren't supported
     pub fn get_shader(self) -> ShaderModuleDescriptor<'static> {
+        self.with_appended_source(include_str!("wgsl/single_pass.wgsl"))
+    }
+
+    /// the shader with two_pass.wgsl appended, which adds the entry points used to render it in two passes
+    pub fn get_two_pass_shader(self) -> ShaderModuleDescriptor<'static> {
+        self.with_appended_source(include_str!("wgsl/two_pass.wgsl"))
+    }
+
+    /// the code shared by a few fractals and gradient.wgsl are appended to every shader, followed by `appended`
+    fn with_appended_source(self, appended: &str) -> ShaderModuleDescriptor<'static> {
+        let mut descriptor = self.get_fractal_shader();
+        if let ShaderSource::Wgsl(source) = &mut descriptor.source {
+            *source = format!("{source}\n{}\n{}\n{appended}", self.shared_source(), include_str!("wgsl/gradient.wgsl")).into();
+        }
+        descriptor
+    }
+
+    /// code used by more than one fractal's shader, it can't have preprocessor directives
+    fn shared_source(self) -> &'static str {
+        match self {
+            Shader::Mandelbrot(_) | Shader::MandelbrotPerturbation(_) => include_str!("wgsl/mandelbrot_coloring.wgsl"),
+            Shader::Newtons(_) | Shader::DomainColoring(_) | Shader::Phoenix => include_str!("wgsl/complex.wgsl"),
+            _ => "",
+        }
+    }
+
+    fn get_fractal_shader(self) -> ShaderModuleDescriptor<'static> {
         match self {
             Shader::TestGrid => include_wgsl!("wgsl/test_grid.wgsl"),
             Shader::Mandelbrot(s) => MandelbrotShader::get_shader(s),
-            Shader::Newtons => include_wgsl!("wgsl/newtons.wgsl"),
+            Shader::MandelbrotPerturbation(s) => s.get_shader(),
+            Shader::Newtons(s) => s.get_shader(),
             Shader::Lyapunov(s) => s.get_shader(),
+            Shader::DomainColoring(s) => s.get_shader(),
+            Shader::Phoenix => include_wgsl!("wgsl/phoenix.wgsl"),
+        }
+    }
+
+    /// switches to the double precision version of the shader if there is one
+    pub fn with_precision(self, double: bool) -> Self {
+        use mandelbrot::Precision;
+        match self {
+            Shader::Mandelbrot(MandelbrotShader::Product(variant, multi, _)) =>
+                Shader::Mandelbrot(MandelbrotShader::Product(variant, multi, if double { Precision::Double } else { Precision::Single })),
+            s => s,
+        }
+    }
+
+    /// switches to the version of the shader that reads its parameters from a texture if storage buffers aren't supported
+    pub fn with_storage_buffers(self, supported: bool) -> Self {
+        let data = if supported { DataSource::Storage } else { DataSource::Texture };
+        match self {
+            Shader::Newtons(NewtonsShader::Product(_)) => Shader::Newtons(NewtonsShader::Product(data)),
+            Shader::Lyapunov(LyapunovShader::Product(func, _)) => Shader::Lyapunov(LyapunovShader::Product(func, data)),
+            Shader::DomainColoring(DomainColoringShader::Product(func, _)) => Shader::DomainColoring(DomainColoringShader::Product(func, data)),
+            s => s,
         }
     }
+
+    /// perturbation shaders need a reference orbit bound in group 1
+    pub fn uses_reference_orbit(self) -> bool {
+        matches!(self, Shader::MandelbrotPerturbation(_))
+    }
+
+    /// None if the shader doesn't read any parameters in group 1
+    pub fn params_binding(self) -> Option<ParamsBinding> {
+        let data = match self {
+            Shader::Newtons(NewtonsShader::Product(data))
+            | Shader::Lyapunov(LyapunovShader::Product(_, data))
+            | Shader::DomainColoring(DomainColoringShader::Product(_, data)) => data,
+            _ => return None,
+        };
+        Some(match data {
+            DataSource::Storage => ParamsBinding::StorageBuffer,
+            DataSource::Texture => ParamsBinding::Texture,
+        })
+    }
 }
 
 pub mod mandelbrot {
@@ -36,20 +118,52 @@ pub mod mandelbrot {
 
         pub value_enum MULTI as Multi: bool { Disabled = false, Enabled = true }
 
+        pub value_enum PRECISION as Precision: u32 { Single = 0, Double = 1 }
+
         pub variants MandelbrotShader from "src/wgsl/mandelbrot.wgsl" {
-            Product(Variant, Multi),
+            Product(Variant, Multi, Precision),
+        }
+
+        // burning ship and custom exponents aren't supported
+        pub variants MandelbrotPerturbationShader from "src/wgsl/mandelbrot_perturbation.wgsl" {
+            Mandelbrot {VARIANT: u32 = 0},
+            Modified   {VARIANT: u32 = 1},
         }
     }
 }
 
 wgsl_variants! {
+    // where the shader reads the fractal's parameters from, check ParamsBinding
+    pub value_enum DATA as DataSource: u32 { Storage = 0, Texture = 1 }
+
+    pub value_enum FUNC as LyapunovFunction: u32 {
+        LogisticMap = 0,
+        SinMap      = 1,
+        GaussMap    = 2,
+        Exponential = 3,
+        CircleMap1  = 4,
+        CircleMap2  = 5,
+    }
+
+    pub value_enum COMPLEX_FUNC as ComplexFunction: u32 {
+        Polynomial           = 0,
+        Rational             = 1,
+        Sine                 = 2,
+        Exponential          = 3,
+        Logarithm            = 4,
+        EssentialSingularity = 5,
+    }
+
+    pub variants NewtonsShader from "src/wgsl/newtons.wgsl" {
+        Product(DataSource),
+    }
+
     pub variants LyapunovShader from "src/wgsl/lyapunov.wgsl" {
-        LogisticMap {FUNC: u32 = 0},
-        SinMap      {FUNC: u32 = 1},
-        GaussMap    {FUNC: u32 = 2},
-        Exponential {FUNC: u32 = 3},
-        CircleMap1  {FUNC: u32 = 4},
-        CircleMap2  {FUNC: u32 = 5},
+        Product(LyapunovFunction, DataSource),
+    }
+
+    pub variants DomainColoringShader from "src/wgsl/domain_coloring.wgsl" {
+        Product(ComplexFunction, DataSource),
     }
 }
 
@@ -65,6 +179,12 @@ impl Vec2Ext for Vec2 {
     }
 }
 
+impl Vec2Ext for glam::Vec2 {
+    fn to_c32(&self) -> Complex32 {
+        Complex32::new(self.x, self.y)
+    }
+}
+
 pub trait Complex32Ext {
     fn to_gvec2(&self) -> glam::Vec2;
 }
@@ -74,3 +194,35 @@ impl Complex32Ext for Complex32 {
         glam::Vec2::new(self.re, self.im)
     }
 }
+
+/// Offsets of the members of a uniform struct, compared with the shader's structs in debug builds
+pub trait MemberOffsets {
+    const MEMBER_OFFSETS: &'static [u64];
+}
+
+/// Declares the structs a fractal writes into its uniforms, each member is followed by its offset in the shader's struct.
+/// encase's derive emits `check` functions next to each struct that are never called,
+/// so the structs are declared in a module that allows dead code and imported from it
+macro_rules! uniform_structs {
+    ($($(#[$meta:meta])* struct $name:ident {
+        $($(#[$member_meta:meta])* $member:ident: $ty:ty = $offset:literal),* $(,)?
+    })*) => {
+        #[allow(dead_code)]
+        mod uniforms {
+            use super::*;
+            $(
+                $(#[$meta])*
+                #[derive(encase::ShaderType)]
+                pub(super) struct $name {
+                    $($(#[$member_meta])* pub(super) $member: $ty),*
+                }
+
+                impl $crate::wgsl::MemberOffsets for $name {
+                    const MEMBER_OFFSETS: &'static [u64] = &[$($offset),*];
+                }
+            )*
+        }
+        use uniforms::*;
+    };
+}
+pub(crate) use uniform_structs;
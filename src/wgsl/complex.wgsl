@@ -0,0 +1,27 @@
+// complex numbers as vec2 of re and im, appended to the shaders of the fractals that need them,
+// check Shader::with_appended_source in wgsl.rs
+
+fn cmul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn csq(z: vec2<f32>) -> vec2<f32> {
+    return vec2(z.x * z.x - z.y * z.y, 2. * z.x * z.y);
+}
+
+// maybe use https://arxiv.org/pdf/1608.07596.pdf
+// from num_complex crate
+fn cdiv(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let norm_sqr = b.x * b.x + b.y * b.y;
+    let re = a.x * b.x + a.y * b.y;
+    let im = a.y * b.x - a.x * b.y;
+    return vec2(re / norm_sqr, im / norm_sqr);
+}
+
+fn cexp(z: vec2<f32>) -> vec2<f32> {
+    return exp(z.x) * vec2(cos(z.y), sin(z.y));
+}
+
+fn csin(z: vec2<f32>) -> vec2<f32> {
+    return vec2(sin(z.x) * cosh(z.y), cos(z.x) * sinh(z.y));
+}
@@ -0,0 +1,135 @@
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
+};
+
+struct Props {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+    // only used by double precision and perturbation shaders
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
+
+    // only used by the polynomial
+    nr_roots: u32,
+    _padding0: u32,
+    _padding1: vec2<u32>,
+}
+
+struct Coloring {
+    // from 0 to 1, how dark the lines where |f(z)| is a power of 2 are
+    modulus_contours: f32,
+    // number of lines around every zero and pole where the argument is constant, 0 if there are none
+    phase_lines: u32,
+    // from 0 to 1, how dark the phase lines are
+    phase_strength: f32,
+    _padding: f32,
+}
+
+const PI: f32 = 3.14159265359;
+
+var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1.,-1.),
+    vec2<f32>(-1.,-1.),
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1., 1.),
+    vec2<f32>( 1.,-1.),
+);
+
+@group(0) @binding(0)
+var<uniform> props: Props;
+
+@group(0) @binding(1)
+var<uniform> coloring: Coloring;
+
+// the roots of the polynomial in xy
+#if DATA == 0
+@group(1) @binding(0)
+var<storage, read> params: array<vec4<f32>>;
+#else
+// WebGL2 doesn't support storage buffers
+@group(1) @binding(0)
+var params: texture_2d<f32>;
+#endif
+
+fn param(i: u32) -> vec4<f32> {
+    #if DATA == 0
+        return params[i];
+    #else
+        let width = textureDimensions(params).x;
+        return textureLoad(params, vec2(i % width, i / width), 0);
+    #endif
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
+    var out: VertexOut;
+    out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
+    out.pos = v_positions[v_idx];
+    return out;
+}
+
+// https://en.wikipedia.org/wiki/Domain_coloring
+@fragment
+fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    return colorize(raw_data(in.pos));
+}
+
+// log2 |f(z)| in x and the argument of f(z) in y, so big values don't overflow before they're colored
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    let uv = (pos + props.offset) * props.scale;
+    let w = evaluate(uv);
+    return vec4(log2(length(w)), atan2(w.y, w.x), 0., 0.);
+}
+
+// the hue is the argument and the lightness goes from black at the zeros to white at the poles
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    let log_modulus = data.x;
+    let hue = fract(data.y / (2. * PI));
+    let lightness = atan(exp2(log_modulus)) * 2. / PI;
+    var color = hsl_to_rgb(hue, 1., lightness);
+
+    // sawtooth waves that are darkest right after every contour
+    color *= mix(1., 0.6 + 0.4 * fract(log_modulus), coloring.modulus_contours);
+    if coloring.phase_lines != 0u {
+        color *= mix(1., 0.6 + 0.4 * fract(hue * f32(coloring.phase_lines)), coloring.phase_strength);
+    }
+    return vec4(color, 1.);
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> vec3<f32> {
+    let rgb = clamp(abs(fract(h + vec3(0., 2./3., 1./3.)) * 6. - 3.) - 1., vec3(0.), vec3(1.));
+    return l + s * (rgb - 0.5) * (1. - abs(2. * l - 1.));
+}
+
+fn evaluate(z: vec2<f32>) -> vec2<f32> {
+    #if COMPLEX_FUNC == 0
+        // polynomial with the given roots
+        var w = vec2(1., 0.);
+        for (var i = 0u; i < props.nr_roots; i++) {
+            w = cmul(w, z - param(i).xy);
+        }
+        return w;
+    #else if COMPLEX_FUNC == 1
+        // (z^2 - 1)(z - 2 - i)^2 / (z^2 + 2 + 2i), the example on wikipedia
+        let a = csq(z) - vec2(1., 0.);
+        let b = csq(z - vec2(2., 1.));
+        return cdiv(cmul(a, b), csq(z) + vec2(2., 2.));
+    #else if COMPLEX_FUNC == 2
+        return csin(z);
+    #else if COMPLEX_FUNC == 3
+        return cexp(z);
+    #else if COMPLEX_FUNC == 4
+        // principal branch
+        return vec2(log(length(z)), atan2(z.y, z.x));
+    #else if COMPLEX_FUNC == 5
+        // essential singularity at 0
+        return cexp(cdiv(vec2(1., 0.), z));
+    #endif
+}
@@ -0,0 +1,34 @@
+// appended to every fractal's shader, check gradients_uniform_data in gradient.rs
+
+// every gradient is baked into 256 colors packed as rgba8, 4 in each element
+struct Gradients {
+    colors: array<vec4<u32>, 256>,
+    // from 0 to 1, animated by the visualizer when the colors are cycling and 0 otherwise
+    cycle_offset: f32,
+    _padding0: f32,
+    _padding1: vec2<f32>,
+}
+
+@group(0) @binding(2)
+var<uniform> gradients: Gradients;
+
+fn gradient_sample(gradient: u32, i: u32) -> vec4<f32> {
+    let index = gradient * 256u + i;
+    return unpack4x8unorm(gradients.colors[index / 4u][index % 4u]);
+}
+
+// color of one of the fractal's gradients at t, t is clamped to 0..1
+fn gradient_color(gradient: u32, t: f32) -> vec4<f32> {
+    let x = clamp(t, 0., 1.) * 255.;
+    let i = min(u32(x), 254u);
+    return mix(gradient_sample(gradient, i), gradient_sample(gradient, i + 1u), x - f32(i));
+}
+
+// moves t along a gradient by the cycling offset, wrapping around.
+// t is kept as is when the colors aren't cycling so 1 stays the last color
+fn cycle_position(t: f32) -> f32 {
+    if gradients.cycle_offset == 0. {
+        return t;
+    }
+    return fract(t + gradients.cycle_offset);
+}
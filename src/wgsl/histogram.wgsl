@@ -0,0 +1,47 @@
+// counts after how many iterations the pixels of the raw data escaped and turns the counts into a cumulative distribution
+// used for histogram coloring, the number of iterations is stored in x by every escape time fractal
+
+struct Props {
+    // the number of iterations, pixels that reach it never escaped so they aren't counted
+    bins: u32,
+    _padding0: u32,
+    _padding1: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var raw: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> counts: array<atomic<u32>>;
+
+@group(0) @binding(2)
+var<storage, read_write> cdf: array<f32>;
+
+@group(0) @binding(3)
+var<uniform> props: Props;
+
+@compute @workgroup_size(8, 8)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(raw);
+    if any(id.xy >= size) {
+        return;
+    }
+    let iterations = u32(textureLoad(raw, id.xy, 0).x);
+    if iterations < props.bins {
+        atomicAdd(&counts[iterations], 1u);
+    }
+}
+
+// runs on a single invocation, there are way less bins than pixels
+@compute @workgroup_size(1)
+fn accumulate() {
+    var total = 0u;
+    for (var i = 0u; i < props.bins; i++) {
+        total += atomicLoad(&counts[i]);
+    }
+    var sum = 0u;
+    for (var i = 0u; i < props.bins; i++) {
+        sum += atomicLoad(&counts[i]);
+        cdf[i] = f32(sum) / f32(max(total, 1u));
+    }
+}
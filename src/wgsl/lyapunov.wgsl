@@ -1,21 +1,35 @@
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
 };
 
 struct Props {
     scale: vec2<f32>,
     offset: vec2<f32>,
+    // only used by double precision and perturbation shaders
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
 
+    iterations: u32,
+    seq_len: u32,
+    _padding: vec2<u32>,
+}
+
+struct Coloring {
     stable_col: vec4<f32>,
     unstable_col: vec4<f32>,
-    iterations: u32,
-    // 1..=16
-    seg_len: u32,
-    // array packed in an integer, 0 is A and 1 is B
-    sequence: u32,
-    // extra parameter for extra fun
-    c: f32,
+    // bool, negative exponents are colored with the first gradient and positive ones with the second
+    gradients: u32,
+    // the gradients end at these exponents
+    min_exponent: f32,
+    max_exponent: f32,
+    // the position in the gradients is raised to this power
+    gamma: f32,
 }
 
 const PI: f32 = 3.14159265359;
@@ -39,46 +53,92 @@ var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
 @group(0) @binding(0)
 var<uniform> props: Props;
 
+@group(0) @binding(1)
+var<uniform> coloring: Coloring;
+
+// the sequence, 4 symbols per element where 0 is A and 1 is B
+#if DATA == 0
+@group(1) @binding(0)
+var<storage, read> params: array<vec4<f32>>;
+#else
+// WebGL2 doesn't support storage buffers
+@group(1) @binding(0)
+var params: texture_2d<f32>;
+#endif
+
+fn param(i: u32) -> vec4<f32> {
+    #if DATA == 0
+        return params[i];
+    #else
+        let width = textureDimensions(params).x;
+        return textureLoad(params, vec2(i % width, i / width), 0);
+    #endif
+}
+
+fn symbol(i: u32) -> f32 {
+    return param(i / 4u)[i % 4u];
+}
+
 @vertex
 fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
     out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
-    out.uv = (v_positions[v_idx] + props.offset) * props.scale;
+    out.pos = v_positions[v_idx];
     return out;
 }
 
 @fragment
 fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
-    let gamma = compute_exponent(in.uv);
-    return exponent_to_color(gamma);
+    return colorize(raw_data(in.pos));
+}
+
+// lyapunov's exponent
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    let uv = (pos + props.offset) * props.scale;
+    return vec4(compute_exponent(uv), 0., 0., 0.);
 }
 
-fn exponent_to_color(gamma: f32) -> vec4<f32> {
-    let color = mix(props.stable_col, props.unstable_col, f32(gamma > 0.0));
-    return color * exp(-ALPHA * abs(gamma));
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    let exponent = data.x;
+    if coloring.gradients != 0u {
+        return exponent_to_color(exponent);
+    }
+    let color = mix(coloring.stable_col, coloring.unstable_col, f32(exponent > 0.0));
+    return vec4(color.rgb * exp(-ALPHA * abs(exponent)), 1.0);
+}
+
+// both gradients start at 0 and end at the clamp range
+fn exponent_to_color(exponent: f32) -> vec4<f32> {
+    if exponent <= 0. {
+        let t = pow(clamp(exponent / coloring.min_exponent, 0., 1.), coloring.gamma);
+        return gradient_color(0u, cycle_position(t));
+    }
+    let t = pow(clamp(exponent / coloring.max_exponent, 0., 1.), coloring.gamma);
+    return gradient_color(1u, cycle_position(t));
 }
 
 // https://en.wikipedia.org/wiki/Lyapunov_fractal
 // https://www.youtube.com/watch?v=yGwy2WyQCQE
 fn compute_exponent(ab: vec2<f32>) -> f32 {
     var xi = 0.5;
-    var seq = props.sequence;
+    // index of the current symbol in the sequence
+    var seq = 0u;
     var exp = 0.;
 
     var i = 1u;
     let ignore_iter = props.iterations / IGNORE_DIV;
     //ignore first iterations to avoid instability
     for (;i <= ignore_iter; i++) {
-        let r = mix(ab.y, ab.x, f32(seq & 1u));
+        let r = mix(ab.y, ab.x, symbol(seq));
         xi = func(xi, r);
-        seq = cycle_seq(seq);
+        seq = (seq + 1u) % props.seq_len;
     }
 
     for (;i <= props.iterations; i++) {
-        let r = mix(ab.y, ab.x, f32(seq & 1u));
+        let r = mix(ab.y, ab.x, symbol(seq));
         xi = func(xi, r);
         exp += exponent(xi, r);
-        seq = cycle_seq(seq);
+        seq = (seq + 1u) % props.seq_len;
     }
 
    return exp / f32(props.iterations - ignore_iter); // todo: maybe multiplying each exponent individually produces more accurate results?
@@ -129,21 +189,3 @@ fn exponent(x: f32, r: f32) -> f32 {
         return log(abs(r * cos (r * x)));
     #endif
 }
-
-// cycles the sequence clockwise, based on seq_len
-// seq needs to be initialised with props.sequence
-fn cycle_seq(seq: u32) -> u32 {
-    return (seq >> 1u) | ((seq & 1u) << (props.seg_len - 1u));
-}
-
-/*fn test_seq(x: f32) -> vec3<f32> {
-    if x < 0. {
-        return vec3<f32>(1.0, 0.0, 0.0);
-    }
-    let iter = i32(x);
-    var seq = props.sequence;
-    for (var i = 1; i <= iter; i++) {
-        seq = cycle_seq(seq);
-    }
-    return vec3(f32(seq & 1u));
-}*/
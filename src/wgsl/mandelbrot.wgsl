@@ -1,21 +1,60 @@
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
 };
 
 struct Props {
+    // a double is stored as the unevaluated sum of a high and a low part
     scale: vec2<f32>,
     offset: vec2<f32>,
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    // only used by perturbation shaders
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
 
     c: vec2<f32>,
     max_iterations: u32,
     escape_radius: f32,
-    exp: f32, // only used if MULTI == true
+    exp: f32, // used if MULTI == true and by smooth coloring
     // 0 - render Mandelbort/base fractal
     // 1 - render a bix of both
     // 2 - render Julia fractal
     julia: i32,
-    _padding: vec2<f32>,
+    // always 1 but the shader compiler can't know that, check the double-float functions
+    one: f32,
+    // bool, the distance to the set is stored in the raw data if there's no orbit trap
+    distance_estimation: u32,
+
+    // 0 - no orbit trap
+    // 1 - point
+    // 2 - line through the center
+    // 3 - cross
+    // 4 - circle
+    trap_shape: u32,
+    trap_radius: f32,
+    trap_center: vec2<f32>,
+    // cos and sin of the angle of the line and the cross
+    trap_direction: vec2<f32>,
+
+    // coloring of the points that didn't escape, it uses the second gradient
+    // 0 - none, they get the last color of the first gradient
+    // 1 - period of the attracting cycle, stored in the raw data
+    // 2 - final |z|
+    // 3 - final argument of z
+    // 4 - interior distance estimate in pixels, stored in the raw data
+    interior_mode: u32,
+
+    // average of a function of every z of the orbit, smoothed across the escape iteration and stored in the raw data
+    // 0 - none
+    // 1 - stripe average
+    // 2 - triangle inequality average
+    average_mode: u32,
+    // number of stripes in a turn around the origin
+    stripe_density: f32,
 }
 
 var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
@@ -30,68 +69,191 @@ var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
 @group(0) @binding(0)
 var<uniform> props: Props;
 
+#if PRECISION == 0
+// re, im
+alias complex = vec2<f32>;
+#else
+// re hi, re lo, im hi, im lo
+alias complex = vec4<f32>;
+#endif
+
 @vertex
 fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
     out.position = vec4(v_positions[v_idx], 0.0, 1.0);
-    out.uv = (v_positions[v_idx] + props.offset) * props.scale;
+    out.pos = v_positions[v_idx];
+    return out;
+}
+
+// colorize and the coloring uniform are in mandelbrot_coloring.wgsl, which is appended to this shader
+@fragment
+fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    return colorize(raw_data(in.pos));
+}
+
+// iterations in x, the last z in yz and the closest distance to the orbit trap or the distance to the set in w
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    #if PRECISION == 0
+    var uv = (pos + props.offset) * props.scale;
+    #else
+    let re = df_add(df_mul(df(props.offset.x, props.offset_lo.x), df(props.scale.x, props.scale_lo.x)), df_mul(df(pos.x, 0.), df(props.scale.x, props.scale_lo.x)));
+    let im = df_add(df_mul(df(props.offset.y, props.offset_lo.y), df(props.scale.y, props.scale_lo.y)), df_mul(df(pos.y, 0.), df(props.scale.y, props.scale_lo.y)));
+    var uv = complex(re, im);
+    #endif
+
     #if VARIANT == 2
     // the burning ship is traditionally flipped on the y axis
     if props.julia == 0 {
-        out.uv *= vec2(1.,-1.);
+        uv = c_conj(uv);
     }
     #endif
-    return out;
-}
 
-@fragment
-fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
     // we could turn it into a variant but it's only run once per fragment so doubling the sources isn't worth it
     if props.julia == 0 {
-        let iterations = compute_iterations(vec2<f32>(), in.uv, 2., props.max_iterations);
-        return vec4(vec3(f32(iterations) / f32(props.max_iterations)), 1.0);
+        let escape = compute_iterations(c_from_f32(vec2<f32>()), uv, props.escape_radius, props.max_iterations, false);
+        return vec4(f32(escape.iterations), escape.z, extra_data(escape));
 
     } else if props.julia == 1 {
-        let iterations_mandelbrot = compute_iterations(vec2<f32>(), in.uv, 2., props.max_iterations/2u);
-        let iterations_julia = compute_iterations(in.uv, props.c, props.escape_radius, props.max_iterations/2u);
-        return vec4(vec3(f32(iterations_mandelbrot + iterations_julia) / f32(props.max_iterations)), 1.0);
+        let mandelbrot = compute_iterations(c_from_f32(vec2<f32>()), uv, 2., props.max_iterations/2u, false);
+        let julia = compute_iterations(uv, c_from_f32(props.c), props.escape_radius, props.max_iterations/2u, true);
+        return vec4(f32(mandelbrot.iterations + julia.iterations), julia.z, extra_data(julia));
 
     } else {
-        let iterations = compute_iterations(in.uv, props.c, props.escape_radius, props.max_iterations);
-        return vec4(vec3(f32(iterations) / f32(props.max_iterations)), 1.0);
+        let escape = compute_iterations(uv, c_from_f32(props.c), props.escape_radius, props.max_iterations, true);
+        return vec4(f32(escape.iterations), escape.z, extra_data(escape));
+    }
+}
+
+// stored in w of the raw data, orbit traps can't be combined with distance estimation or interior coloring
+fn extra_data(escape: Escape) -> f32 {
+    if props.trap_shape != 0u {
+        return escape.trap;
     }
+    return escape.distance;
 }
 
 // https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set
-fn compute_iterations(z0: vec2<f32>, c: vec2<f32>, escape_radius: f32, max_iterations: u32) -> u32 {
+// julia is true if the pixel is z0 instead of c
+fn compute_iterations(z0: complex, c: complex, escape_radius: f32, max_iterations: u32, julia: bool) -> Escape {
     var iterations = 0u;
     var z = z0;
+    var trap = 1e20;
+    // derivative of z with respect to the pixel
+    var dz = select(vec2(0., 0.), vec2(1., 0.), julia);
     let r_sq = escape_radius * escape_radius;
-    while z.x * z.x + z.y * z.y <= r_sq && iterations < max_iterations {
+    // periodicity checking, z is compared with the z saved at the last power of 2.
+    // The period found in the latest window is kept because the orbit is closer to the cycle
+    let check_period = props.interior_mode == 1u || props.interior_mode == 4u;
+    var saved = z;
+    var saved_at = 0u;
+    var period = 0u;
+    var found = false;
+    // sum of the average coloring's terms, the previous one is without the last iteration so the average can be smoothed
+    var average = Average(0., 0., 0., 0.);
+    while c_norm_sq(z) <= r_sq && iterations < max_iterations {
+        // it stops growing before it overflows, those points are right next to the set anyway
+        if props.distance_estimation != 0u && dot(dz, dz) < 1e30 {
+            dz = c_mul_f32(equation_derivative(c_to_f32(z)), dz);
+            if !julia {
+                dz.x += 1.;
+            }
+        }
         z = equation(z,c);
         iterations++;
+        if props.trap_shape != 0u && c_norm_sq(z) <= r_sq {
+            trap = min(trap, trap_distance(c_to_f32(z)));
+        }
+        if props.average_mode != 0u {
+            average = add_average_term(average, c_to_f32(z), c_to_f32(c));
+        }
+        if check_period {
+            if !found && c_norm_sq(c_sub(z, saved)) < PERIOD_EPSILON * PERIOD_EPSILON {
+                period = iterations - saved_at;
+                found = true;
+            }
+            if (iterations & (iterations - 1u)) == 0u {
+                saved = z;
+                saved_at = iterations;
+                found = false;
+            }
+        }
+    }
+    var distance = 0.;
+    if iterations >= max_iterations {
+        distance = interior_data(c_to_f32(z), c_to_f32(c), period, julia);
+    } else if props.distance_estimation != 0u {
+        distance = distance_estimate(c_to_f32(z), dz);
+    } else if props.average_mode != 0u {
+        distance = smooth_average(average, c_to_f32(z));
+    }
+    return Escape(iterations, c_to_f32(z), trap, distance);
+}
+
+// distance to the set in pixels, https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Exterior_distance_estimation
+fn distance_estimate(z: vec2<f32>, dz: vec2<f32>) -> f32 {
+    let r = length(z);
+    return r * log(r) / length(dz) / props.pixel_size;
+}
+
+// -1 if it isn't known
+fn interior_data(z: vec2<f32>, c: vec2<f32>, period: u32, julia: bool) -> f32 {
+    switch props.interior_mode {
+        case 1u: { return f32(period); }
+        case 4u: {
+            // the formula is for the parameter plane
+            if julia || period == 0u { return -1.; }
+            return interior_distance(z, c, period);
+        }
+        default: { return 0.; }
     }
-    return iterations;
 }
 
-fn equation(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+// derivative of the equation with respect to z, the burning ship doesn't have one
+fn equation_derivative(z: vec2<f32>) -> vec2<f32> {
+    #if MULTI == false
+        let d = 2. * z;
+    #else
+        let d = props.exp * cpowf(z, props.exp - 1.);
+    #endif
+    #if VARIANT == 1
+        return d - vec2(1., 0.);
+    #else
+        return d;
+    #endif
+}
+
+fn equation_second_derivative(z: vec2<f32>) -> vec2<f32> {
+    #if MULTI == false
+        return vec2(2., 0.);
+    #else
+        return props.exp * (props.exp - 1.) * cpowf(z, props.exp - 2.);
+    #endif
+}
+
+// single precision step of the equation, check interior_distance
+fn equation_f32(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    return c_to_f32(equation(c_from_f32(z), c_from_f32(c)));
+}
+
+fn equation(z: complex, c: complex) -> complex {
     #if VARIANT == 0
         // mandelbrot
-        return raise_power(z) + c;
+        return c_add(raise_power(z), c);
     #else if VARIANT == 1
         // modified mandelbrot set
-        return raise_power(z) - z + c;
+        return c_add(c_sub(raise_power(z), z), c);
     #else if VARIANT == 2
         // burning ship
-        return raise_power(abs(z)) + c;
+        return c_add(raise_power(c_abs(z)), c);
     #endif
 }
 
-fn raise_power(z: vec2<f32>) -> vec2<f32> {
+fn raise_power(z: complex) -> complex {
     #if MULTI == false
-        return vec2(z.x * z.x - z.y * z.y, 2. * z.x * z.y);
+        return c_sq(z);
     #else
-        return cpowf(z, props.exp);
+        // todo: real powers are only computed in single precision
+        return c_from_f32(cpowf(c_to_f32(z), props.exp));
     #endif
 }
 
@@ -101,3 +263,86 @@ fn cpowf(x: vec2<f32>, y: f32) -> vec2<f32> {
     var theta = atan2(x.y, x.x) * y;
     return vec2<f32>(r * cos(theta), r * sin(theta));
 }
+
+#if PRECISION == 0
+
+fn c_from_f32(x: vec2<f32>) -> complex { return x; }
+fn c_to_f32(x: complex) -> vec2<f32> { return x; }
+fn c_norm_sq(x: complex) -> f32 { return dot(x, x); }
+fn c_conj(x: complex) -> complex { return vec2(x.x, -x.y); }
+fn c_abs(x: complex) -> complex { return abs(x); }
+fn c_add(a: complex, b: complex) -> complex { return a + b; }
+fn c_sub(a: complex, b: complex) -> complex { return a - b; }
+fn c_sq(x: complex) -> complex { return vec2(x.x * x.x - x.y * x.y, 2. * x.x * x.y); }
+
+#else
+
+fn c_from_f32(x: vec2<f32>) -> complex { return vec4(x.x, 0., x.y, 0.); }
+fn c_to_f32(x: complex) -> vec2<f32> { return x.xz; }
+// the low parts don't matter when checking for escape
+fn c_norm_sq(x: complex) -> f32 { return x.x * x.x + x.z * x.z; }
+fn c_conj(x: complex) -> complex { return vec4(x.xy, -x.zw); }
+fn c_abs(x: complex) -> complex { return complex(df_abs(x.xy), df_abs(x.zw)); }
+fn c_add(a: complex, b: complex) -> complex { return complex(df_add(a.xy, b.xy), df_add(a.zw, b.zw)); }
+fn c_sub(a: complex, b: complex) -> complex { return complex(df_add(a.xy, -b.xy), df_add(a.zw, -b.zw)); }
+fn c_sq(x: complex) -> complex {
+    let re = df_add(df_mul(x.xy, x.xy), -df_mul(x.zw, x.zw));
+    let im = df_mul(df_mul(x.xy, x.zw), vec2(2., 0.));
+    return complex(re, im);
+}
+
+// double-float arithmetic, a number is stored as the unevaluated sum of two f32s giving around 48 bits of mantissa
+// http://andrewthall.org/papers/df64_qf128.pdf
+// it relies on rounding errors so expressions like (a + b) - a must not be simplified to b
+// WGSL has no way to mark math as exact and some drivers do simplify them, multiplying by props.one prevents that
+
+fn df(hi: f32, lo: f32) -> vec2<f32> { return vec2(hi, lo); }
+
+fn df_abs(a: vec2<f32>) -> vec2<f32> {
+    if a.x < 0. { return -a; }
+    return a;
+}
+
+fn quick_two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    return vec2(s, b - (s * props.one - a));
+}
+
+fn two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    let v = s * props.one - a;
+    return vec2(s, (a - (s * props.one - v)) + (b - v));
+}
+
+// splits a f32 into two halves with 12 bits each so their products are exact
+fn split(a: f32) -> vec2<f32> {
+    let t = 4097. * a;
+    let hi = t - (t * props.one - a);
+    return vec2(hi, a - hi);
+}
+
+// fma would be simpler but it isn't guaranteed to be fused on every backend
+fn two_prod(a: f32, b: f32) -> vec2<f32> {
+    let p = a * b;
+    let a_s = split(a);
+    let b_s = split(b);
+    let err = ((a_s.x * b_s.x - p * props.one) + a_s.x * b_s.y + a_s.y * b_s.x) + a_s.y * b_s.y;
+    return vec2(p, err);
+}
+
+fn df_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    var s = two_sum(a.x, b.x);
+    let t = two_sum(a.y, b.y);
+    s.y += t.x;
+    s = quick_two_sum(s.x, s.y);
+    s.y += t.y;
+    return quick_two_sum(s.x, s.y);
+}
+
+fn df_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    var p = two_prod(a.x, b.x);
+    p.y += a.x * b.y + a.y * b.x;
+    return quick_two_sum(p.x, p.y);
+}
+
+#endif
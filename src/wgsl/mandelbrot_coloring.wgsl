@@ -0,0 +1,205 @@
+// appended to mandelbrot.wgsl and mandelbrot_perturbation.wgsl, check Shader::with_appended_source in wgsl.rs.
+// Colors the raw data both shaders store, they have to define Props and the equation used by interior_distance:
+// equation_f32, equation_derivative and equation_second_derivative
+
+struct Coloring {
+    // 0 - iterations / max_iterations
+    // 1 - cumulative distribution of the iterations (histogram coloring)
+    // 2, 3 - the average stored in the raw data, check props.average_mode
+    mode: u32,
+    // bool, continuous iteration count
+    smooth_iterations: u32,
+    // the gradient is sampled at fract(t * repeat + offset)
+    offset: f32,
+    repeat: f32,
+    // the gradient goes from 0 to this distance to the orbit trap
+    trap_distance: f32,
+    // 0 - lines of the interior color on the boundary
+    // 1 - the gradient goes from 0 to de_thickness pixels away from the set
+    de_mode: u32,
+    // in pixels
+    de_thickness: f32,
+    // how much the distance estimation is mixed with the iteration coloring
+    de_strength: f32,
+}
+
+@group(0) @binding(1)
+var<uniform> coloring: Coloring;
+
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    let iterations = u32(data.x);
+    var t: f32;
+    if props.trap_shape != 0u {
+        // every point is colored by how close its orbit got to the trap
+        t = min(data.w / coloring.trap_distance, 1.);
+    } else if props.average_mode != 0u && iterations < props.max_iterations {
+        // the average is already between 0 and 1
+        t = data.w;
+    } else {
+        if iterations >= props.max_iterations {
+            return interior_color(data);
+        }
+        var n = data.x;
+        if coloring.smooth_iterations != 0u {
+            n = smooth_iterations(iterations, data.yz);
+        }
+        t = n / f32(props.max_iterations);
+        // single pass shaders fall back to linear coloring
+        if coloring.mode == 1u && histogram_available() {
+            let i = u32(max(floor(n), 0.));
+            t = mix(cumulative_distribution(i), cumulative_distribution(i + 1u), fract(n));
+        }
+    }
+    let color = gradient_color(0u, gradient_position(t));
+    if props.trap_shape != 0u || props.distance_estimation == 0u {
+        return color;
+    }
+
+    // the distance to the set in pixels is in w
+    if coloring.de_mode == 0u {
+        // 1 on the boundary and 0 further than the thickness
+        let line = 1. - smoothstep(0., coloring.de_thickness, data.w);
+        return mix(color, gradient_color(0u, 1.), line * coloring.de_strength);
+    } else {
+        let distance_color = gradient_color(0u, gradient_position(min(data.w / coloring.de_thickness, 1.)));
+        return mix(color, distance_color, coloring.de_strength);
+    }
+}
+
+// number of periods before the interior gradient starts over
+const PERIOD_COLORS: u32 = 12u;
+
+// color of the points that never escaped, the period or the interior distance is in w.
+// Points whose value isn't known get the last color like when there's no interior coloring
+fn interior_color(data: vec4<f32>) -> vec4<f32> {
+    var t: f32;
+    switch props.interior_mode {
+        case 1u: {
+            if data.w < 1. { return gradient_color(0u, 1.); }
+            t = f32((u32(data.w) - 1u) % PERIOD_COLORS) / f32(PERIOD_COLORS - 1u);
+        }
+        case 2u: { t = length(data.yz) / 2.; }
+        case 3u: { t = atan2(data.z, data.y) / (2. * 3.14159265) + 0.5; }
+        case 4u: {
+            if data.w < 0. { return gradient_color(0u, 1.); }
+            // logarithmic so the whole gradient shows from the boundary to the middle of the components
+            t = log2(1. + data.w) / 10.;
+        }
+        default: { return gradient_color(0u, 1.); }
+    }
+    return gradient_color(1u, t);
+}
+
+fn gradient_position(t: f32) -> f32 {
+    let position = t * coloring.repeat + coloring.offset;
+    // 1 is kept so the whole gradient is used when it isn't repeated
+    if position > 1. {
+        return cycle_position(fract(position));
+    }
+    return cycle_position(position);
+}
+
+// continuous iteration count using the log-log renormalization, it's between iterations - 1 and iterations.
+// |z| went past the escape radius r in the last iteration so log|z| / log r is between 1 and the exponent
+fn smooth_iterations(iterations: u32, z: vec2<f32>) -> f32 {
+    let log_ratio = log(length(z)) / log(props.escape_radius);
+    return f32(iterations) - log(max(log_ratio, 1.)) / log(max(props.exp, 1.1));
+}
+
+// distance from z to the orbit trap
+fn trap_distance(z: vec2<f32>) -> f32 {
+    let p = z - props.trap_center;
+    // rotated so the line lies on the x axis
+    let q = vec2(dot(p, props.trap_direction), dot(p, vec2(-props.trap_direction.y, props.trap_direction.x)));
+    switch props.trap_shape {
+        case 1u: { return length(p); }
+        case 2u: { return abs(q.y); }
+        case 3u: { return min(abs(q.x), abs(q.y)); }
+        default: { return abs(length(p) - props.trap_radius); }
+    }
+}
+
+struct Escape {
+    iterations: u32,
+    z: vec2<f32>,
+    // closest distance to the orbit trap, only points that didn't escape count
+    trap: f32,
+    // distance to the set in pixels if distance estimation is enabled or the average if there's average coloring,
+    // the period or the interior distance if the point didn't escape
+    distance: f32,
+}
+
+// z has to get this close to the z saved by the periodicity checking
+const PERIOD_EPSILON: f32 = 1e-6;
+
+struct Average {
+    sum: f32,
+    count: f32,
+    previous_sum: f32,
+    previous_count: f32,
+}
+
+// from On Smooth Fractal Coloring Techniques by Jussi Härkönen
+// z is the latest iteration, terms that aren't defined are skipped
+fn add_average_term(average: Average, z: vec2<f32>, c: vec2<f32>) -> Average {
+    var term: f32;
+    if props.average_mode == 1u {
+        // stripe average
+        term = 0.5 * sin(props.stripe_density * atan2(z.y, z.x)) + 0.5;
+    } else {
+        // triangle inequality average, z - c is the previous z raised to the power and |z| is somewhere between
+        // the two bounds of the triangle inequality, they're the same if one of the terms is 0
+        let a = length(z - c);
+        let b = length(c);
+        let low = abs(a - b);
+        let high = a + b;
+        if high - low <= 0. {
+            return Average(average.sum, average.count, average.sum, average.count);
+        }
+        term = (length(z) - low) / (high - low);
+    }
+    return Average(average.sum + term, average.count + 1., average.sum, average.count);
+}
+
+// interpolates between the average with and without the last iteration using the fractional part of the continuous iteration count
+fn smooth_average(average: Average, z: vec2<f32>) -> f32 {
+    let last = average.sum / max(average.count, 1.);
+    let previous = average.previous_sum / max(average.previous_count, 1.);
+    let log_ratio = log(length(z)) / log(props.escape_radius);
+    let fraction = clamp(log(max(log_ratio, 1.)) / log(max(props.exp, 1.1)), 0., 1.);
+    return mix(last, previous, fraction);
+}
+
+// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Interior_distance_estimation
+// z is close to the attracting cycle after all the iterations, the derivatives of f^period are computed along one period.
+// The perturbation shaders iterate the cycle without the reference, it's only as precise as single precision
+fn interior_distance(z0: vec2<f32>, c: vec2<f32>, period: u32) -> f32 {
+    var z = z0;
+    var dz = vec2(1., 0.);
+    var dc = vec2(0., 0.);
+    var dzdz = vec2(0., 0.);
+    var dcdz = vec2(0., 0.);
+    for (var i = 0u; i < period; i++) {
+        let d = equation_derivative(z);
+        let d2 = equation_second_derivative(z);
+        dcdz = c_mul_f32(d, dcdz) + c_mul_f32(d2, c_mul_f32(dz, dc));
+        dzdz = c_mul_f32(d, dzdz) + c_mul_f32(d2, c_mul_f32(dz, dz));
+        dz = c_mul_f32(d, dz);
+        dc = c_mul_f32(d, dc) + vec2(1., 0.);
+        z = equation_f32(z, c);
+    }
+    // the cycle isn't attracting, the period was wrong
+    if dot(dz, dz) >= 1. { return -1.; }
+    let denominator = dcdz + c_div_f32(c_mul_f32(dzdz, dc), vec2(1., 0.) - dz);
+    let distance = (1. - dot(dz, dz)) / length(denominator) / props.pixel_size;
+    // the pixel size is relative to 2^scale_exp
+    return ldexp(distance, -props.scale_exp);
+}
+
+fn c_mul_f32(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn c_div_f32(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y) / dot(b, b);
+}
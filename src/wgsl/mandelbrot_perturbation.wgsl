@@ -0,0 +1,272 @@
+// renders deep zooms by iterating the difference between each pixel and a reference orbit computed on the cpu
+// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Perturbation_theory_and_series_approximation
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // clip space position, raw_data computes the position relative to the reference orbit from it
+    @location(0) pos: vec2<f32>,
+};
+
+// same as mandelbrot.wgsl
+struct Props {
+    // the real scale is (scale + scale_lo) * 2^scale_exp
+    scale: vec2<f32>,
+    // offset from the reference orbit's center
+    offset: vec2<f32>,
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
+
+    c: vec2<f32>,
+    max_iterations: u32,
+    escape_radius: f32,
+    exp: f32, // only used by smooth coloring
+    // 0 - render Mandelbort/base fractal
+    // 2 - render Julia fractal
+    julia: i32,
+    one: f32, // unused
+    // bool, the distance to the set is stored in the raw data if there's no orbit trap
+    distance_estimation: u32,
+
+    // 0 - no orbit trap
+    // 1 - point
+    // 2 - line through the center
+    // 3 - cross
+    // 4 - circle
+    trap_shape: u32,
+    trap_radius: f32,
+    trap_center: vec2<f32>,
+    // cos and sin of the angle of the line and the cross
+    trap_direction: vec2<f32>,
+
+    // 0 - none
+    // 1 - period
+    // 2 - final |z|
+    // 3 - final argument of z
+    // 4 - interior distance estimate
+    interior_mode: u32,
+
+    // 0 - none
+    // 1 - stripe average
+    // 2 - triangle inequality average
+    average_mode: u32,
+    stripe_density: f32,
+}
+
+var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1.,-1.),
+    vec2<f32>(-1.,-1.),
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1., 1.),
+    vec2<f32>( 1.,-1.),
+);
+
+@group(0) @binding(0)
+var<uniform> props: Props;
+
+// z of the reference for every iteration, the first one is the starting z
+@group(1) @binding(0)
+var<storage, read> orbit: array<vec2<f32>>;
+
+@vertex
+fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
+    var out: VertexOut;
+    out.position = vec4(v_positions[v_idx], 0.0, 1.0);
+    out.pos = v_positions[v_idx];
+    return out;
+}
+
+// colorize and the coloring uniform are in mandelbrot_coloring.wgsl, which is appended to this shader
+@fragment
+fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    return colorize(raw_data(in.pos));
+}
+
+// iterations in x, the last z in yz and the closest distance to the orbit trap or the distance to the set in w
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    // position relative to the reference orbit in units of scale
+    let delta = fe_new((pos + props.offset) * (props.scale + props.scale_lo), props.scale_exp);
+    var escape: Escape;
+    if props.julia == 0 {
+        escape = compute_iterations(fe_new(vec2<f32>(), 0), delta);
+    } else {
+        escape = compute_iterations(delta, fe_new(vec2<f32>(), 0));
+    }
+    // orbit traps can't be combined with distance estimation or interior coloring
+    return vec4(f32(escape.iterations), escape.z, select(escape.distance, escape.trap, props.trap_shape != 0u));
+}
+
+// z = Z + dz where Z is the reference, c = C + dc
+// dz is iterated on its own: dz' = 2Z*dz + dz^2 + dc (for z^2 + c)
+fn compute_iterations(dz0: FloatExp, dc: FloatExp) -> Escape {
+    let r_sq = props.escape_radius * props.escape_radius;
+    let orbit_len = arrayLength(&orbit);
+
+    var dz = dz0;
+    // index into the reference orbit, it goes back to 0 when rebasing
+    var n = 0u;
+    var iterations = 0u;
+    var z = orbit[0] + fe_to_f32(dz);
+    var trap = 1e20;
+    // derivative of z with respect to the pixel, it gets way bigger than f32 can represent
+    var der = fe_new(vec2<f32>(), 0);
+    if props.julia != 0 {
+        der = fe_new(vec2(1., 0.), 0);
+    }
+    // periodicity checking, same as mandelbrot.wgsl but z only has single precision
+    let check_period = props.interior_mode == 1u || props.interior_mode == 4u;
+    var saved = z;
+    var saved_at = 0u;
+    var period = 0u;
+    var found = false;
+    var average = Average(0., 0., 0., 0.);
+    while iterations < props.max_iterations {
+        if props.distance_estimation != 0u {
+            der = fe_mul(der, equation_derivative(z));
+            if props.julia == 0 {
+                der = fe_add(der, fe_new(vec2(1., 0.), 0));
+            }
+        }
+        let big_z = orbit[n];
+        let dz_f32 = fe_to_f32(dz);
+        #if VARIANT == 0
+            // dz * (2Z + dz) + dc
+            dz = fe_add(fe_mul(dz, 2. * big_z + dz_f32), dc);
+        #else if VARIANT == 1
+            // dz * (2Z + dz - 1) + dc
+            dz = fe_add(fe_mul(dz, 2. * big_z + dz_f32 - vec2(1., 0.)), dc);
+        #endif
+        n++;
+        iterations++;
+
+        z = orbit[n] + fe_to_f32(dz);
+        if props.average_mode != 0u {
+            average = add_average_term(average, z, pixel_c(dc));
+        }
+        let z_sq = dot(z, z);
+        if z_sq > r_sq { break; }
+        if props.trap_shape != 0u {
+            trap = min(trap, trap_distance(z));
+        }
+        if check_period {
+            let diff = z - saved;
+            if !found && dot(diff, diff) < PERIOD_EPSILON * PERIOD_EPSILON {
+                period = iterations - saved_at;
+                found = true;
+            }
+            if (iterations & (iterations - 1u)) == 0u {
+                saved = z;
+                saved_at = iterations;
+                found = false;
+            }
+        }
+
+        // the reference can't be used anymore if it escaped or if the pixel got closer to 0 than the delta (glitches)
+        // rebasing makes the pixel's own z the new delta and starts following the reference from the beginning
+        // https://fractalforums.org/fractal-mathematics-and-new-theories/28/another-solution-to-perturbation-glitches/4360
+        let dz_sq = fe_norm_sq(dz);
+        if n + 1u >= orbit_len || z_sq < dz_sq {
+            dz = fe_new(z - orbit[0], 0);
+            n = 0u;
+        }
+    }
+    var distance = 0.;
+    if iterations >= props.max_iterations {
+        distance = interior_data(z, dc, period);
+    } else if props.distance_estimation != 0u {
+        distance = distance_estimate(z, der);
+    } else if props.average_mode != 0u {
+        distance = smooth_average(average, z);
+    }
+    return Escape(iterations, z, trap, distance);
+}
+
+// c of the pixel in single precision, which is enough for the average coloring and the interior distance
+fn pixel_c(dc: FloatExp) -> vec2<f32> {
+    if props.julia != 0 {
+        return props.c;
+    }
+    // the reference starts at 0 so its first iteration is its c
+    return orbit[1] + fe_to_f32(dc);
+}
+
+// distance to the set in pixels, same as mandelbrot.wgsl but the derivative and the pixel size have exponents
+fn distance_estimate(z: vec2<f32>, der: FloatExp) -> f32 {
+    let r = length(z);
+    let log_distance = log2(r * log(r) / (length(der.m) * props.pixel_size)) - f32(der.e + props.scale_exp);
+    return exp2(min(log_distance, 64.));
+}
+
+// -1 if it isn't known, same as mandelbrot.wgsl
+fn interior_data(z: vec2<f32>, dc: FloatExp, period: u32) -> f32 {
+    switch props.interior_mode {
+        case 1u: { return f32(period); }
+        case 4u: {
+            if props.julia != 0 || period == 0u { return -1.; }
+            return interior_distance(z, pixel_c(dc), period);
+        }
+        default: { return 0.; }
+    }
+}
+
+// derivative of the equation with respect to z
+fn equation_derivative(z: vec2<f32>) -> vec2<f32> {
+    #if VARIANT == 0
+        return 2. * z;
+    #else if VARIANT == 1
+        return 2. * z - vec2(1., 0.);
+    #endif
+}
+
+fn equation_second_derivative(z: vec2<f32>) -> vec2<f32> {
+    return vec2(2., 0.);
+}
+
+// the equation without the reference, check interior_distance
+fn equation_f32(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    #if VARIANT == 0
+        return c_mul_f32(z, z) + c;
+    #else if VARIANT == 1
+        return c_mul_f32(z, z) - z + c;
+    #endif
+}
+
+// complex number with an extra exponent: m * 2^e
+// deltas are way smaller than what f32 can represent on its own
+struct FloatExp {
+    m: vec2<f32>,
+    e: i32,
+}
+
+// keeps the mantissa around 1 so it never underflows or overflows
+fn fe_new(m: vec2<f32>, e: i32) -> FloatExp {
+    let largest = max(abs(m.x), abs(m.y));
+    if largest == 0. { return FloatExp(vec2<f32>(), -10000); }
+    let shift = frexp(largest).exp;
+    return FloatExp(ldexp(m, vec2(-shift)), e + shift);
+}
+
+// rounds to 0 if too small
+fn fe_to_f32(a: FloatExp) -> vec2<f32> {
+    return ldexp(a.m, vec2(clamp(a.e, -200, 200)));
+}
+
+fn fe_norm_sq(a: FloatExp) -> f32 {
+    return ldexp(dot(a.m, a.m), clamp(2 * a.e, -200, 200));
+}
+
+fn fe_add(a: FloatExp, b: FloatExp) -> FloatExp {
+    if a.e >= b.e {
+        return fe_new(a.m + ldexp(b.m, vec2(max(b.e - a.e, -200))), a.e);
+    } else {
+        return fe_new(b.m + ldexp(a.m, vec2(max(a.e - b.e, -200))), b.e);
+    }
+}
+
+// multiplies by a regular complex number
+fn fe_mul(a: FloatExp, b: vec2<f32>) -> FloatExp {
+    return fe_new(vec2(a.m.x * b.x - a.m.y * b.y, a.m.x * b.y + a.m.y * b.x), a.e);
+}
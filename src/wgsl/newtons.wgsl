@@ -1,26 +1,38 @@
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
 };
 
 struct Props {
-    scale:  vec2<f32>, //0..8
-    offset: vec2<f32>, //8..16
-
-    arr: array<Element, 6>,     //016..112
-    colors: array<vec4<f32>, 5>,//112..192
-    a: vec2<f32>,               //192..200
-    c: vec2<f32>,               //200..208
-    nr_roots: u32,              //208..212
-    max_iterations: u32,        //212..216
-    threshold: f32,             //216..220
-    _padding: f32,              //220..224
+    scale:     vec2<f32>, //0..8
+    offset:    vec2<f32>, //8..16
+    // only used by double precision and perturbation shaders
+    scale_lo:  vec2<f32>, //16..24
+    offset_lo: vec2<f32>, //24..32
+    scale_exp: i32,       //32..36
+    pixel_size: f32,             //36..40
+    _header_padding1: vec2<i32>, //40..48
+
+    a: vec2<f32>,               //48..56
+    c: vec2<f32>,               //56..64
+    nr_roots: u32,              //64..68
+    max_iterations: u32,        //68..72
+    threshold: f32,             //72..76
+    _padding: f32,              //76..80
 }
-// array elements must have a size of 16 so we interweave the roots and polynomial constant arrays
-// https://www.w3.org/TR/WGSL/#address-space-layout-constraints
-struct Element {
-    root: vec2<f32>,
-    coefficient: vec2<f32>,
+
+// same as newtons.rs
+const MAX_ROOTS: u32 = 32u;
+
+struct Coloring {
+    // one for each root
+    colors: array<vec4<f32>, MAX_ROOTS>,
+    color_non_converged: vec4<f32>,
+    // from -1 to 1, how much slower pixels are lightened (negative) or darkened (positive)
+    shading: f32,
+    // bool, pixels that didn't converge get color_non_converged
+    non_converged: u32,
 }
 
 var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
@@ -35,66 +47,113 @@ var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
 @group(0) @binding(0)
 var<uniform> props: Props;
 
+@group(0) @binding(1)
+var<uniform> coloring: Coloring;
+
+// the roots in xy and the polynomial coefficients in zw, there's one more coefficient than roots
+#if DATA == 0
+@group(1) @binding(0)
+var<storage, read> params: array<vec4<f32>>;
+#else
+// WebGL2 doesn't support storage buffers
+@group(1) @binding(0)
+var params: texture_2d<f32>;
+#endif
+
+fn param(i: u32) -> vec4<f32> {
+    #if DATA == 0
+        return params[i];
+    #else
+        let width = textureDimensions(params).x;
+        return textureLoad(params, vec2(i % width, i / width), 0);
+    #endif
+}
+
 @vertex
 fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
     out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
-    out.uv = (v_positions[v_idx] + props.offset) * props.scale;
+    out.pos = v_positions[v_idx];
     return out;
 }
 
 // https://youtu.be/-RdOwhmqP5s
 @fragment
 fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
-    let root = newtons_method(in.uv);
+    return colorize(raw_data(in.pos));
+}
+
+// index of the root or -1 in x, the smooth number of iterations until it converged or -1 in y
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    let uv = (pos + props.offset) * props.scale;
+    let result = newtons_method(uv);
+    return vec4(f32(result.root), result.iterations, 0., 0.);
+}
+
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    let root = i32(data.x);
+    if data.y < 0. && coloring.non_converged != 0u {
+        return coloring.color_non_converged;
+    }
+    // transparent if it isn't close to any root
     if (root == -1) {
-        discard;
+        return vec4<f32>();
     }
-    return props.colors[root];
+    // pixels that didn't converge are shaded like the slowest ones
+    var n = data.y;
+    if n < 0. {
+        n = f32(props.max_iterations);
+    }
+    // most pixels converge in a few iterations so they get most of the range
+    let t = log2(1. + n) / log2(1. + f32(props.max_iterations));
+    let color = coloring.colors[root];
+    let shade = select(vec4(1.), vec4(0., 0., 0., 1.), coloring.shading > 0.);
+    return mix(color, shade, abs(coloring.shading) * t);
+}
+
+// the newton step has to get this small for the pixel to converge
+const TOLERANCE: f32 = 1e-4;
+
+struct Convergence {
+    root: i32,
+    // smooth, -1 if it didn't converge
+    iterations: f32,
 }
 
 // https://en.wikipedia.org/wiki/Newton_fractal#Implementation
-// will return -1 if it's not close enough to any of the roots and the root index otherwise
-fn newtons_method(z_no_shadowing_whyyy: vec2<f32>) -> i32 {
+// root will be -1 if it's not close enough to any of the roots and the root index otherwise
+fn newtons_method(z_no_shadowing_whyyy: vec2<f32>) -> Convergence {
     var z = z_no_shadowing_whyyy;
+    var iterations = -1.;
     for(var iteration = 0u; iteration < props.max_iterations; iteration++) {
         var zp = vec2(1.,0.);
         var prev = vec2<f32>();
         var f = vec2<f32>();
         var fd = vec2<f32>();
-        for (var i=0;i<=5;i++) {
-            let coef = props.arr[i].coefficient;
+        for (var i=0u;i<=props.nr_roots;i++) {
+            let coef = param(i).zw;
             f += cmul(coef, zp);
             fd += cmul(coef, prev) * f32(i);
             prev = zp;
             zp = cmul(cmul(props.a,zp), z) + props.c;
         }
-        z = z - cdiv(f,fd);
+        let step = cdiv(f,fd);
+        z = z - step;
+        // the step is about the distance to the root, it gets squared every iteration once it's close.
+        // The fraction is how far the last step was from crossing the tolerance, so the count doesn't have bands
+        let d = length(step);
+        if d < TOLERANCE {
+            iterations = f32(iteration + 1u) - clamp(log2(log(max(d, 1e-30)) / log(TOLERANCE)), 0., 1.);
+            break;
+        }
     }
     var closest_root = -1; var closest_dist = props.threshold;
     for (var i=0u;i<props.nr_roots;i++) {
-        let d = distance(z,props.arr[i].root);
+        let d = distance(z,param(i).xy);
         if (d < closest_dist) {
             closest_root = i32(i);
             closest_dist = d;
         }
     }
-    return closest_root;
-}
-
-fn cmul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
-    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
-}
-
-fn csq(z: vec2<f32>) -> vec2<f32> {
-    return vec2(z.x * z.x - z.y * z.y, 2. * z.x * z.y);
-}
-
-// maybe use https://arxiv.org/pdf/1608.07596.pdf
-// from num_complex crate
-fn cdiv(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
-    let norm_sqr = b.x * b.x + b.y * b.y;
-    let re = a.x * b.x + a.y * b.y;
-    let im = a.y * b.x - a.x * b.y;
-    return vec2(re / norm_sqr, im / norm_sqr);
+    return Convergence(closest_root, iterations);
 }
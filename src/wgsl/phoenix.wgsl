@@ -0,0 +1,114 @@
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
+};
+
+struct Props {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+    // only used by double precision and perturbation shaders
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
+
+    // only used by the julia version, otherwise the pixel is c
+    c: vec2<f32>,
+    // multiplies the previous z
+    p: vec2<f32>,
+    max_iterations: u32,
+    // check Phoenix::escape_radius
+    escape_radius: f32,
+    // bool, the pixel is the first z instead of c
+    julia: u32,
+    _padding: u32,
+}
+
+struct Coloring {
+    // bool, continuous iteration count
+    smooth_iterations: u32,
+    // the gradient is sampled at fract(t * repeat + offset)
+    offset: f32,
+    repeat: f32,
+    _padding: f32,
+}
+
+var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1.,-1.),
+    vec2<f32>(-1.,-1.),
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1., 1.),
+    vec2<f32>( 1.,-1.),
+);
+
+@group(0) @binding(0)
+var<uniform> props: Props;
+
+@group(0) @binding(1)
+var<uniform> coloring: Coloring;
+
+@vertex
+fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
+    var out: VertexOut;
+    out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
+    out.pos = v_positions[v_idx];
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    return colorize(raw_data(in.pos));
+}
+
+// iterations in x and the last z in yz
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    let uv = (pos + props.offset) * props.scale;
+    if props.julia != 0u {
+        // the julia version is traditionally drawn with the axes swapped
+        return compute_iterations(uv.yx, props.c);
+    }
+    return compute_iterations(vec2(0., 0.), uv);
+}
+
+// same as mandelbrot.wgsl without the histogram
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    let iterations = u32(data.x);
+    if iterations >= props.max_iterations {
+        return gradient_color(0u, 1.);
+    }
+    var n = data.x;
+    if coloring.smooth_iterations != 0u {
+        // the previous z is tiny compared to z^2 once it escapes, so it's smoothed like z^2 + c
+        let log_ratio = log(length(data.yz)) / log(props.escape_radius);
+        n -= log2(max(log_ratio, 1.));
+    }
+    return gradient_color(0u, gradient_position(n / f32(props.max_iterations)));
+}
+
+fn gradient_position(t: f32) -> f32 {
+    let position = t * coloring.repeat + coloring.offset;
+    // 1 is kept so the whole gradient is used when it isn't repeated
+    if position > 1. {
+        return cycle_position(fract(position));
+    }
+    return cycle_position(position);
+}
+
+// z_{n+1} = z_n^2 + c + p * z_{n-1}, the orbit starts with z_{-1} = 0
+fn compute_iterations(z0: vec2<f32>, c: vec2<f32>) -> vec4<f32> {
+    let r_sq = props.escape_radius * props.escape_radius;
+    var z = z0;
+    var previous = vec2(0., 0.);
+    var iterations = 0u;
+    while dot(z, z) <= r_sq && iterations < props.max_iterations {
+        let next = csq(z) + c + cmul(props.p, previous);
+        previous = z;
+        z = next;
+        iterations++;
+    }
+    return vec4(f32(iterations), z, 0.);
+}
@@ -0,0 +1,42 @@
+// averages the samples stored in the accumulation texture and draws them to the screen
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Props {
+    // 1 / number of samples that have been accumulated
+    weight: f32,
+}
+
+var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1.,-1.),
+    vec2<f32>(-1.,-1.),
+    vec2<f32>(-1., 1.),
+    vec2<f32>( 1., 1.),
+    vec2<f32>( 1.,-1.),
+);
+
+@group(0) @binding(0)
+var accumulation: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> props: Props;
+
+@vertex
+fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
+    var out: VertexOut;
+    out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
+    // from 0,0 in the top left corner to 1,1 in the bottom right
+    out.uv = v_positions[v_idx] * vec2(0.5, -0.5) + 0.5;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    let size = textureDimensions(accumulation);
+    let texel = min(vec2<u32>(in.uv * vec2<f32>(size)), size - 1u);
+    // the result is premultiplied, alpha is the fraction of samples that weren't discarded
+    return textureLoad(accumulation, texel, 0) * props.weight;
+}
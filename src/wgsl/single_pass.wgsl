@@ -0,0 +1,11 @@
+
+// appended to a fractal's shader when it's drawn in a single pass, check two_pass.wgsl
+
+// there's no raw data to count so the histogram isn't available
+fn histogram_available() -> bool {
+    return false;
+}
+
+fn cumulative_distribution(iterations: u32) -> f32 {
+    return 0.;
+}
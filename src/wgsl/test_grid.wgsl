@@ -1,11 +1,19 @@
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
+    // clip space position, raw_data computes uv from it
+    @location(0) pos: vec2<f32>,
 };
 
 struct Props {
     scale: vec2<f32>,
     offset: vec2<f32>,
+    // only used by double precision and perturbation shaders
+    scale_lo: vec2<f32>,
+    offset_lo: vec2<f32>,
+    scale_exp: i32,
+    // height of a pixel in the same units as scale
+    pixel_size: f32,
+    _header_padding1: vec2<i32>,
 }
 
 var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
@@ -24,11 +32,20 @@ var<uniform> props: Props;
 fn vertex(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
     out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
-    out.uv = (v_positions[v_idx] + props.offset) * props.scale;
+    out.pos = v_positions[v_idx];
     return out;
 }
 
 @fragment
 fn fragment(in: VertexOut) -> @location(0) vec4<f32> {
-    return vec4<f32>(fract(in.uv/2. + 0.5) * 2. - 1. , 0.0, 1.0);
-}
\ No newline at end of file
+    return colorize(raw_data(in.pos));
+}
+
+fn raw_data(pos: vec2<f32>) -> vec4<f32> {
+    let uv = (pos + props.offset) * props.scale;
+    return vec4(fract(uv/2. + 0.5) * 2. - 1., 0., 0.);
+}
+
+fn colorize(data: vec4<f32>) -> vec4<f32> {
+    return vec4(data.xy, 0.0, 1.0);
+}
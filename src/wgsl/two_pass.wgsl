@@ -0,0 +1,50 @@
+
+// appended to a fractal's shader when it's rendered in two passes:
+// compute stores the raw data of every pixel and colorize_fragment colors it,
+// so the coloring can be changed without computing the fractal again
+
+// check RawData in rendering.rs
+@group(2) @binding(0)
+var raw_output: texture_storage_2d<rgba32float, write>;
+
+struct Tile {
+    origin: vec2<u32>,
+    _padding: vec2<u32>,
+}
+
+@group(2) @binding(1)
+var<uniform> tile: Tile;
+
+@group(2) @binding(2)
+var raw_input: texture_2d<f32>;
+
+@compute @workgroup_size(8, 8)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pixel = tile.origin + id.xy;
+    let size = textureDimensions(raw_output);
+    if any(pixel >= size) {
+        return;
+    }
+    // same as the position the vertex shader outputs for the center of the pixel,
+    // up to rounding: the rasterizer interpolates it differently, so pixels in chaotic areas
+    // (the noisy parts of Newton's fractals, Julia sets close to their boundary) can differ from a single pass render
+    let pos = (vec2<f32>(pixel) + 0.5) / vec2<f32>(size) * vec2(2., -2.) + vec2(-1., 1.);
+    textureStore(raw_output, pixel, raw_data(pos));
+}
+
+@fragment
+fn colorize_fragment(in: VertexOut) -> @location(0) vec4<f32> {
+    return colorize(textureLoad(raw_input, vec2<u32>(in.position.xy), 0));
+}
+
+// fraction of the escaped pixels of the first sample that took at most as many iterations as the index, check histogram.wgsl
+@group(3) @binding(0)
+var<storage, read> cdf: array<f32>;
+
+fn histogram_available() -> bool {
+    return true;
+}
+
+fn cumulative_distribution(iterations: u32) -> f32 {
+    return cdf[min(iterations, arrayLength(&cdf) - 1u)];
+}
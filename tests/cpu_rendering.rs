@@ -0,0 +1,61 @@
+// Renders every fractal on the cpu and on a software adapter, the cpu versions of the shaders should give the same pixels
+use glam::DVec2;
+use wgpu::TextureFormat;
+use fractal_studio::app::cpu_rendering::render_cpu;
+use fractal_studio::app::export::{ExportJob, MAX_TILE_SIZE};
+use fractal_studio::app::perturbation::{prepare_view, BigComplex};
+use fractal_studio::app::rendering::{create_headless_device, RenderData, Supersampling};
+use fractal_studio::fractal::domain_coloring::DomainColoring;
+use fractal_studio::fractal::lyapunov::Lyapunov;
+use fractal_studio::fractal::mandelbrot::MandelbrotFamily;
+use fractal_studio::fractal::newtons::Newtons;
+use fractal_studio::fractal::phoenix::Phoenix;
+use fractal_studio::fractal::test_grid::TestGrid;
+use fractal_studio::fractal::Fractal;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+/// max difference of a channel before a pixel counts as different, the gpu doesn't round exactly like the cpu
+const CHANNEL_TOLERANCE: u8 = 16;
+/// fraction of pixels allowed to be different, chaotic areas can flip with tiny floating point differences
+const MAX_DIFFERENT_PIXELS: f64 = 0.02;
+
+#[test]
+fn cpu_matches_gpu() {
+    let (adapter, device, queue) = pollster::block_on(create_headless_device(true))
+        .expect("the cpu is compared with a software adapter, install one (e.g. mesa's llvmpipe)");
+    let mut render_data = RenderData::new(&device, &adapter, TextureFormat::Rgba8Unorm);
+
+    // the examples are left out, most of them are too chaotic at this size
+    let fractals = [
+        Fractal::TestGrid(TestGrid::default()),
+        Fractal::MandelbrotFamily(MandelbrotFamily::default_mandelbrot()),
+        Fractal::Newtons(Newtons::default()),
+        Fractal::Lyapunov(Lyapunov::default()),
+        Fractal::Phoenix(Phoenix::default()),
+        Fractal::DomainColoring(DomainColoring::default()),
+    ];
+    let mut failures = vec![];
+    for fractal in fractals {
+        for cycle_offset in [0., 0.3] {
+            // same view as the visualizer when it's opened
+            let scale = DVec2::new(WIDTH as f64 / HEIGHT as f64, 1.);
+            let view = prepare_view(&fractal, &BigComplex::zero(64), scale, [WIDTH, HEIGHT], render_data.supports_storage_buffers(), None);
+            let (shader, view_scale, view_offset) = (view.shader, view.scale, view.offset);
+            let mut job = ExportJob::new(WIDTH, HEIGHT, MAX_TILE_SIZE, view, fractal.clone(), Supersampling::X1, cycle_offset);
+            job.finish_blocking(&device, &queue, &mut render_data).unwrap();
+            let gpu = job.into_pixels();
+            let cpu = render_cpu(&fractal, view_scale, view_offset, [WIDTH, HEIGHT], Supersampling::X1, cycle_offset);
+
+            let different = gpu.as_chunks::<4>().0.iter().zip(cpu.as_chunks::<4>().0)
+                .filter(|(gpu, cpu)| gpu.iter().zip(cpu.iter()).any(|(&g, &c)| g.abs_diff(c) > CHANNEL_TOLERANCE))
+                .count();
+            let fraction = different as f64 / (WIDTH * HEIGHT) as f64;
+            if fraction > MAX_DIFFERENT_PIXELS {
+                failures.push(format!("{:?} with a cycle offset of {cycle_offset}: {:.2}% of the pixels are different", shader, fraction * 100.));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "the cpu doesn't match the gpu:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,164 @@
+// Renders every example fractal and the fixtures below on a software adapter and compares them with the reference images in tests/golden.
+// Run with UPDATE_GOLDEN=1 to (re)create the references after an intended change
+use std::path::{Path, PathBuf};
+use glam::DVec2;
+use image::{Rgba, RgbaImage};
+use wgpu::TextureFormat;
+use fractal_studio::app::export::{ExportJob, MAX_TILE_SIZE};
+use fractal_studio::app::library::EXAMPLE_FRACTALS;
+use fractal_studio::app::perturbation::{prepare_view, BigComplex};
+use fractal_studio::app::rendering::{create_headless_device, RenderData, Supersampling};
+use fractal_studio::fractal::Fractal;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+/// max difference of a channel before a pixel counts as different, drivers don't round exactly the same way
+const CHANNEL_TOLERANCE: u8 = 16;
+/// fraction of pixels allowed to be different, chaotic areas can flip with tiny floating point differences
+const MAX_DIFFERENT_PIXELS: f64 = 0.01;
+
+/// a fractal rendered with its own view, for what the examples don't cover
+#[derive(Clone, Copy)]
+struct Fixture {
+    name: &'static str,
+    code: &'static str,
+    center: DVec2,
+    /// half the height of the view, 1 is the view of the visualizer when it's opened
+    scale: f64,
+    samples: Supersampling,
+}
+
+const FIXTURES: &[Fixture] = &[
+    // coloring modes, the linear one is used by the examples
+    Fixture {
+        name: "Histogram", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6lIaXN0b2dyYW2uc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Stripe average", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ61TdHJpcGVBdmVyYWdlrnN0cmlwZV9kZW5zaXR5ykCgAACmc21vb3Row6hncmFkaWVudIKlc3RvcHOVgqhwb3NpdGlvbsoAAAAApWNvbG9ylAAHZMz_gqhwb3NpdGlvbso-gAAApWNvbG9ylCBrzMvM_4KocG9zaXRpb27KPwAAAKVjb2xvcpTM7cz_zP_M_4KocG9zaXRpb27KP0AAAKVjb2xvcpTM_8yqAMz_gqhwb3NpdGlvbso_gAAApWNvbG9ylAACAMz_rWludGVycG9sYXRpb26jUmdir2dyYWRpZW50X29mZnNldMoAAAAAr2dyYWRpZW50X3JlcGVhdMpAgAAAqm9yYml0X3RyYXDAs2Rpc3RhbmNlX2VzdGltYXRpb27AqGludGVyaW9ywA",
+    },
+    Fixture {
+        name: "Triangle inequality", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ7JUcmlhbmdsZUluZXF1YWxpdHmuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0yj-AAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Orbit trap", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcIWlc2hhcGWlQ3Jvc3OmY2VudGVyksoAAAAAygAAAAClYW5nbGXKAAAAAKZyYWRpdXPKPwAAAKhkaXN0YW5jZco_gAAAs2Rpc3RhbmNlX2VzdGltYXRpb27AqGludGVyaW9ywA",
+    },
+    Fixture {
+        name: "Distance estimation", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvboOkbW9kZadPdXRsaW5lqXRoaWNrbmVzc8o_gAAAqHN0cmVuZ3Royj-AAACoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Interior period", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3KCpG1vZGWmUGVyaW9kqGdyYWRpZW50gqVzdG9wc5OCqHBvc2l0aW9uygAAAAClY29sb3KUzP8AAMz_gqhwb3NpdGlvbso_AAAApWNvbG9ylADM_8z_zP-CqHBvc2l0aW9uyj-AAAClY29sb3KUzP8AAMz_rWludGVycG9sYXRpb26jSHN2",
+    },
+    Fixture {
+        name: "Interior distance", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3KCpG1vZGWoRGlzdGFuY2WoZ3JhZGllbnSCpXN0b3Bzk4KocG9zaXRpb27KAAAAAKVjb2xvcpTM_wAAzP-CqHBvc2l0aW9uyj8AAAClY29sb3KUAMz_zP_M_4KocG9zaXRpb27KP4AAAKVjb2xvcpTM_wAAzP-taW50ZXJwb2xhdGlvbqNIc3Y",
+    },
+    // deep enough for double precision, then for perturbation
+    Fixture {
+        name: "Double precision", center: DVec2::new(-0.743643887037151, 0.13182590420533), scale: 1e-6, samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQu4p3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Perturbation", center: DVec2::new(-0.743643887037151, 0.13182590420533), scale: 1e-12, samples: Supersampling::X4,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQu4p3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6ZMaW5lYXKuc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    // the minimum and maximum number of samples on thin filaments
+    Fixture {
+        name: "Supersampling X1", center: DVec2::new(-0.743643887037151, 0.13182590420533), scale: 1e-2, samples: Supersampling::X1,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6lIaXN0b2dyYW2uc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Supersampling X16", center: DVec2::new(-0.743643887037151, 0.13182590420533), scale: 1e-2, samples: Supersampling::X16,
+        code: "gbBNYW5kZWxicm90RmFtaWx5jappdGVyYXRpb25zzQEsp3ZhcmlhbnSqTWFuZGVsYnJvdKdqdWxpYV9jwKdtdWx0aV9lwKhjb2xvcmluZ6lIaXN0b2dyYW2uc3RyaXBlX2RlbnNpdHnKQKAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAdkzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUIGvMy8z_gqhwb3NpdGlvbso_AAAApWNvbG9ylMztzP_M_8z_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zKoAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUAAIAzP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAACqb3JiaXRfdHJhcMCzZGlzdGFuY2VfZXN0aW1hdGlvbsCoaW50ZXJpb3LA",
+    },
+    Fixture {
+        name: "Phoenix", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gadQaG9lbml4h6ppdGVyYXRpb25zzQEsp2p1bGlhX2PAoXCSyr8AAADKAAAAAKZzbW9vdGjDqGdyYWRpZW50gqVzdG9wc5WCqHBvc2l0aW9uygAAAAClY29sb3KUAAAAzP-CqHBvc2l0aW9uyj6AAAClY29sb3KUegAAzP-CqHBvc2l0aW9uyj8AAAClY29sb3KUzP9qAMz_gqhwb3NpdGlvbso_QAAApWNvbG9ylMz_zOYAzP-CqHBvc2l0aW9uyj-AAAClY29sb3KUzP_M_8z_zP-taW50ZXJwb2xhdGlvbqNSZ2KvZ3JhZGllbnRfb2Zmc2V0ygAAAACvZ3JhZGllbnRfcmVwZWF0ykCAAAA",
+    },
+    Fixture {
+        name: "Phoenix Julia", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "gadQaG9lbml4h6ppdGVyYXRpb25zzQEsp2p1bGlhX2OSyj8RE0DKAAAAAKFwksq_AAAAygAAAACmc21vb3Row6hncmFkaWVudIKlc3RvcHOVgqhwb3NpdGlvbsoAAAAApWNvbG9ylAAAAMz_gqhwb3NpdGlvbso-gAAApWNvbG9ylHoAAMz_gqhwb3NpdGlvbso_AAAApWNvbG9ylMz_agDM_4KocG9zaXRpb27KP0AAAKVjb2xvcpTM_8zmAMz_gqhwb3NpdGlvbso_gAAApWNvbG9ylMz_zP_M_8z_rWludGVycG9sYXRpb26jUmdir2dyYWRpZW50X29mZnNldMoAAAAAr2dyYWRpZW50X3JlcGVhdMpAgAAA",
+    },
+    Fixture {
+        name: "Domain coloring", center: DVec2::ZERO, scale: 1., samples: Supersampling::X4,
+        code: "ga5Eb21haW5Db2xvcmluZ4WoZnVuY3Rpb26qUG9seW5vbWlhbKVyb290c5OSyj-AAADKAAAAAJLKvwAAAMo_XbItksq_AAAAyr9dsi2wbW9kdWx1c19jb250b3Vyc8o_AAAAq3BoYXNlX2xpbmVzDK5waGFzZV9zdHJlbmd0aMo-mZma",
+    },
+    Fixture {
+        name: "Domain coloring rational", center: DVec2::ZERO, scale: 3., samples: Supersampling::X4,
+        code: "ga5Eb21haW5Db2xvcmluZ4WoZnVuY3Rpb26oUmF0aW9uYWylcm9vdHOTkso_gAAAygAAAACSyr8AAADKP12yLZLKvwAAAMq_XbItsG1vZHVsdXNfY29udG91cnPKPwAAAKtwaGFzZV9saW5lcwyucGhhc2Vfc3RyZW5ndGjKPpmZmg",
+    },
+];
+
+#[test]
+fn fractals_match_references() {
+    let (adapter, device, queue) = pollster::block_on(create_headless_device(true))
+        .expect("the golden images are rendered on a software adapter, install one (e.g. mesa's llvmpipe)");
+    let mut render_data = RenderData::new(&device, &adapter, TextureFormat::Rgba8Unorm);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    // the examples get the same view as the visualizer when it's opened
+    let examples = EXAMPLE_FRACTALS.iter()
+        .map(|&(name, code)| Fixture { name, code, center: DVec2::ZERO, scale: 1., samples: Supersampling::X4 });
+    let mut failures = vec![];
+    for Fixture { name, code, center, scale, samples } in examples.chain(FIXTURES.iter().copied()) {
+        let fractal = Fractal::from_code(code).unwrap();
+        let scale = DVec2::new(scale * WIDTH as f64 / HEIGHT as f64, scale);
+        let center = BigComplex::from_dvec2(center, 128);
+        let view = prepare_view(&fractal, &center, scale, [WIDTH, HEIGHT], render_data.supports_storage_buffers(), None);
+        let mut job = ExportJob::new(WIDTH, HEIGHT, MAX_TILE_SIZE, view, fractal, samples, 0.);
+        job.finish_blocking(&device, &queue, &mut render_data).unwrap();
+        let mut actual = RgbaImage::from_raw(WIDTH, HEIGHT, job.into_pixels()).unwrap();
+        // some shaders discard pixels
+        actual.pixels_mut().for_each(|p| p.0[3] = 255);
+
+        let file_name = format!("{}.png", name.to_lowercase().replace(' ', "_"));
+        let reference_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(&file_name);
+        if update {
+            actual.save(&reference_path).unwrap();
+            continue;
+        }
+
+        let Ok(reference) = image::open(&reference_path) else {
+            failures.push(format!("{name}: missing {}, run with UPDATE_GOLDEN=1 to create it", reference_path.display()));
+            continue;
+        };
+        let reference = reference.to_rgba8();
+
+        let (diff, different) = diff_images(&reference, &actual);
+        let fraction = different as f64 / (WIDTH * HEIGHT) as f64;
+        if fraction > MAX_DIFFERENT_PIXELS {
+            let dir = diff_dir();
+            actual.save(dir.join(format!("actual_{file_name}"))).unwrap();
+            diff.save(dir.join(format!("diff_{file_name}"))).unwrap();
+            failures.push(format!("{name}: {:.2}% of the pixels are different, check {}", fraction * 100., dir.display()));
+        }
+    }
+
+    assert!(failures.is_empty(), "golden images don't match:\n{}", failures.join("\n"));
+}
+
+/// the different pixels are red and the rest is a faded copy of the reference
+fn diff_images(reference: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, u32) {
+    let mut different = 0;
+    let diff = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (r, a) = (reference.get_pixel(x, y), actual.get_pixel(x, y));
+        if r.0.iter().zip(a.0).any(|(&r, a)| r.abs_diff(a) > CHANNEL_TOLERANCE) {
+            different += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([r[0] / 4, r[1] / 4, r[2] / 4, 255])
+        }
+    });
+    (diff, different)
+}
+
+fn diff_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}